@@ -0,0 +1,353 @@
+// Map analysis used by game logic: connected floor areas, rooms and corridors
+use std::collections::{HashSet, VecDeque};
+use serde::{Serialize, Deserialize};
+use super::{Coord, Grid, Map};
+
+// Rooms are open areas, corridors are floor one tile wide
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    Room,
+    Corridor
+}
+
+// One entry of the region table, kept in the map metadata (r# keys in legacy json)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Region {
+    pub id: usize,
+    pub kind: RegionKind,
+    pub component: usize, // regions with the same component are connected by floor
+    pub size: usize,
+    pub min_y: i32,
+    pub min_x: i32,
+    pub max_y: i32,
+    pub max_x: i32,
+    pub centroid_y: f64,
+    pub centroid_x: f64
+}
+
+// Result of find_regions, the region id of every floor tile and the region table
+pub struct Regions {
+    pub tile_regions: Grid<Option<usize>>,
+    pub regions: Vec<Region>
+}
+
+// The end of a corridor, length is how many steps back to a junction or room
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadEnd {
+    pub y: i32,
+    pub x: i32,
+    pub length: usize
+}
+
+// Layout report written by maps analyze
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Report {
+    pub floor_tiles: usize,
+    pub components: usize,
+    pub rooms: usize,
+    pub corridors: usize,
+    pub corridor_ratio: f64, // fraction of the floor that is corridor, 1.0 is one long corridor
+    pub loops: usize,
+    pub average_path_length: f64,
+    pub chokepoints: Vec<Coord>,
+    pub dead_ends: Vec<DeadEnd>
+}
+
+// A floor tile is part of a room if it's in any 2x2 block of floor, otherwise it's a corridor
+pub fn is_room_tile(map: &Map, coord: Coord) -> bool {
+    if !map.is_floor(coord) {
+        return false;
+    }
+    for (dy, dx) in [(-1, -1), (-1, 0), (0, -1), (0, 0)].iter() {
+        let (top, left) = (coord.y + dy, coord.x + dx);
+        if map.is_floor(Coord::new(top, left)) && map.is_floor(Coord::new(top, left+1)) && map.is_floor(Coord::new(top+1, left)) && map.is_floor(Coord::new(top+1, left+1)) {
+            return true;
+        }
+    }
+    false
+}
+
+// Flood fill from start over tiles accepted by same, marking each one with label
+fn flood_fill<F: Fn(Coord) -> bool>(labels: &mut Grid<Option<usize>>, start: Coord, label: usize, same: F) -> Vec<Coord> {
+    let mut filled = Vec::new();
+    let mut queue = VecDeque::new();
+    labels.set(start, Some(label));
+    queue.push_back(start);
+    while let Some(coord) = queue.pop_front() {
+        filled.push(coord);
+        for next in coord.neighbors().iter() {
+            if labels.get(*next) == Some(&None) && same(*next) {
+                labels.set(*next, Some(label));
+                queue.push_back(*next);
+            }
+        }
+    }
+    filled
+}
+
+// Label every floor tile with the id of the connected floor area it is in
+pub fn floor_components(map: &Map) -> Grid<Option<usize>> {
+    let (sizey, sizex) = map.mapsize();
+    let mut components = Grid::new(sizey, sizex, None);
+    let mut next = 0;
+    for coord in map.map_grid.coords() {
+        if map.is_floor(coord) && components.get(coord) == Some(&None) {
+            flood_fill(&mut components, coord, next, |c| map.is_floor(c));
+            next += 1;
+        }
+    }
+    components
+}
+
+// Split the floor into rooms and corridors, each connected piece of the same kind is one region
+pub fn find_regions(map: &Map) -> Regions {
+    let (sizey, sizex) = map.mapsize();
+    let components = floor_components(map);
+    let mut tile_regions = Grid::new(sizey, sizex, None);
+    let mut regions = Vec::new();
+    for coord in map.map_grid.coords() {
+        if !map.is_floor(coord) || tile_regions.get(coord) != Some(&None) {
+            continue;
+        }
+        let room = is_room_tile(map, coord);
+        let tiles = flood_fill(&mut tile_regions, coord, regions.len(), |c| {
+            map.is_floor(c) && is_room_tile(map, c) == room
+        });
+        let mut region = Region {
+            id: regions.len(),
+            kind: if room { RegionKind::Room } else { RegionKind::Corridor },
+            component: components.get(coord).cloned().flatten().unwrap_or(0),
+            size: tiles.len(),
+            min_y: coord.y,
+            min_x: coord.x,
+            max_y: coord.y,
+            max_x: coord.x,
+            centroid_y: 0.0,
+            centroid_x: 0.0
+        };
+        for tile in tiles.iter() {
+            region.min_y = region.min_y.min(tile.y);
+            region.min_x = region.min_x.min(tile.x);
+            region.max_y = region.max_y.max(tile.y);
+            region.max_x = region.max_x.max(tile.x);
+            region.centroid_y += tile.y as f64;
+            region.centroid_x += tile.x as f64;
+        }
+        region.centroid_y /= tiles.len() as f64;
+        region.centroid_x /= tiles.len() as f64;
+        regions.push(region);
+    }
+    Regions { tile_regions, regions }
+}
+
+// Store region ids on the floor tiles and the region table in the map metadata, replacing old labels
+pub fn label_regions(map: &mut Map) -> Vec<Region> {
+    let found = find_regions(map);
+    for (cell, (_, id)) in map.map_grid.values_mut().zip(found.tile_regions.iter()) {
+        if let Some(cell) = cell {
+            cell.region = id.map(|id| id as u32);
+        }
+    }
+    map.map_metadata.regions = found.regions.clone();
+    found.regions
+}
+
+// Region table stored in a map by label_regions, empty if the map was never labeled
+pub fn regions(map: &Map) -> &[Region] {
+    &map.map_metadata.regions
+}
+
+// Region id stored on the floor tile at a coordinate
+pub fn region_at(map: &Map, coord: Coord) -> Option<usize> {
+    map.cell(coord).and_then(|c| c.region).map(|r| r as usize)
+}
+
+// The region table is stored in legacy json as r0, r1, r2...
+pub fn region_key(n: usize) -> String {
+    String::from("r") + &n.to_string()
+}
+
+// Floor tiles next to a coordinate
+fn floor_neighbors(map: &Map, coord: Coord) -> Vec<Coord> {
+    coord.neighbors().iter().cloned().filter(|c| map.is_floor(*c)).collect()
+}
+
+// Articulation points of the floor, removing one of these tiles splits its area in two
+// Uses an explicit stack instead of recursion so big maps don't overflow
+pub fn chokepoints(map: &Map) -> Vec<Coord> {
+    let (sizey, sizex) = map.mapsize();
+    let mut disc = Grid::new(sizey, sizex, 0); // 0 means not visited yet
+    let mut low = Grid::new(sizey, sizex, 0);
+    let mut parent: Grid<Option<Coord>> = Grid::new(sizey, sizex, None);
+    let mut is_chokepoint = Grid::new(sizey, sizex, false);
+    let mut time = 1;
+    for root in map.map_grid.coords() {
+        if !map.is_floor(root) || disc.get(root) != Some(&0) {
+            continue;
+        }
+        disc.set(root, time);
+        low.set(root, time);
+        time += 1;
+        let mut root_children = 0;
+        let mut stack = vec![(root, 0)];
+        while let Some((u, next)) = stack.last().cloned() {
+            if next < 4 {
+                stack.last_mut().unwrap().1 += 1;
+                let v = u.neighbors()[next];
+                if !map.is_floor(v) {
+                    continue;
+                }
+                if disc.get(v) == Some(&0) {
+                    parent.set(v, Some(u));
+                    disc.set(v, time);
+                    low.set(v, time);
+                    time += 1;
+                    if u == root {
+                        root_children += 1;
+                    }
+                    stack.push((v, 0));
+                } else if parent.get(u) != Some(&Some(v)) {
+                    let lowest = low.get(u).cloned().unwrap_or(0).min(disc.get(v).cloned().unwrap_or(0));
+                    low.set(u, lowest);
+                }
+            } else {
+                stack.pop();
+                if let Some(&(p, _)) = stack.last() {
+                    let low_u = low.get(u).cloned().unwrap_or(0);
+                    let lowest = low.get(p).cloned().unwrap_or(0).min(low_u);
+                    low.set(p, lowest);
+                    if p != root && low_u >= disc.get(p).cloned().unwrap_or(0) {
+                        is_chokepoint.set(p, true);
+                    }
+                }
+            }
+        }
+        if root_children > 1 {
+            is_chokepoint.set(root, true);
+        }
+    }
+    is_chokepoint.iter().filter(|(_, choke)| **choke).map(|(coord, _)| coord).collect()
+}
+
+// Floor tiles with only one way out, and how far back the corridor goes before it opens up
+pub fn dead_ends(map: &Map) -> Vec<DeadEnd> {
+    let mut ends = Vec::new();
+    for coord in map.map_grid.coords() {
+        if !map.is_floor(coord) || floor_neighbors(map, coord).len() != 1 {
+            continue;
+        }
+        // Walk the corridor until a junction, a room or another dead end
+        let mut length = 0;
+        let mut previous = coord;
+        let mut current = floor_neighbors(map, coord)[0];
+        loop {
+            let next = floor_neighbors(map, current);
+            if next.len() != 2 || is_room_tile(map, current) {
+                break;
+            }
+            length += 1;
+            let step = if next[0] == previous { next[1] } else { next[0] };
+            previous = current;
+            current = step;
+        }
+        ends.push(DeadEnd { y: coord.y, x: coord.x, length: length + 1 });
+    }
+    ends
+}
+
+// One node of the graph loops are counted on, a whole room or a single corridor tile
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Node {
+    Room(usize),
+    Tile(Coord)
+}
+
+// Independent loops in the floor (edges - nodes + components), on the tile graph with each room
+// merged into one node so every open 2x2 block in a room isn't a loop. A corridor that leaves a room
+// and comes back into the same room is a loop
+pub fn loop_count(found: &Regions) -> usize {
+    let node = |coord: Coord, id: usize| match found.regions[id].kind {
+        RegionKind::Room => Node::Room(id),
+        RegionKind::Corridor => Node::Tile(coord)
+    };
+    let mut nodes = HashSet::new();
+    let mut edges = HashSet::new();
+    for (coord, id) in found.tile_regions.iter() {
+        let a = match id {
+            Some(id) => node(coord, *id),
+            None => continue
+        };
+        nodes.insert(a);
+        for next in [Coord::new(coord.y+1, coord.x), Coord::new(coord.y, coord.x+1)].iter() {
+            if let Some(Some(id)) = found.tile_regions.get(*next) {
+                let b = node(*next, *id);
+                if a != b {
+                    edges.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+    }
+    let mut components: Vec<usize> = found.regions.iter().map(|r| r.component).collect();
+    components.sort();
+    components.dedup();
+    (edges.len() + components.len()).saturating_sub(nodes.len())
+}
+
+// Average walking distance between every pair of connected regions, measured from the
+// floor tile of each region closest to its centroid
+pub fn average_path_length(map: &Map, found: &Regions) -> f64 {
+    let mut centers: Vec<Option<(Coord, f64)>> = vec![None; found.regions.len()];
+    for (coord, id) in found.tile_regions.iter() {
+        let id = match id {
+            Some(id) => *id,
+            None => continue
+        };
+        let region = &found.regions[id];
+        let distance = (coord.y as f64 - region.centroid_y).powi(2) + (coord.x as f64 - region.centroid_x).powi(2);
+        match centers[id] {
+            Some((_, best)) if best <= distance => {},
+            _ => centers[id] = Some((coord, distance))
+        }
+    }
+    let mut total = 0.0;
+    let mut pairs = 0;
+    for (a, center) in centers.iter().enumerate() {
+        let center = match center {
+            Some((center, _)) => *center,
+            None => continue
+        };
+        let distances = map.distance_field(center);
+        for (other, _) in centers.iter().skip(a + 1).flatten() {
+            if let Some(Some(d)) = distances.get(*other) {
+                total += *d as f64;
+                pairs += 1;
+            }
+        }
+    }
+    if pairs == 0 {
+        return 0.0;
+    }
+    total / pairs as f64
+}
+
+// Run every analysis on a map for maps analyze
+pub fn analyze(map: &Map) -> Report {
+    let found = find_regions(map);
+    let mut components: Vec<usize> = found.regions.iter().map(|r| r.component).collect();
+    components.sort();
+    components.dedup();
+    let floor_tiles: usize = found.regions.iter().map(|r| r.size).sum();
+    let corridor_tiles: usize = found.regions.iter().filter(|r| r.kind == RegionKind::Corridor).map(|r| r.size).sum();
+    Report {
+        floor_tiles,
+        components: components.len(),
+        rooms: found.regions.iter().filter(|r| r.kind == RegionKind::Room).count(),
+        corridors: found.regions.iter().filter(|r| r.kind == RegionKind::Corridor).count(),
+        corridor_ratio: if floor_tiles == 0 { 0.0 } else { corridor_tiles as f64 / floor_tiles as f64 },
+        loops: loop_count(&found),
+        average_path_length: average_path_length(map, &found),
+        chokepoints: chokepoints(map),
+        dead_ends: dead_ends(map)
+    }
+}
@@ -0,0 +1,245 @@
+// Compact binary map format, a lot smaller than json for big maps
+//
+// Layout, numbers are little endian and "varint" is LEB128:
+//   magic "MAPB", format version (1 byte), height (u32), width (u32)
+//   palette: varint count, then each terrain character as a u32, index 0 is a missing tile
+//   terrain: runs of (varint palette index, varint length) in row major order until every tile is covered
+//   biomes: runs of (biome code, varint length), code 0 is no biome
+//   regions: runs of (varint region id + 1, varint length), 0 is no region
+//   metadata: varint length, then the version 2 json file with the terrain layers left empty
+use std::collections::HashMap;
+use super::{Biome, Cell, Grid, Map, MapError, MapFile};
+use super::metadata::FORMAT_VERSION;
+
+pub const MAGIC: &[u8; 4] = b"MAPB";
+pub const BINARY_VERSION: u8 = 1;
+
+// Check if file contents start with the binary magic bytes
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn biome_code(biome: Option<Biome>) -> u64 {
+    match biome {
+        None => 0,
+        Some(Biome::Forest) => 1,
+        Some(Biome::Desert) => 2,
+        Some(Biome::Swamp) => 3,
+        Some(Biome::Tundra) => 4
+    }
+}
+
+fn biome_from_code(code: u64) -> Result<Option<Biome>, MapError> {
+    match code {
+        0 => Ok(None),
+        1 => Ok(Some(Biome::Forest)),
+        2 => Ok(Some(Biome::Desert)),
+        3 => Ok(Some(Biome::Swamp)),
+        4 => Ok(Some(Biome::Tundra)),
+        _ => Err(bad(&format!("has unknown biome code {}", code)))
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+// Run length encode one value per tile
+fn write_runs(out: &mut Vec<u8>, values: &[u64]) {
+    let mut i = 0;
+    while i < values.len() {
+        let mut length = 1;
+        while i + length < values.len() && values[i + length] == values[i] {
+            length += 1;
+        }
+        write_varint(out, values[i]);
+        write_varint(out, length as u64);
+        i += length;
+    }
+}
+
+// Cursor over the file contents
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+fn bad(message: &str) -> MapError {
+    MapError::Parse(format!("binary map file {}", message))
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], MapError> {
+        if n > self.bytes.len() - self.pos {
+            return Err(bad("is truncated"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+    fn u8(&mut self) -> Result<u8, MapError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, MapError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn varint(&mut self) -> Result<u64, MapError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                return Err(bad("has a bad number"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+    // Undo write_runs for a grid of count tiles, run gets each value with the index of its first tile and its length
+    fn runs<F>(&mut self, count: usize, mut run: F) -> Result<(), MapError>
+        where F: FnMut(u64, usize, usize) -> Result<(), MapError> {
+        let mut start = 0;
+        while start < count {
+            let value = self.varint()?;
+            let length = self.varint()? as usize;
+            if length == 0 || length > count - start {
+                return Err(bad("has a bad tile run"));
+            }
+            run(value, start, length)?;
+            start += length;
+        }
+        Ok(())
+    }
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
+
+// Write a map in the binary format
+pub fn encode(map: &Map) -> Vec<u8> {
+    let mut palette: Vec<char> = Vec::new();
+    let mut palette_index: HashMap<char, u64> = HashMap::new();
+    let mut terrain = Vec::new();
+    let mut biomes = Vec::new();
+    let mut regions = Vec::new();
+    for (_, cell) in map.map_grid.iter() {
+        match cell {
+            Some(cell) => {
+                let index = *palette_index.entry(cell.c).or_insert_with(|| {
+                    palette.push(cell.c);
+                    palette.len() as u64
+                });
+                terrain.push(index);
+                biomes.push(biome_code(cell.biome));
+                regions.push(cell.region.map(|r| r as u64 + 1).unwrap_or(0));
+            },
+            None => {
+                terrain.push(0);
+                biomes.push(0);
+                regions.push(0);
+            }
+        }
+    }
+    let mut metadata = map.map_metadata.clone();
+    metadata.version = FORMAT_VERSION;
+    metadata.height = map.map_grid.height();
+    metadata.width = map.map_grid.width();
+    let file = MapFile {
+        metadata,
+        terrain: Vec::new(),
+        biomes: Vec::new(),
+        tile_regions: Vec::new(),
+        entities: map.map_entities.clone(),
+        objects: map.map_objects.clone()
+    };
+    let metadata = serde_json::to_vec(&file).unwrap();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(BINARY_VERSION);
+    write_u32(&mut out, map.map_grid.height() as u32);
+    write_u32(&mut out, map.map_grid.width() as u32);
+    write_varint(&mut out, palette.len() as u64);
+    for c in palette.iter() {
+        write_u32(&mut out, *c as u32);
+    }
+    write_runs(&mut out, &terrain);
+    write_runs(&mut out, &biomes);
+    write_runs(&mut out, &regions);
+    write_varint(&mut out, metadata.len() as u64);
+    out.extend_from_slice(&metadata);
+    out
+}
+
+// Read a map written by encode
+pub fn decode(bytes: &[u8]) -> Result<Map, MapError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err(bad("has no MAPB magic"));
+    }
+    let version = reader.u8()?;
+    if version > BINARY_VERSION {
+        return Err(MapError::Schema(format!("binary map version {} is newer than this program supports ({})", version, BINARY_VERSION)));
+    }
+    let height = reader.u32()?;
+    let width = reader.u32()?;
+    let count = Map::check_size(height as i64, width as i64)?;
+    let (height, width) = (height as i32, width as i32);
+    let palette_size = reader.varint()? as usize;
+    let mut palette = Vec::new();
+    for _ in 0..palette_size {
+        palette.push(std::char::from_u32(reader.u32()?).ok_or_else(|| bad("palette has a bad character"))?);
+    }
+    // Runs go straight into the cells, capped by what's left of the file so a small file that claims a big size
+    // can't make a big allocation before it runs out
+    let mut cells: Vec<Option<Cell>> = Vec::with_capacity(count.min(reader.remaining()));
+    reader.runs(count, |index, _, length| {
+        let cell = match index {
+            0 => None,
+            _ => Some(Cell::new(*palette.get(index as usize - 1).ok_or_else(|| bad("tile is not in the palette"))?))
+        };
+        cells.resize(cells.len() + length, cell);
+        Ok(())
+    })?;
+    reader.runs(count, |code, start, length| {
+        let biome = biome_from_code(code)?;
+        for cell in cells[start..start + length].iter_mut().flatten() {
+            cell.biome = biome;
+        }
+        Ok(())
+    })?;
+    reader.runs(count, |id, start, length| {
+        let region = match id {
+            0 => None,
+            _ if id - 1 > u32::MAX as u64 => return Err(bad("has a region id that's too big")),
+            _ => Some((id - 1) as u32)
+        };
+        for cell in cells[start..start + length].iter_mut().flatten() {
+            cell.region = region;
+        }
+        Ok(())
+    })?;
+    let metadata_size = reader.varint()? as usize;
+    let mut file: MapFile = serde_json::from_slice(reader.take(metadata_size)?)?;
+    file.metadata.height = height;
+    file.metadata.width = width;
+    // The terrain layers of the json are left empty by encode, the grid is the runs
+    let map_grid = Grid::from_cells(height, width, cells).ok_or_else(|| bad("has the wrong number of tiles"))?;
+    Ok(Map { map_metadata: file.metadata, map_grid, map_entities: file.entities, map_objects: file.objects })
+}
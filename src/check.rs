@@ -0,0 +1,250 @@
+// Structural checks of map files, a corrupted map can still load and run so this finds what's wrong with it
+//
+// check_map looks at a loaded map, check_file and check_bytes also look at the file itself for things loading throws
+// away: json keys that appear twice and version 2 terrain rows that don't fit in the map size
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufReader, Read};
+use serde::Serialize;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use super::{binary, stream, Codec, Coord, Map, MapError};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    DuplicateKey, // a json key written twice, only the last one is loaded
+    BadMetadata, // a metadata key that doesn't look like what this program writes
+    OutOfBounds, // a tile, entity, exit or spawn outside the map size
+    KeyMismatch, // a "YxX" key whose tile has a different y or x
+    UnknownCharacter, // a tile that is neither the wall nor the floor character
+    SpawnOnWall,
+    MissingTile // no tile at a coordinate inside the map size, an error unless holes are allowed
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub severity: Severity,
+    pub key: String, // tile key, metadata key or json path the problem is at
+    pub message: String
+}
+
+// Everything found in one map, ok means there are no errors (warnings are fine)
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckReport {
+    pub ok: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    pub problems: Vec<Problem>
+}
+
+impl ProblemKind {
+    // Every kind is an error, allow_missing turns missing tiles into warnings
+    pub fn severity(&self) -> Severity {
+        Severity::Error
+    }
+}
+
+impl Problem {
+    pub fn new(kind: ProblemKind, key: &str, message: String) -> Problem {
+        Problem { kind, severity: kind.severity(), key: key.to_string(), message }
+    }
+}
+
+impl CheckReport {
+    // Sorted so the same map always gets the same report
+    pub fn new(mut problems: Vec<Problem>) -> CheckReport {
+        problems.sort_by(|a, b| (a.kind, &a.key).cmp(&(b.kind, &b.key)));
+        let errors = problems.iter().filter(|p| p.severity == Severity::Error).count();
+        CheckReport { ok: errors == 0, errors, warnings: problems.len() - errors, problems }
+    }
+    // Missing tiles as warnings, for imported maps that have holes on purpose
+    pub fn allow_missing(mut self) -> CheckReport {
+        for problem in self.problems.iter_mut().filter(|p| p.kind == ProblemKind::MissingTile) {
+            problem.severity = Severity::Warning;
+        }
+        CheckReport::new(self.problems)
+    }
+}
+
+// Check a loaded map
+pub fn check_map(map: &Map) -> CheckReport {
+    CheckReport::new(map_problems(map))
+}
+
+fn map_problems(map: &Map) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let palette = map.map_metadata.palette;
+    for (coord, cell) in map.map_grid.iter() {
+        match cell {
+            None => problems.push(Problem::new(ProblemKind::MissingTile, &coord.key(), format!("no tile at {}", coord.key()))),
+            Some(cell) if cell.c != palette.wall && cell.c != palette.floor => {
+                problems.push(Problem::new(ProblemKind::UnknownCharacter, &coord.key(), format!("tile '{}' at {} is not the wall '{}' or floor '{}'", cell.c, coord.key(), palette.wall, palette.floor)));
+            },
+            _ => {}
+        }
+    }
+    // Legacy keys from_legacy couldn't use are kept as objects, which is where broken tiles end up
+    for (key, tile) in map.map_objects.iter() {
+        if let Some(coord) = Coord::from_key(key) {
+            if coord.y != tile.y || coord.x != tile.x {
+                problems.push(Problem::new(ProblemKind::KeyMismatch, key, format!("key {} holds a tile with y={} x={}", key, tile.y, tile.x)));
+            } else if !map.map_grid.contains(coord) {
+                problems.push(Problem::new(ProblemKind::OutOfBounds, key, format!("tile {} is outside the {}x{} map", key, map.map_grid.height(), map.map_grid.width())));
+            }
+        } else if key == "mapsize" || key == "player" || key == "exit" {
+            problems.push(Problem::new(ProblemKind::BadMetadata, key, format!("{} doesn't look like the {} key maps writes, so it was not used", key, key)));
+        }
+    }
+    for (e, entity) in map.map_entities.iter().enumerate() {
+        let coord = Coord::new(entity.y, entity.x);
+        if !map.map_grid.contains(coord) {
+            problems.push(Problem::new(ProblemKind::OutOfBounds, &Map::entity_key(e), format!("entity {} at {} is outside the map", Map::entity_key(e), coord.key())));
+        }
+    }
+    if let Some(exit) = map.map_metadata.exit {
+        if !map.map_grid.contains(exit) {
+            problems.push(Problem::new(ProblemKind::OutOfBounds, "exit", format!("exit at {} is outside the map", exit.key())));
+        }
+    }
+    let spawn = map.map_metadata.spawn;
+    if !map.map_grid.contains(spawn) {
+        problems.push(Problem::new(ProblemKind::OutOfBounds, "spawn", format!("spawn at {} is outside the map", spawn.key())));
+    } else if map.cell(spawn).is_some_and(|cell| cell.c == palette.wall) {
+        problems.push(Problem::new(ProblemKind::SpawnOnWall, "spawn", format!("spawn at {} is on a wall", spawn.key())));
+    }
+    problems
+}
+
+// Check a map file in any format and compression, errors that stop the map loading at all are returned as errors
+// The file is read twice as a stream, once to load the map and once to look at the json, never all at once
+pub fn check_file(filename: &str) -> Result<CheckReport, MapError> {
+    let map = stream::load_map(filename, &mut |_| {})?;
+    let (reader, codec) = stream::open_map_file(filename)?;
+    let compressed = codec != Codec::None;
+    let problems = file_problems(&map, BufReader::new(reader)).map_err(|error| match error {
+        MapError::Io(error) if compressed => MapError::Decompress(error),
+        error => error
+    })?;
+    Ok(CheckReport::new(problems))
+}
+
+// Check map file contents in any format
+pub fn check_bytes(bytes: &[u8]) -> Result<CheckReport, MapError> {
+    let map = Map::from_bytes(bytes)?;
+    Ok(CheckReport::new(file_problems(&map, bytes)?))
+}
+
+// Problems of the loaded map and of the file it came from
+fn file_problems<R: Read>(map: &Map, mut reader: R) -> Result<Vec<Problem>, MapError> {
+    let mut problems = map_problems(map);
+    let mut head = Vec::new();
+    reader.by_ref().take(binary::MAGIC.len() as u64).read_to_end(&mut head)?;
+    if binary::is_binary(&head) {
+        return Ok(problems);
+    }
+    let scan = scan_json(io::Cursor::new(head).chain(reader))?;
+    for path in scan.duplicates {
+        problems.push(Problem::new(ProblemKind::DuplicateKey, &path, format!("key {} appears more than once, only the last one was loaded", path)));
+    }
+    // Version 2 terrain rows are cut to the map size on load
+    if scan.version_2 {
+        let (height, width) = (map.map_grid.height().max(0) as usize, map.map_grid.width().max(0) as usize);
+        for (y, length) in scan.terrain_rows.iter().enumerate() {
+            if y >= height {
+                problems.push(Problem::new(ProblemKind::OutOfBounds, &format!("terrain[{}]", y), format!("terrain row {} is below the {}x{} map", y, height, width)));
+            } else if *length > width {
+                problems.push(Problem::new(ProblemKind::OutOfBounds, &format!("terrain[{}]", y), format!("terrain row {} is {} tiles long, the map is {} wide", y, length, width)));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+// What the json of a map file holds that loading it throws away
+#[derive(Default)]
+struct JsonScan {
+    duplicates: Vec<String>, // paths of keys that appear more than once
+    terrain_rows: Vec<usize>, // length of each version 2 terrain row
+    version_2: bool // there is a metadata object
+}
+
+fn scan_json<R: Read>(reader: R) -> Result<JsonScan, MapError> {
+    let mut scan = JsonScan::default();
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    KeyScan { path: String::new(), scan: &mut scan }.deserialize(&mut deserializer).map_err(|error| {
+        if error.is_io() { MapError::Io(error.into()) } else { MapError::from(error) }
+    })?;
+    Ok(scan)
+}
+
+// Paths of json object keys that appear more than once, like "metadata.spawn" or "3x4"
+pub fn duplicate_keys(text: &str) -> Result<Vec<String>, MapError> {
+    Ok(scan_json(text.as_bytes())?.duplicates)
+}
+
+// Walks a json value without keeping it, remembering repeated keys and how long terrain rows are
+struct KeyScan<'a> {
+    path: String,
+    scan: &'a mut JsonScan
+}
+
+impl<'a> KeyScan<'a> {
+    fn child(&mut self, path: String) -> KeyScan<'_> {
+        KeyScan { path, scan: &mut *self.scan }
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for KeyScan<'a> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for KeyScan<'a> {
+    type Value = ();
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any json value")
+    }
+    fn visit_map<A: MapAccess<'de>>(mut self, mut access: A) -> Result<(), A::Error> {
+        let mut seen = HashSet::new();
+        while let Some(key) = access.next_key::<String>()? {
+            let path = if self.path.is_empty() { key.clone() } else { format!("{}.{}", self.path, key) };
+            if self.path.is_empty() && key == "metadata" {
+                self.scan.version_2 = true;
+            }
+            let child = self.child(path);
+            if !seen.insert(key) {
+                child.scan.duplicates.push(child.path.clone());
+            }
+            access.next_value_seed(child)?;
+        }
+        Ok(())
+    }
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut access: A) -> Result<(), A::Error> {
+        let mut i = 0;
+        while access.next_element_seed(self.child(format!("{}[{}]", self.path, i)))?.is_some() {
+            i += 1;
+        }
+        Ok(())
+    }
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> { Ok(()) }
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> { Ok(()) }
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> { Ok(()) }
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> { Ok(()) }
+    fn visit_str<E>(self, value: &str) -> Result<(), E> {
+        if self.path.starts_with("terrain[") {
+            self.scan.terrain_rows.push(value.chars().count());
+        }
+        Ok(())
+    }
+    fn visit_unit<E>(self) -> Result<(), E> { Ok(()) }
+}
@@ -0,0 +1,85 @@
+// Compatibility with the python curses-map-generator, whose json layout is the legacy layout here
+//
+// The shared layout, as python writes it and both programs read it:
+//   - the file is one json object, gzipped or not, and every value in it is a tile object:
+//     {"y": 12, "x": 35, "c": ".", "neighbors": []}, c is a single character and neighbors a list of keys
+//   - "YxX" keys ("12x35") are terrain tiles, y and x in the tile are the same as in the key
+//   - there is a terrain tile for every y below mapsize.y and every x below mapsize.x, and none outside
+//   - "mapsize" holds the map height in y and width in x, its c is "$"
+//   - "player" holds the player character in c, its y and x are 0
+//   - "v#" keys numbered from v0 are the voronoi region seeds, their neighbors are the keys of the two closest seeds
+//   - the spawn is not stored, both programs put the player at 12x35
+//   - key order means nothing, python writes keys in hash order and this program in legacy_key_order
+// This program adds keys (exit, r#, e#), a last v# seed at the spawn for its region and tile fields (biome, region,
+// entity, region_info) on top, python doesn't read them and keeps working. Maps written by python have none of them.
+use serde_json::Value;
+use super::{Coord, MapError};
+
+// Metadata keys python always writes
+pub const PYTHON_KEYS: [&str; 2] = ["mapsize", "player"];
+
+// Where python puts the player, there is no key for it
+pub const PYTHON_SPAWN: Coord = Coord { y: 12, x: 35 };
+
+// Fields of a tile object, in the order python writes them
+pub const TILE_FIELDS: [&str; 4] = ["y", "x", "c", "neighbors"];
+
+// Everything in legacy json that python couldn't read, empty when the file follows the shared layout
+pub fn layout_problems(value: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    let objects = match value.as_object() {
+        Some(objects) => objects,
+        None => return vec![String::from("map file is not a json object")]
+    };
+    for key in PYTHON_KEYS.iter() {
+        if !objects.contains_key(*key) {
+            problems.push(format!("no {} key", key));
+        }
+    }
+    for (key, tile) in objects.iter() {
+        for field in TILE_FIELDS.iter() {
+            if tile.get(*field).is_none() {
+                problems.push(format!("{} has no {} field", key, field));
+            }
+        }
+        if tile.get("c").and_then(|c| c.as_str()).is_some_and(|c| c.chars().count() != 1) {
+            problems.push(format!("{} c is not one character", key));
+        }
+        if tile.get("neighbors").is_some_and(|n| !n.as_array().is_some_and(|a| a.iter().all(|k| k.is_string()))) {
+            problems.push(format!("{} neighbors is not a list of keys", key));
+        }
+        if let Some(coord) = Coord::from_key(key) {
+            if tile.get("y").and_then(|y| y.as_i64()) != Some(coord.y as i64) || tile.get("x").and_then(|x| x.as_i64()) != Some(coord.x as i64) {
+                problems.push(format!("{} has a different y or x than its key", key));
+            }
+        }
+    }
+    let size = |field: &str| objects.get("mapsize").and_then(|m| m.get(field)).and_then(|n| n.as_i64());
+    if let (Some(height), Some(width)) = (size("y"), size("x")) {
+        let terrain: Vec<Coord> = objects.keys().filter_map(|k| Coord::from_key(k)).collect();
+        if let Some(outside) = terrain.iter().find(|c| c.y as i64 >= height || c.x as i64 >= width) {
+            problems.push(format!("terrain tile {} is outside the {}x{} map", outside.key(), height, width));
+        }
+        if (terrain.len() as i64) < height * width {
+            problems.push(format!("{} of the {} terrain tiles are missing", height * width - terrain.len() as i64, height * width));
+        }
+    }
+    if let Some(player) = objects.get("player") {
+        if player.get("y").and_then(|y| y.as_i64()) != Some(0) || player.get("x").and_then(|x| x.as_i64()) != Some(0) {
+            problems.push(String::from("player y and x should be 0"));
+        }
+    }
+    let seeds = objects.keys().filter(|k| k.starts_with('v') && k[1..].parse::<usize>().is_ok()).count();
+    if let Some(gap) = (0..seeds).find(|v| !objects.contains_key(&format!("v{}", v))) {
+        problems.push(format!("region seeds skip v{}", gap));
+    }
+    problems.sort();
+    problems
+}
+
+// Two legacy json files hold the same map, compared as json values so key order and spacing don't matter
+pub fn same_map(a: &str, b: &str) -> Result<bool, MapError> {
+    let a: Value = serde_json::from_str(a)?;
+    let b: Value = serde_json::from_str(b)?;
+    Ok(a == b)
+}
@@ -0,0 +1,112 @@
+// Compression codecs for map files, the codec is found from the first bytes of a file so any of them load
+//
+// Magic bytes: gzip 1f 8b, zstd 28 b5 2f fd, xz fd 37 7a 58 5a 00. Anything else is read as it is
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+
+pub const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+pub const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+pub const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+// Bytes needed to tell every codec apart
+pub const MAGIC_LEN: usize = 6;
+
+// Compression levels, the defaults of the gzip, zstd and xz command line tools
+pub const ZSTD_LEVEL: i32 = 3;
+pub const XZ_LEVEL: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    None,
+    Gzip,
+    Zstd,
+    Xz
+}
+
+impl Codec {
+    // Codec names used on the command line
+    pub fn from_name(name: &str) -> Option<Codec> {
+        match name {
+            "none" => Some(Codec::None),
+            "gzip" | "gz" => Some(Codec::Gzip),
+            "zstd" | "zst" => Some(Codec::Zstd),
+            "xz" => Some(Codec::Xz),
+            _ => None
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Xz => "xz"
+        }
+    }
+    pub fn magic(&self) -> &'static [u8] {
+        match self {
+            Codec::None => &[],
+            Codec::Gzip => GZIP_MAGIC,
+            Codec::Zstd => ZSTD_MAGIC,
+            Codec::Xz => XZ_MAGIC
+        }
+    }
+    // Codec of file contents from their first bytes, MAGIC_LEN of them are enough
+    pub fn detect(head: &[u8]) -> Codec {
+        [Codec::Gzip, Codec::Zstd, Codec::Xz].iter().copied().find(|codec| head.starts_with(codec.magic())).unwrap_or(Codec::None)
+    }
+    // Codec of a file, only its first bytes are read
+    pub fn detect_file(path: &str) -> io::Result<Codec> {
+        let mut head = Vec::with_capacity(MAGIC_LEN);
+        File::open(path)?.take(MAGIC_LEN as u64).read_to_end(&mut head)?;
+        Ok(Codec::detect(&head))
+    }
+    // Reader giving the decompressed contents of reader
+    pub fn reader<'a, R: Read + 'a>(&self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Codec::None => Box::new(reader),
+            Codec::Gzip => Box::new(GzDecoder::new(reader)),
+            Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Codec::Xz => Box::new(XzDecoder::new_multi_decoder(reader))
+        })
+    }
+    // Compress bytes into out, everything is flushed when this returns
+    pub fn write<W: Write>(&self, out: W, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Codec::None => {
+                let mut out = BufWriter::new(out);
+                out.write_all(bytes)?;
+                out.flush()
+            },
+            Codec::Gzip => {
+                let mut encoder = GzEncoder::new(out, Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()?.flush()
+            },
+            Codec::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(out, ZSTD_LEVEL)?;
+                encoder.write_all(bytes)?;
+                encoder.finish()?.flush()
+            },
+            Codec::Xz => {
+                let mut encoder = XzEncoder::new(out, XZ_LEVEL);
+                encoder.write_all(bytes)?;
+                encoder.finish()?.flush()
+            }
+        }
+    }
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write(&mut out, bytes)?;
+        Ok(out)
+    }
+    pub fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.reader(bytes)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,62 @@
+// Errors from loading and saving map files, each kind has its own exit code so scripts can tell them apart
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error), // opening, reading or writing the file
+    Decompress(io::Error), // the gzip, zstd or xz stream is broken or cut short
+    Parse(String), // not json, or a binary map that can't be read
+    Schema(String), // json or binary that doesn't have the map layout, or a newer version than this program
+    Validation(String) // file names and other checks before anything is read
+}
+
+impl MapError {
+    // Exit code for the command line, 1 is left for bad arguments
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MapError::Io(_) => 2,
+            MapError::Decompress(_) => 3,
+            MapError::Parse(_) => 4,
+            MapError::Schema(_) => 5,
+            MapError::Validation(_) => 6
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(error) => write!(f, "{}", error),
+            MapError::Decompress(error) => write!(f, "could not decompress map file: {}", error),
+            MapError::Parse(message) => write!(f, "could not parse map file: {}", message),
+            MapError::Schema(message) => write!(f, "map file has a bad layout: {}", message),
+            MapError::Validation(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapError::Io(error) | MapError::Decompress(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(error: io::Error) -> MapError {
+        MapError::Io(error)
+    }
+}
+
+// Broken json is a parse error, json with the wrong fields or types is a schema error
+impl From<serde_json::Error> for MapError {
+    fn from(error: serde_json::Error) -> MapError {
+        match error.classify() {
+            serde_json::error::Category::Data => MapError::Schema(error.to_string()),
+            _ => MapError::Parse(error.to_string())
+        }
+    }
+}
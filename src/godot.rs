@@ -0,0 +1,101 @@
+// Godot 3 scene export, a .tscn text resource with a TileMap of the terrain and marker nodes for the spawn and exit
+use std::collections::HashMap;
+use super::{Coord, Map};
+
+// How map characters become Godot tiles
+#[derive(Debug, Clone)]
+pub struct GodotOptions {
+    pub tile_ids: HashMap<char, i32>, // tile id in the TileSet for each map character, others are left empty
+    pub cell_size: i32,
+    pub tileset: Option<String> // res:// path of the TileSet resource, the TileMap has none if this is empty
+}
+
+impl GodotOptions {
+    // Walls are tile 0 and floors tile 1 of the TileSet
+    pub fn new(wall: char, floor: char) -> GodotOptions {
+        let mut tile_ids = HashMap::new();
+        tile_ids.insert(wall, 0);
+        tile_ids.insert(floor, 1);
+        GodotOptions { tile_ids, cell_size: 16, tileset: None }
+    }
+    // Read "c=id" pairs separated by commas, like "#=0,.=1"
+    pub fn parse_tile_ids(spec: &str) -> Result<HashMap<char, i32>, String> {
+        let mut tile_ids = HashMap::new();
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == ',' && chars.get(i + 1) != Some(&'=') {
+                i += 1;
+                continue;
+            }
+            let c = chars[i];
+            if chars.get(i + 1) != Some(&'=') {
+                return Err(format!("tile id for '{}' must look like {}=0", c, c));
+            }
+            i += 2;
+            let mut number = String::new();
+            while i < chars.len() && chars[i] != ',' {
+                number.push(chars[i]);
+                i += 1;
+            }
+            match number.trim().parse::<i32>() {
+                Ok(id) if id >= 0 => { tile_ids.insert(c, id); },
+                _ => return Err(format!("tile id for '{}' must be a number 0 or larger, not '{}'", c, number))
+            }
+        }
+        Ok(tile_ids)
+    }
+}
+
+impl Default for GodotOptions {
+    fn default() -> GodotOptions {
+        GodotOptions::new('#', '.')
+    }
+}
+
+// Cell index used in Godot 3 TileMap tile_data, the row in the high 16 bits and the column in the low 16 bits
+pub fn cell_index(coord: Coord) -> i32 {
+    (coord.y << 16) | (coord.x & 0xffff)
+}
+
+// Pixel position of the middle of a cell
+fn cell_center(coord: Coord, cell_size: i32) -> String {
+    let (x, y, cell_size) = (coord.x as i64, coord.y as i64, cell_size as i64);
+    format!("Vector2( {}, {} )", x * cell_size + cell_size / 2, y * cell_size + cell_size / 2)
+}
+
+// Write a map as a Godot 3 scene: a Node2D root named Map with a TileMap child and Position2D markers
+pub fn export_tscn(map: &Map, options: &GodotOptions) -> String {
+    let mut out = String::new();
+    match options.tileset {
+        Some(ref tileset) => {
+            out.push_str("[gd_scene load_steps=2 format=2]\n\n");
+            out.push_str(&format!("[ext_resource path=\"{}\" type=\"TileSet\" id=1]\n\n", tileset.replace('"', "\\\"")));
+        },
+        None => out.push_str("[gd_scene format=2]\n\n")
+    }
+    out.push_str("[node name=\"Map\" type=\"Node2D\"]\n\n");
+    out.push_str("[node name=\"TileMap\" type=\"TileMap\" parent=\".\"]\n");
+    if options.tileset.is_some() {
+        out.push_str("tile_set = ExtResource( 1 )\n");
+    }
+    out.push_str(&format!("cell_size = Vector2( {}, {} )\n", options.cell_size, options.cell_size));
+    out.push_str("format = 1\n");
+    // Each cell is three numbers: cell index, tile id and autotile coordinate
+    let mut tile_data = Vec::new();
+    for (coord, cell) in map.map_grid.iter() {
+        if let Some(id) = cell.and_then(|cell| options.tile_ids.get(&cell.c)) {
+            tile_data.push(cell_index(coord).to_string());
+            tile_data.push(id.to_string());
+            tile_data.push(String::from("0"));
+        }
+    }
+    out.push_str(&format!("tile_data = PoolIntArray( {} )\n\n", tile_data.join(", ")));
+    out.push_str("[node name=\"Spawn\" type=\"Position2D\" parent=\".\"]\n");
+    out.push_str(&format!("position = {}\n", cell_center(map.map_metadata.spawn, options.cell_size)));
+    if let Some(exit) = map.map_metadata.exit {
+        out.push_str("\n[node name=\"Exit\" type=\"Position2D\" parent=\".\"]\n");
+        out.push_str(&format!("position = {}\n", cell_center(exit, options.cell_size)));
+    }
+    out
+}
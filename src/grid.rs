@@ -0,0 +1,121 @@
+// Dense 2d storage for map data, indexed with typed coordinates instead of "YxX" string keys
+use serde::{Serialize, Deserialize};
+
+// A position on the map, y is the row and x is the column
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Coord {
+    pub y: i32,
+    pub x: i32
+}
+
+// Row major grid, every coordinate inside height x width has exactly one value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid<T> {
+    height: i32,
+    width: i32,
+    cells: Vec<T>
+}
+
+impl Coord {
+    pub fn new(y: i32, x: i32) -> Coord {
+        Coord { y, x }
+    }
+    // Key used for this position in legacy json maps, "Ypos" + "x" + "Xpos"
+    pub fn key(&self) -> String {
+        self.y.to_string() + "x" + &self.x.to_string()
+    }
+    // Read a legacy "YxX" key, metadata keys like mapsize or v0 are not coordinates
+    // Only keys written exactly like Coord::key are accepted, so "07x3" stays a separate key
+    pub fn from_key(key: &str) -> Option<Coord> {
+        let mut parts = key.splitn(2, 'x');
+        let y: i32 = parts.next()?.parse().ok()?;
+        let x: i32 = parts.next()?.parse().ok()?;
+        let coord = Coord::new(y, x);
+        if coord.key() == key {
+            Some(coord)
+        } else {
+            None
+        }
+    }
+    // The four positions a player can move to
+    pub fn neighbors(&self) -> [Coord; 4] {
+        [Coord::new(self.y-1, self.x), Coord::new(self.y+1, self.x), Coord::new(self.y, self.x-1), Coord::new(self.y, self.x+1)]
+    }
+    // Distance moving only up, down, left and right
+    pub fn manhattan(&self, other: Coord) -> i32 {
+        (self.y - other.y).abs() + (self.x - other.x).abs()
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    // New grid with every cell set to fill, negative sizes make an empty grid
+    pub fn new(height: i32, width: i32, fill: T) -> Grid<T> {
+        let height = height.max(0);
+        let width = width.max(0);
+        Grid {
+            height,
+            width,
+            cells: vec![fill; (height as usize) * (width as usize)]
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    // Grid of values already in row major order, None if there aren't height x width of them
+    pub fn from_cells(height: i32, width: i32, cells: Vec<T>) -> Option<Grid<T>> {
+        if height < 0 || width < 0 || cells.len() != (height as usize) * (width as usize) {
+            return None;
+        }
+        Some(Grid { height, width, cells })
+    }
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+    // Check if a coordinate is inside the grid
+    pub fn contains(&self, coord: Coord) -> bool {
+        coord.y >= 0 && coord.y < self.height && coord.x >= 0 && coord.x < self.width
+    }
+    fn index(&self, coord: Coord) -> Option<usize> {
+        if self.contains(coord) {
+            Some((coord.y * self.width + coord.x) as usize)
+        } else {
+            None
+        }
+    }
+    // Value at a coordinate, None outside the grid
+    pub fn get(&self, coord: Coord) -> Option<&T> {
+        self.index(coord).map(|i| &self.cells[i])
+    }
+    pub fn get_mut(&mut self, coord: Coord) -> Option<&mut T> {
+        match self.index(coord) {
+            Some(i) => Some(&mut self.cells[i]),
+            None => None
+        }
+    }
+    // Replace the value at a coordinate, returns false if it's outside the grid
+    pub fn set(&mut self, coord: Coord, value: T) -> bool {
+        match self.index(coord) {
+            Some(i) => {
+                self.cells[i] = value;
+                true
+            },
+            None => false
+        }
+    }
+    // Every coordinate in row major order
+    pub fn coords(&self) -> impl Iterator<Item = Coord> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| Coord::new(y, x)))
+    }
+    // Every coordinate and value in row major order
+    pub fn iter(&self) -> impl Iterator<Item = (Coord, &T)> {
+        self.coords().zip(self.cells.iter())
+    }
+    // Every value in row major order, for changing the whole grid at once
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.cells.iter_mut()
+    }
+}
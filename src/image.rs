@@ -0,0 +1,284 @@
+// Render maps to png images, each tile is a square of pixels in the color the curses viewer draws it,
+// and read maps painted in an image editor back in
+use std::collections::HashMap;
+use std::io::BufWriter;
+use super::{Biome, Cell, Coord, Map, MapError, Palette};
+use super::analysis;
+use super::{COLOR_WALL, COLOR_FLOOR, COLOR_PLAYER, COLOR_ENTITY, COLOR_EXIT, COLOR_FOREST, COLOR_DESERT, COLOR_SWAMP, COLOR_TUNDRA, COLOR_UNKNOWN};
+
+// Largest pixels per tile, a 200x200 map at 64 is already 12800 pixels wide
+pub const MAX_SCALE: u32 = 64;
+
+// Most pixels in one image, a 16384x16384 square or 768 MiB of rgb, so a big map at a big scale is an error
+// instead of an allocation that can't be made
+pub const MAX_PIXELS: u64 = 16384 * 16384;
+
+// Rgb pixels in row major order
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+// Pixel colors of a painted map and the tile character each one becomes, a space is no tile
+#[derive(Debug, Clone)]
+pub struct ColorLegend {
+    pub colors: HashMap<[u8; 3], char>
+}
+
+// Rgb value of a color number from the 256 color terminal palette (xterm default colors)
+pub fn xterm_rgb(color: i16) -> [u8; 3] {
+    const BASIC: [[u8; 3]; 16] = [
+        [0, 0, 0], [128, 0, 0], [0, 128, 0], [128, 128, 0], [0, 0, 128], [128, 0, 128], [0, 128, 128], [192, 192, 192],
+        [128, 128, 128], [255, 0, 0], [0, 255, 0], [255, 255, 0], [0, 0, 255], [255, 0, 255], [0, 255, 255], [255, 255, 255]
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match color {
+        0..=15 => BASIC[color as usize],
+        16..=231 => {
+            let n = (color - 16) as usize;
+            [LEVELS[n / 36], LEVELS[(n / 6) % 6], LEVELS[n % 6]]
+        },
+        232..=255 => {
+            let gray = 8 + 10 * (color - 232) as u8;
+            [gray, gray, gray]
+        },
+        _ => [0, 0, 0]
+    }
+}
+
+// Viewer color pair of a terrain tile, picked the same way as CursesMap::color_tile
+pub fn tile_pair(cell: &Cell, palette: &Palette) -> (i16, i16) {
+    if cell.c == palette.wall {
+        COLOR_WALL
+    } else if cell.c == palette.floor {
+        match cell.biome {
+            Some(Biome::Forest) => COLOR_FOREST,
+            Some(Biome::Desert) => COLOR_DESERT,
+            Some(Biome::Swamp) => COLOR_SWAMP,
+            Some(Biome::Tundra) => COLOR_TUNDRA,
+            None => COLOR_FLOOR
+        }
+    } else {
+        COLOR_UNKNOWN
+    }
+}
+
+// Color of a terrain tile
+pub fn tile_color(cell: &Cell, palette: &Palette) -> [u8; 3] {
+    xterm_rgb(tile_pair(cell, palette).0)
+}
+
+impl Image {
+    // Black image, images over MAX_PIXELS are validation errors
+    pub fn new(width: u32, height: u32) -> Result<Image, MapError> {
+        let pixels = width as u64 * height as u64;
+        if pixels > MAX_PIXELS {
+            return Err(MapError::Validation(format!("a {}x{} pixel image is over the {} pixel limit", width, height, MAX_PIXELS)));
+        }
+        Ok(Image { width, height, pixels: vec![0; pixels as usize * 3] })
+    }
+    // Fill the square for one tile
+    pub fn fill_tile(&mut self, coord: Coord, scale: u32, color: [u8; 3]) {
+        if coord.y < 0 || coord.x < 0 {
+            return;
+        }
+        let (width, height, scale) = (self.width as usize, self.height as usize, scale as usize);
+        let (top, left) = (coord.y as usize * scale, coord.x as usize * scale);
+        if top >= height || left >= width {
+            return;
+        }
+        for py in top..(top + scale).min(height) {
+            for px in left..(left + scale).min(width) {
+                let i = (py * width + px) * 3;
+                self.pixels[i..i + 3].copy_from_slice(&color);
+            }
+        }
+    }
+    // Color of one pixel
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+    // Every scale x scale square starting at the top left is one color
+    pub fn is_squares(&self, scale: u32) -> bool {
+        (0..self.height).all(|y| (0..self.width).all(|x| self.pixel(x, y) == self.pixel(x - x % scale, y - y % scale)))
+    }
+    // Png file contents
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(BufWriter::new(&mut out), self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&self.pixels).unwrap();
+        }
+        out
+    }
+}
+
+// Read a color written as RRGGBB hex, with or without a leading #
+pub fn parse_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let mut color = [0; 3];
+    for (i, part) in color.iter_mut().enumerate() {
+        *part = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(color)
+}
+
+impl ColorLegend {
+    pub fn new() -> ColorLegend {
+        ColorLegend { colors: HashMap::new() }
+    }
+    // Colors written by export --to png, so exported images import again
+    pub fn from_palette(palette: &Palette) -> ColorLegend {
+        let mut legend = ColorLegend::new();
+        legend.insert([0, 0, 0], ' ');
+        for color in [COLOR_FLOOR, COLOR_FOREST, COLOR_DESERT, COLOR_SWAMP, COLOR_TUNDRA, COLOR_ENTITY, COLOR_EXIT].iter() {
+            legend.insert(xterm_rgb(color.0), palette.floor);
+        }
+        legend.insert(xterm_rgb(COLOR_WALL.0), palette.wall);
+        legend
+    }
+    // Read "RRGGBB=c" pairs separated by commas, like "000000=#,ffffff=.,0000ff= "
+    pub fn parse(spec: &str) -> Result<ColorLegend, String> {
+        let mut legend = ColorLegend::new();
+        let mut rest = spec;
+        while !rest.trim_start_matches([',', ' ']).is_empty() {
+            rest = rest.trim_start_matches([',', ' ']);
+            let equals = match rest.find('=') {
+                Some(i) => i,
+                None => return Err(format!("legend entry '{}' must look like RRGGBB=#", rest))
+            };
+            let hex = &rest[..equals];
+            let color = match parse_color(hex) {
+                Some(color) => color,
+                None => return Err(format!("bad legend color '{}', should be RRGGBB hex", hex))
+            };
+            let mut chars = rest[equals + 1..].chars();
+            let c = match chars.next() {
+                Some(c) => c,
+                None => return Err(format!("legend color {} has no character", hex))
+            };
+            legend.insert(color, c);
+            rest = chars.as_str();
+            if !rest.is_empty() && !rest.starts_with(',') {
+                return Err(format!("legend color {} should map to one character", hex));
+            }
+        }
+        Ok(legend)
+    }
+    pub fn insert(&mut self, color: [u8; 3], c: char) {
+        self.colors.insert(color, c);
+    }
+    pub fn get(&self, color: [u8; 3]) -> Option<char> {
+        self.colors.get(&color).cloned()
+    }
+}
+
+impl Default for ColorLegend {
+    fn default() -> ColorLegend {
+        ColorLegend::from_palette(&Palette::default())
+    }
+}
+
+// Decode png file contents into rgb pixels, transparent pixels become black
+pub fn decode_png(bytes: &[u8]) -> Result<Image, MapError> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| MapError::Parse(format!("bad png file: {}", e)))?;
+    let mut image = Image::new(reader.info().width, reader.info().height)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| MapError::Parse(format!("bad png file: {}", e)))?;
+    let channels = info.color_type.samples();
+    for (i, pixel) in image.pixels.chunks_mut(3).enumerate() {
+        let p = &buf[i * channels..(i + 1) * channels];
+        let (rgb, alpha) = match info.color_type {
+            png::ColorType::Grayscale => ([p[0], p[0], p[0]], 255),
+            png::ColorType::GrayscaleAlpha => ([p[0], p[0], p[0]], p[1]),
+            png::ColorType::Rgba => ([p[0], p[1], p[2]], p[3]),
+            _ => ([p[0], p[1], p[2]], 255)
+        };
+        if alpha != 0 {
+            pixel.copy_from_slice(&rgb);
+        }
+    }
+    Ok(image)
+}
+
+// Pixels per tile of an image, the largest scale it's drawn in whole squares of one color
+// Exported maps always give back their export scale, the spawn is one square no other square shares a color with
+pub fn detect_scale(image: &Image) -> u32 {
+    (2..=MAX_SCALE).rev().find(|scale| image.width.is_multiple_of(*scale) && image.height.is_multiple_of(*scale) && image.is_squares(*scale)).unwrap_or(1)
+}
+
+// Build a map from a painted image, reading the middle pixel of each scale x scale square as one tile
+// Without a scale it's detected, pixels in the spawn color are floor tiles with the spawn on them and
+// colors that aren't in the legend are schema errors
+pub fn import_png(bytes: &[u8], legend: &ColorLegend, spawn_color: [u8; 3], scale: Option<u32>) -> Result<Map, MapError> {
+    let image = decode_png(bytes)?;
+    let scale = scale.unwrap_or_else(|| detect_scale(&image)).clamp(1, MAX_SCALE);
+    let (sizey, sizex) = ((image.height / scale) as i32, (image.width / scale) as i32);
+    if sizey == 0 || sizex == 0 {
+        return Err(MapError::Schema(format!("image is smaller than one {}x{} tile", scale, scale)));
+    }
+    Map::check_size(sizey as i64, sizex as i64)?;
+    let palette = Palette::default();
+    let mut map = Map::empty(sizey, sizex, palette);
+    let mut spawn: Option<Coord> = None;
+    for coord in map.map_grid.coords().collect::<Vec<Coord>>() {
+        let (py, px) = (coord.y as u32 * scale + scale / 2, coord.x as u32 * scale + scale / 2);
+        let color = image.pixel(px, py);
+        let c = if color == spawn_color {
+            if let Some(first) = spawn {
+                return Err(MapError::Schema(format!("image has more than one spawn marker, at {} and {}", first.key(), coord.key())));
+            }
+            spawn = Some(coord);
+            palette.floor
+        } else {
+            match legend.get(color) {
+                Some(c) => c,
+                None => return Err(MapError::Schema(format!("color {:02x}{:02x}{:02x} at tile {} is not in the legend", color[0], color[1], color[2], coord.key())))
+            }
+        };
+        if c != ' ' {
+            map.map_grid.set(coord, Some(Cell::new(c)));
+        }
+    }
+    if let Some(spawn) = spawn {
+        map.map_metadata.spawn = spawn;
+    }
+    analysis::label_regions(&mut map);
+    Ok(map)
+}
+
+// Draw a map with scale pixels per tile, layered like the viewer: terrain, exit, entities then the player at spawn
+pub fn render(map: &Map, scale: u32) -> Result<Image, MapError> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    let (sizey, sizex) = map.mapsize();
+    let mut image = Image::new(sizex.max(0) as u32 * scale, sizey.max(0) as u32 * scale)?;
+    let palette = map.map_metadata.palette;
+    for (coord, cell) in map.map_grid.iter() {
+        if let Some(cell) = cell {
+            image.fill_tile(coord, scale, tile_color(cell, &palette));
+        }
+    }
+    if let Some(exit) = map.map_metadata.exit {
+        image.fill_tile(exit, scale, xterm_rgb(COLOR_EXIT.0));
+    }
+    for entity in map.map_entities.iter() {
+        image.fill_tile(Coord::new(entity.y, entity.x), scale, xterm_rgb(COLOR_ENTITY.0));
+    }
+    image.fill_tile(map.map_metadata.spawn, scale, xterm_rgb(COLOR_PLAYER.0));
+    Ok(image)
+}
+
+// Png file contents for a map
+pub fn export_png(map: &Map, scale: u32) -> Result<Vec<u8>, MapError> {
+    Ok(render(map, scale)?.to_png())
+}
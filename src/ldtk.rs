@@ -0,0 +1,333 @@
+// LDtk project export, one level with a Terrain IntGrid layer (1 is wall, 2 is floor) and an Entities layer
+// holding the spawn, exit and placed entities
+use serde_json::{json, Value};
+use super::{EntityKind, Map};
+use super::image::xterm_rgb;
+use super::{COLOR_WALL, COLOR_FLOOR, COLOR_PLAYER, COLOR_ENTITY, COLOR_EXIT};
+
+// LDtk version the project files are written for
+pub const LDTK_VERSION: &str = "1.5.3";
+
+// IntGrid values of the Terrain layer, 0 is an empty cell
+pub const INT_WALL: i32 = 1;
+pub const INT_FLOOR: i32 = 2;
+
+const TERRAIN_UID: i32 = 1;
+const ENTITIES_UID: i32 = 2;
+const LEVEL_UID: i32 = 3;
+const SPAWN_UID: i32 = 10;
+const EXIT_UID: i32 = 11;
+const MONSTER_UID: i32 = 12;
+const CHEST_UID: i32 = 13;
+const NPC_UID: i32 = 14;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// LDtk wants a unique iid on the project, level, layers and entities
+// They're numbered from a hash of the map, so exporting the same map twice gives the same file
+struct Iids {
+    hash: u64,
+    count: u64
+}
+
+impl Iids {
+    // Hash of what the export writes: size, terrain, spawn, exit and entities
+    fn new(map: &Map) -> Iids {
+        let mut hash = FNV_OFFSET;
+        let mut add = |n: i64| {
+            for byte in n.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        let (sizey, sizex) = map.mapsize();
+        add(sizey as i64);
+        add(sizex as i64);
+        for (_, cell) in map.map_grid.iter() {
+            add(cell.map_or(-1, |cell| cell.c as i64));
+        }
+        let spawn = map.map_metadata.spawn;
+        add(spawn.y as i64);
+        add(spawn.x as i64);
+        if let Some(exit) = map.map_metadata.exit {
+            add(exit.y as i64);
+            add(exit.x as i64);
+        }
+        for entity in map.map_entities.iter() {
+            add(entity.y as i64);
+            add(entity.x as i64);
+            add(entity.c as i64);
+        }
+        Iids { hash, count: 0 }
+    }
+    // The next number for this map, splitmix64 of the hash and how many came before
+    fn number(&mut self) -> u64 {
+        self.count += 1;
+        let mut z = self.hash.wrapping_add(self.count.wrapping_mul(0x9e3779b97f4a7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    // Formatted like a version 4 uuid, which is what LDtk makes
+    fn next(&mut self) -> String {
+        let (a, b) = (self.number(), self.number());
+        format!("{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}", a >> 32, (a >> 16) & 0xffff, a & 0xfff, ((b >> 48) & 0x3fff) | 0x8000, b & 0xffff_ffff_ffff)
+    }
+}
+
+fn hex(color: i16) -> String {
+    let rgb = xterm_rgb(color);
+    format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2])
+}
+
+// Pixel position of a cell count, in i64 so a long map at a big grid size doesn't overflow
+fn pixels(cells: i32, grid_size: i32) -> i64 {
+    cells as i64 * grid_size as i64
+}
+
+// Layer definition shared by both layers, LDtk fills in anything missing with defaults but complains about it
+fn layer_def(kind: &str, identifier: &str, uid: i32, grid_size: i32) -> Value {
+    json!({
+        "__type": kind,
+        "identifier": identifier,
+        "type": kind,
+        "uid": uid,
+        "doc": null,
+        "uiColor": null,
+        "gridSize": grid_size,
+        "guideGridWid": 0,
+        "guideGridHei": 0,
+        "displayOpacity": 1,
+        "inactiveOpacity": 1,
+        "hideInList": false,
+        "hideFieldsWhenInactive": true,
+        "canSelectWhenInactive": true,
+        "renderInWorldView": true,
+        "pxOffsetX": 0,
+        "pxOffsetY": 0,
+        "parallaxFactorX": 0,
+        "parallaxFactorY": 0,
+        "parallaxScaling": true,
+        "requiredTags": [],
+        "excludedTags": [],
+        "autoTilesKilledByOtherLayers": false,
+        "intGridValues": [],
+        "intGridValuesGroups": [],
+        "autoRuleGroups": [],
+        "autoSourceLayerDefUid": null,
+        "tilesetDefUid": null,
+        "tilePivotX": 0,
+        "tilePivotY": 0,
+        "biomeFieldUid": null
+    })
+}
+
+fn entity_def(identifier: &str, uid: i32, color: i16, max_count: i32, grid_size: i32) -> Value {
+    json!({
+        "identifier": identifier,
+        "uid": uid,
+        "tags": [],
+        "exportToToc": false,
+        "allowOutOfBounds": false,
+        "doc": null,
+        "width": grid_size,
+        "height": grid_size,
+        "resizableX": false,
+        "resizableY": false,
+        "minWidth": null,
+        "maxWidth": null,
+        "minHeight": null,
+        "maxHeight": null,
+        "keepAspectRatio": false,
+        "tileOpacity": 1,
+        "fillOpacity": 1,
+        "lineOpacity": 1,
+        "hollow": false,
+        "color": hex(color),
+        "renderMode": "Rectangle",
+        "showName": true,
+        "tilesetId": null,
+        "tileRenderMode": "FitInside",
+        "tileRect": null,
+        "uiTileRect": null,
+        "nineSliceBorders": [],
+        "maxCount": max_count,
+        "limitScope": "PerLevel",
+        "limitBehavior": "MoveLastOne",
+        "pivotX": 0,
+        "pivotY": 0,
+        "fieldDefs": []
+    })
+}
+
+fn entity_instance(iids: &mut Iids, identifier: &str, uid: i32, color: i16, y: i32, x: i32, grid_size: i32) -> Value {
+    json!({
+        "__identifier": identifier,
+        "__grid": [x, y],
+        "__pivot": [0, 0],
+        "__tags": [],
+        "__tile": null,
+        "__smartColor": hex(color),
+        "__worldX": pixels(x, grid_size),
+        "__worldY": pixels(y, grid_size),
+        "iid": iids.next(),
+        "width": grid_size,
+        "height": grid_size,
+        "defUid": uid,
+        "px": [pixels(x, grid_size), pixels(y, grid_size)],
+        "fieldInstances": []
+    })
+}
+
+fn layer_instance(iids: &mut Iids, kind: &str, identifier: &str, uid: i32, sizey: i32, sizex: i32, grid_size: i32) -> Value {
+    json!({
+        "__identifier": identifier,
+        "__type": kind,
+        "__cWid": sizex,
+        "__cHei": sizey,
+        "__gridSize": grid_size,
+        "__opacity": 1,
+        "__pxTotalOffsetX": 0,
+        "__pxTotalOffsetY": 0,
+        "__tilesetDefUid": null,
+        "__tilesetRelPath": null,
+        "iid": iids.next(),
+        "levelId": LEVEL_UID,
+        "layerDefUid": uid,
+        "pxOffsetX": 0,
+        "pxOffsetY": 0,
+        "visible": true,
+        "optionalRules": [],
+        "intGridCsv": [],
+        "autoLayerTiles": [],
+        "seed": iids.number() % 9999999,
+        "overrideTilesetUid": null,
+        "gridTiles": [],
+        "entityInstances": []
+    })
+}
+
+// Write a map as an LDtk project with one level, grid_size is the pixel size of a cell
+pub fn export_ldtk(map: &Map, grid_size: i32) -> String {
+    let mut iids = Iids::new(map);
+    let (sizey, sizex) = map.mapsize();
+    let palette = map.map_metadata.palette;
+
+    let mut terrain_def = layer_def("IntGrid", "Terrain", TERRAIN_UID, grid_size);
+    terrain_def["intGridValues"] = json!([
+        { "value": INT_WALL, "identifier": "wall", "color": hex(COLOR_WALL.0), "tile": null, "groupUid": 0 },
+        { "value": INT_FLOOR, "identifier": "floor", "color": hex(COLOR_FLOOR.0), "tile": null, "groupUid": 0 }
+    ]);
+    let entities_def = layer_def("Entities", "Entities", ENTITIES_UID, grid_size);
+
+    let mut terrain = layer_instance(&mut iids, "IntGrid", "Terrain", TERRAIN_UID, sizey, sizex, grid_size);
+    terrain["intGridCsv"] = map.map_grid.iter().map(|(_, cell)| match cell {
+        Some(cell) if cell.c == palette.wall => INT_WALL,
+        Some(cell) if cell.c == palette.floor => INT_FLOOR,
+        _ => 0
+    }).collect::<Vec<i32>>().into();
+
+    let spawn = map.map_metadata.spawn;
+    let mut instances = vec![entity_instance(&mut iids, "Spawn", SPAWN_UID, COLOR_PLAYER.0, spawn.y, spawn.x, grid_size)];
+    if let Some(exit) = map.map_metadata.exit {
+        instances.push(entity_instance(&mut iids, "Exit", EXIT_UID, COLOR_EXIT.0, exit.y, exit.x, grid_size));
+    }
+    for entity in map.map_entities.iter() {
+        let (identifier, uid) = match entity.entity.kind {
+            EntityKind::Monster => ("Monster", MONSTER_UID),
+            EntityKind::Chest => ("Chest", CHEST_UID),
+            EntityKind::Npc => ("Npc", NPC_UID)
+        };
+        instances.push(entity_instance(&mut iids, identifier, uid, COLOR_ENTITY.0, entity.y, entity.x, grid_size));
+    }
+    let mut entities = layer_instance(&mut iids, "Entities", "Entities", ENTITIES_UID, sizey, sizex, grid_size);
+    entities["entityInstances"] = instances.into();
+
+    let defs = json!({
+        "layers": [entities_def, terrain_def],
+        "entities": [
+            entity_def("Spawn", SPAWN_UID, COLOR_PLAYER.0, 1, grid_size),
+            entity_def("Exit", EXIT_UID, COLOR_EXIT.0, 1, grid_size),
+            entity_def("Monster", MONSTER_UID, COLOR_ENTITY.0, 0, grid_size),
+            entity_def("Chest", CHEST_UID, COLOR_ENTITY.0, 0, grid_size),
+            entity_def("Npc", NPC_UID, COLOR_ENTITY.0, 0, grid_size)
+        ],
+        "tilesets": [],
+        "enums": [],
+        "externalEnums": [],
+        "levelFields": []
+    });
+    let level = json!({
+        "identifier": "Level_0",
+        "iid": iids.next(),
+        "uid": LEVEL_UID,
+        "worldX": 0,
+        "worldY": 0,
+        "worldDepth": 0,
+        "pxWid": pixels(sizex, grid_size),
+        "pxHei": pixels(sizey, grid_size),
+        "__bgColor": hex(0),
+        "bgColor": null,
+        "useAutoIdentifier": true,
+        "bgRelPath": null,
+        "bgPos": null,
+        "bgPivotX": 0.5,
+        "bgPivotY": 0.5,
+        "__smartColor": "#ADADB5",
+        "__bgPos": null,
+        "externalRelPath": null,
+        "fieldInstances": [],
+        // Top layer first, like LDtk saves them
+        "layerInstances": [entities, terrain],
+        "__neighbours": []
+    });
+
+    let project = json!({
+        "__header__": {
+            "fileType": "LDtk Project JSON",
+            "app": "LDtk",
+            "doc": "https://ldtk.io/json",
+            "schema": "https://ldtk.io/files/JSON_SCHEMA.json",
+            "appAuthor": "Sebastien 'deepnight' Benard",
+            "appVersion": LDTK_VERSION,
+            "url": "https://ldtk.io"
+        },
+        "iid": iids.next(),
+        "jsonVersion": LDTK_VERSION,
+        "appBuildId": 473703,
+        "nextUid": NPC_UID + 1,
+        "identifierStyle": "Capitalize",
+        "toc": [],
+        "worldLayout": "Free",
+        "worldGridWidth": pixels(sizex, grid_size),
+        "worldGridHeight": pixels(sizey, grid_size),
+        "defaultLevelWidth": pixels(sizex, grid_size),
+        "defaultLevelHeight": pixels(sizey, grid_size),
+        "defaultPivotX": 0,
+        "defaultPivotY": 0,
+        "defaultGridSize": grid_size,
+        "defaultEntityWidth": grid_size,
+        "defaultEntityHeight": grid_size,
+        "bgColor": "#40465B",
+        "defaultLevelBgColor": hex(0),
+        "minifyJson": true,
+        "externalLevels": false,
+        "exportTiled": false,
+        "simplifiedExport": false,
+        "imageExportMode": "None",
+        "exportLevelBg": true,
+        "pngFilePattern": null,
+        "backupOnSave": false,
+        "backupLimit": 10,
+        "backupRelPath": null,
+        "levelNamePattern": "Level_%idx",
+        "tutorialDesc": null,
+        "customCommands": [],
+        "flags": [],
+        "defs": defs,
+        "levels": [level],
+        "worlds": [],
+        "dummyWorldIid": iids.next()
+    });
+    serde_json::to_string(&project).unwrap()
+}
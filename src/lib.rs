@@ -1,26 +1,52 @@
 extern crate ncurses;
+extern crate libc;
 extern crate rand;
 extern crate serde;
 extern crate serde_json;
-extern crate regex;
 extern crate flate2;
+extern crate zstd;
+extern crate xz2;
+extern crate png;
+extern crate roxmltree;
+
+pub mod analysis;
+pub mod binary;
+pub mod check;
+pub mod compat;
+pub mod compress;
+pub mod error;
+pub mod godot;
+pub mod grid;
+pub mod image;
+pub mod ldtk;
+pub mod metadata;
+pub mod render;
+pub mod rexpaint;
+pub mod save;
+pub mod stream;
+pub mod text;
+pub mod tiled;
 
 use ncurses::*;
 use rand::Rng;
 use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::string::String;
 use serde::{Serialize, Deserialize};
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufWriter;
-use std::path::Path;
-//use regex::Regex;
-use regex::RegexSet;
+use std::path::{Path, PathBuf};
 use flate2::write::GzEncoder;
 use flate2::read::GzDecoder;
 use flate2::Compression;
+use analysis::Region;
+pub use compress::Codec;
+pub use error::MapError;
+pub use grid::{Coord, Grid};
+pub use metadata::{MapFile, MapMetadata, Palette, RegionSeed};
+pub use save::SaveOptions;
 
 // The player x and y coordinates move the view of the map, with the player at center
 pub struct Player {
@@ -29,13 +55,95 @@ pub struct Player {
     c: char,
 }
 
+// Set when curses runs on /dev/tty, see Curses::start_curses_tty
+static ON_TTY: AtomicBool = AtomicBool::new(false);
+
+// Spawn location, the viewer starts the player at the center of its 24x70 window
+pub const SPAWN_Y: i32 = 12;
+pub const SPAWN_X: i32 = 35;
+
+// Most tiles a map file can ask for, 10000x10000, bigger sizes are rejected before the grid is made
+pub const MAX_TILES: usize = 100_000_000;
+
 // Each tile represents a character on the map, stored in game_objects, and json files
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tile {
     pub y: i32,
     pub x: i32,
     pub c: char,
-    pub neighbors: Vec<String> // this will store a key to game_objects, for each neighbor tiles
+    pub neighbors: Vec<String>, // this will store a key to game_objects, for each neighbor tiles
+    // Only entity tiles (e# keys) use this, left out of the json otherwise so python maps are unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity: Option<Entity>,
+    // Biome of the v-region this tile belongs to, also set on the v# region seeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biome: Option<Biome>,
+    // Id of the room or corridor a floor tile is in, see analysis::label_regions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<usize>,
+    // Only region table tiles (r# keys) use this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region_info: Option<Region>
+}
+
+// Terrain kept in each grid cell, written to json files as a Tile with an empty neighbors list
+// Kept to 16 bytes with or without the Option around it, a 5000x5000 grid is 400 MB
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    pub biome: Option<Biome>,
+    pub region: Option<u32>
+}
+
+// Climate of a v-region, only changes how the floor is colored
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Biome {
+    Forest,
+    Desert,
+    Swamp,
+    Tundra
+}
+
+// Types of things that can be placed on top of the terrain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Monster,
+    Chest,
+    Npc
+}
+
+// Entity data stored on e# tiles, properties hold any extra json a game wants to attach
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entity {
+    pub kind: EntityKind,
+    #[serde(default)]
+    pub properties: serde_json::Map<String, serde_json::Value>
+}
+
+// An entity on top of the terrain, version 2 map files keep these in their own list
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlacedEntity {
+    pub y: i32,
+    pub x: i32,
+    pub c: char,
+    #[serde(flatten)]
+    pub entity: Entity
+}
+
+// Rules for spreading entities over the floor v-regions during map generation
+pub struct EntityRules {
+    pub density: f64, // fraction of each region's floor tiles that get an entity
+    pub min_spawn_distance: i32, // no entities closer than this to spawn
+    pub kinds: Vec<EntityKind>
+}
+
+// Settings for Map::new_with_options, Map::new uses the defaults
+pub struct MapOptions {
+    pub entities: EntityRules,
+    pub exit_percentile: f64, // 0.0 is the reachable floor tile closest to spawn, 1.0 is the farthest
+    pub biome_noise: f64 // how far a region's latitude can be shifted at random, as a fraction of map height
 }
 
 // Shared curses static functions
@@ -58,6 +166,20 @@ pub struct CursesQuestion {
 }
 
 // Window to draw map
+// Colors of the viewer as (foreground, background) from the 256 color terminal palette
+// Image export uses the same numbers so pictures look like the curses view
+pub const COLOR_WALL: (i16, i16) = (57, 234);
+pub const COLOR_FLOOR: (i16, i16) = (60, 0);
+pub const COLOR_PLAYER: (i16, i16) = (35, 0);
+pub const COLOR_ENTITY: (i16, i16) = (166, 0);
+pub const COLOR_EXIT: (i16, i16) = (226, 0);
+pub const COLOR_FOREST: (i16, i16) = (28, 0);
+pub const COLOR_DESERT: (i16, i16) = (179, 0);
+pub const COLOR_SWAMP: (i16, i16) = (65, 0);
+pub const COLOR_TUNDRA: (i16, i16) = (153, 0);
+// Tiles that are neither wall nor floor, so a broken map doesn't look like it has players all over it
+pub const COLOR_UNKNOWN: (i16, i16) = (201, 0);
+
 pub struct CursesMap {
     pub height: i32,
     pub width: i32,
@@ -67,16 +189,40 @@ pub struct CursesMap {
     pub curse_player: char,
     pub curse_color_wall: i16,
     pub curse_color_floor: i16,
-    pub curse_color_player: i16
+    pub curse_color_player: i16,
+    pub curse_color_entity: i16,
+    pub curse_color_exit: i16,
+    pub curse_color_forest: i16,
+    pub curse_color_desert: i16,
+    pub curse_color_swamp: i16,
+    pub curse_color_tundra: i16,
+    pub curse_color_unknown: i16
 }
 
 pub struct Map {
-    pub map_wall: char,
-    pub map_floor: char,
-    pub map_player: char,
-    pub map_game_objects: HashMap<String, Tile>
+    pub map_metadata: MapMetadata,
+    pub map_grid: Grid<Option<Cell>>, // terrain, None where a map file was missing a tile
+    pub map_entities: Vec<PlacedEntity>,
+    pub map_objects: HashMap<String, Tile> // legacy json keys this program doesn't understand, kept so saving loses nothing
+}
+
+// File layouts save_map can write, load_map tells them apart on its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    Json, // version 2, typed metadata separate from terrain
+    Legacy, // one json object of tiles, same as the python curses-map-generator
+    Binary // compact run length encoded tiles, see binary.rs
 }
 
+// Where maps are kept when a command is given a file name without a directory
+pub const DEFAULT_DIR: &str = "./resources/maps";
+// Environment variable with the map directory, --dir on the command line is used over it
+pub const DIR_VARIABLE: &str = "MAPS_DIR";
+// File name for stdin or stdout, create writes the map to stdout and view reads it from stdin
+pub const STDIO: &str = "-";
+// Longest file name most file systems allow, in bytes
+pub const MAX_NAME_LEN: usize = 255;
+
 pub struct Validation {
     pub file_name: String,
     pub file_path: String,
@@ -88,45 +234,109 @@ pub struct Validation {
 
 // Simple validation used to share file information with rest of program
 impl Validation {
+    // A file in the map directory from MAPS_DIR, or the default one
     pub fn new(file_name: &str) -> Validation {
-        let path = String::from("./resources/maps/") + &file_name;
+        Validation::in_dir(&Validation::map_dir(None), file_name)
+    }
+
+    // A bare file name ("cave.map") is in dir, a name with a directory ("maps/cave.map", "/tmp/cave.map") is used
+    // as it is, relative to the working directory
+    pub fn in_dir(dir: &str, file_name: &str) -> Validation {
+        let path = if Validation::is_path(file_name) {
+            PathBuf::from(file_name)
+        } else {
+            Path::new(dir).join(file_name)
+        };
+        let path = path.to_string_lossy().into_owned();
         Validation {
-            file_name: file_name.to_string().clone(),
+            file_name: file_name.to_string(),
             file_path: path.clone(),
             file_exists: Path::new(&path).exists(),
-            file_valid: Validation::validate_filename(&file_name),
+            file_valid: Validation::validate_filename(file_name),
             file_compressed: Validation::file_compressed(&path)
         }
     }
 
+    // The map directory: dir when it's given (--dir), then MAPS_DIR, then DEFAULT_DIR
+    pub fn map_dir(dir: Option<&str>) -> String {
+        Validation::map_dir_from(dir, std::env::var(DIR_VARIABLE).ok().as_deref())
+    }
+
+    // map_dir with the MAPS_DIR value passed in, an empty value is the same as none
+    pub fn map_dir_from(dir: Option<&str>, env: Option<&str>) -> String {
+        match (dir, env) {
+            (Some(dir), _) => dir.to_string(),
+            (None, Some(env)) if !env.is_empty() => env.to_string(),
+            _ => String::from(DEFAULT_DIR)
+        }
+    }
+
+    // Check if a file name has a directory in it, or is only a name
+    pub fn is_path(file_name: &str) -> bool {
+        let path = Path::new(file_name);
+        path.is_absolute() || path.components().count() > 1
+    }
+
     // Check if filename is fairly normally named
     pub fn validate_filename(file_name: &str) -> bool {
-        let set = RegexSet::new(&[
-            r"^[\w_\d]+$",
-            r"^[\w_\d]+.map$",
-            r"^[\w_\d]+.gz$",
-            r"^[\w_\d]+.map.gz$"
-        ]).unwrap();
-        let matches: Vec<_> = set.matches(&file_name).into_iter().collect();
-        if matches.len() > 0 {
-            return true;
+        Validation::check_filename(file_name).is_ok()
+    }
+
+    // The file name policy, only the last part of a path is checked:
+    // letters and numbers in any language, '_', '-' and '.', not starting with '.' or '-' and at most 255 bytes
+    pub fn check_filename(file_name: &str) -> Result<(), String> {
+        let name = match Path::new(file_name).file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return Err(format!("'{}' is not a file name", file_name))
+        };
+        if name.len() > MAX_NAME_LEN {
+            return Err(format!("file name '{}' is longer than {} bytes", name, MAX_NAME_LEN));
+        } else if name.starts_with('.') {
+            return Err(format!("file name '{}' can't start with '.'", name));
+        } else if name.starts_with('-') {
+            return Err(format!("file name '{}' can't start with '-'", name));
+        }
+        match name.chars().find(|c| !(c.is_alphanumeric() || *c == '_' || *c == '-' || *c == '.')) {
+            Some(c) => Err(format!("file name '{}' can't have {:?} in it, use letters, numbers, '_', '-' and '.'", name, c)),
+            None => Ok(())
+        }
+    }
+
+    // Checks for a map that a command reads, the name has to be allowed and the file already there
+    pub fn check_existing(&self) -> Result<(), MapError> {
+        Validation::check_filename(&self.file_name).map_err(MapError::Validation)?;
+        if ! self.file_exists {
+            return Err(MapError::Validation(format!("file '{}' does not exist", self.file_path)));
+        } else if Path::new(&self.file_path).is_dir() {
+            return Err(MapError::Validation(format!("'{}' is a directory", self.file_path)));
+        }
+        Ok(())
+    }
+
+    // Checks for a map that a command writes, the name has to be allowed and not taken yet
+    pub fn check_new(&self) -> Result<(), MapError> {
+        self.check_target(false)
+    }
+
+    // Checks for a map that a command writes, with replace a map that's already there can be written over
+    pub fn check_target(&self, replace: bool) -> Result<(), MapError> {
+        Validation::check_filename(&self.file_name).map_err(MapError::Validation)?;
+        if self.file_exists && Path::new(&self.file_path).is_dir() && replace {
+            return Err(MapError::Validation(format!("'{}' is a directory", self.file_path)));
+        } else if self.file_exists && !replace {
+            return Err(MapError::Validation(format!("file {} already exists, --force replaces it", self.file_path)));
+        }
+        match Path::new(&self.file_path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                Err(MapError::Validation(format!("directory '{}' does not exist", dir.display())))
+            },
+            _ => Ok(())
         }
-        return false;
     }
 
-    // Return true if file is compressed with gzip, false if not
+    // Return true if file is compressed with gzip, zstd or xz, only the first bytes are read to tell
     pub fn file_compressed(path: &str) -> bool {
-        let f = File::open(&path);
-        let f = match f {
-            Ok(file) => file,
-            Err(_error) => return false
-        };
-        let mut s = String::new();
-        let gz = GzDecoder::new(f).read_to_string(&mut s);
-        match gz {
-            Ok(_buf) => return true,
-            Err(_error) => return false
-        };
+        Codec::detect_file(path).is_ok_and(|codec| codec != Codec::None)
     }
 }
 
@@ -152,63 +362,63 @@ impl Player {
         self.x += 1;
     }
     // Determine which keys are being pressed
-    pub fn get_keyboard_input(_win: WINDOW, stats: WINDOW, ch: i32, curse_floor: char, game_objects: &HashMap<String, Tile>, player: &mut Player) {
+    pub fn get_keyboard_input(_win: WINDOW, stats: WINDOW, ch: i32, curse_floor: char, map: &Map, player: &mut Player) {
         if ch == KEY_UP {
-            // Determine the game object key of the tile above the cursor
-            let key = (player.y-1).to_string() + "x" + &(player.x).to_string();
+            // Determine the coordinate of the tile above the cursor
+            let coord = Coord::new(player.y-1, player.x);
             // Check if the tile above the cursor is a wall or floor
-            match game_objects.get(&key) {
+            match map.cell(coord) {
                 Some(tile) => {
                     if tile.c == curse_floor{
                         player.move_up();
                     } else { // Prevent movement through walls
                         wclear(stats);
-                        mvwaddstr(stats,3,1,&key);
+                        mvwaddstr(stats,3,1,&coord.key());
                     }
                 },
                 None => {}
             }
         } else if ch == KEY_DOWN {
-            // Determine the game object key of the tile below the cursor
-            let key = (player.y+1).to_string() + "x" + &(player.x).to_string();
+            // Determine the coordinate of the tile below the cursor
+            let coord = Coord::new(player.y+1, player.x);
             // Check if the tile below the cursor is a wall or floor
-            match game_objects.get(&key) {
+            match map.cell(coord) {
                 Some(tile) => {
                     if tile.c == curse_floor{
                         player.move_down();
                     } else { // Prevent movement through walls
                         wclear(stats);
-                        mvwaddstr(stats,3,1,&key);
+                        mvwaddstr(stats,3,1,&coord.key());
                     }
                 },
                 None => {}
             }
         } else if ch == KEY_LEFT {
-            // Determine the game object key of the tile left of the cursor
-            let key = (player.y).to_string() + "x" + &(player.x-1).to_string();
+            // Determine the coordinate of the tile left of the cursor
+            let coord = Coord::new(player.y, player.x-1);
             // Check if the tile above the cursor is a wall or floor
-            match game_objects.get(&key) {
+            match map.cell(coord) {
                 Some(tile) => {
                     if tile.c == curse_floor {
                         player.move_left();
                     } else { // Prevent movement through walls
                         wclear(stats);
-                        mvwaddstr(stats,3,1,&key);
+                        mvwaddstr(stats,3,1,&coord.key());
                     }
                 },
                 None => {}
             }
         } else if ch == KEY_RIGHT {
-            // Determine the game object key of the tile right of the cursor
-            let key = (player.y).to_string() + "x" + &(player.x+1).to_string();
+            // Determine the coordinate of the tile right of the cursor
+            let coord = Coord::new(player.y, player.x+1);
             // Check if the tile above the cursor is a wall or floor
-            match game_objects.get(&key) {
+            match map.cell(coord) {
                 Some(tile) => {
                     if tile.c == curse_floor {
                         player.move_right();
                     } else { // Prevent movement through walls
                         wclear(stats);
-                        mvwaddstr(stats,3,1,&key);
+                        mvwaddstr(stats,3,1,&coord.key());
                     }
                 },
                 None => {}
@@ -237,13 +447,33 @@ impl Curses {
     }
 
     pub fn start_curses() {
-        initscr();
+        // Windows call this again, it mustn't swap a terminal screen for one on stdin
+        if !ON_TTY.load(Ordering::Relaxed) {
+            initscr();
+        }
         raw();
         keypad(stdscr(), true);
         start_color();
         use_default_colors();
     }
 
+    // Start curses on the terminal itself instead of stdin and stdout, for a map piped in on stdin
+    pub fn start_curses_tty() -> std::io::Result<()> {
+        let path = std::ffi::CString::new("/dev/tty").unwrap();
+        let mode = std::ffi::CString::new("r+").unwrap();
+        let tty = unsafe { libc::fopen(path.as_ptr(), mode.as_ptr()) };
+        if tty.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        newterm(None, tty as ncurses::ll::FILE_p, tty as ncurses::ll::FILE_p);
+        ON_TTY.store(true, Ordering::Relaxed);
+        raw();
+        keypad(stdscr(), true);
+        start_color();
+        use_default_colors();
+        Ok(())
+    }
+
     pub fn cursor_visible() {
         curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
         echo();
@@ -270,7 +500,7 @@ impl Curses {
     }
 
     // Getting a file name
-    pub fn get_map_file_name() -> Validation {
+    pub fn get_map_file_name(dir: &str) -> Validation {
         Curses::cursor_visible();
         let question = CursesQuestion::new(24, 70, "Enter file name(or quit): ");
         let mut answer: String;
@@ -278,7 +508,7 @@ impl Curses {
         loop {
             question.print_question();
             answer = question.get_answer();
-            v = Validation::new(&answer);
+            v = Validation::in_dir(dir, &answer);
             if v.file_name == "q" || v.file_name == "quit" {
                 question.end();
                 Curses::exit(0);
@@ -384,14 +614,28 @@ impl CursesMap {
             curse_player: curse_player,
             curse_color_wall: 1,
             curse_color_floor: 2,
-            curse_color_player: 3
+            curse_color_player: 3,
+            curse_color_entity: 4,
+            curse_color_exit: 5,
+            curse_color_forest: 6,
+            curse_color_desert: 7,
+            curse_color_swamp: 8,
+            curse_color_tundra: 9,
+            curse_color_unknown: 10
         };
         Curses::start_curses();
         Curses::cursor_invisible();
         // Create custom color pairs for: player, floors, and walls
-        init_pair(curses_map.curse_color_wall, 57, 234);
-        init_pair(curses_map.curse_color_floor, 60, 0);
-        init_pair(curses_map.curse_color_player, 35, 0);
+        init_pair(curses_map.curse_color_wall, COLOR_WALL.0, COLOR_WALL.1);
+        init_pair(curses_map.curse_color_floor, COLOR_FLOOR.0, COLOR_FLOOR.1);
+        init_pair(curses_map.curse_color_player, COLOR_PLAYER.0, COLOR_PLAYER.1);
+        init_pair(curses_map.curse_color_entity, COLOR_ENTITY.0, COLOR_ENTITY.1);
+        init_pair(curses_map.curse_color_exit, COLOR_EXIT.0, COLOR_EXIT.1);
+        init_pair(curses_map.curse_color_forest, COLOR_FOREST.0, COLOR_FOREST.1);
+        init_pair(curses_map.curse_color_desert, COLOR_DESERT.0, COLOR_DESERT.1);
+        init_pair(curses_map.curse_color_swamp, COLOR_SWAMP.0, COLOR_SWAMP.1);
+        init_pair(curses_map.curse_color_tundra, COLOR_TUNDRA.0, COLOR_TUNDRA.1);
+        init_pair(curses_map.curse_color_unknown, COLOR_UNKNOWN.0, COLOR_UNKNOWN.1);
         // BUG if refresh is not run at least once, no windows refresh works (uhh?)
         refresh();
         curses_map
@@ -410,26 +654,37 @@ impl CursesMap {
         mvwaddch(win, self.height/2, self.width/2, player.c as chtype);
     }
     // Draws the map. Only draws tiles within players view window, ignores rest of map.
-    fn draw_map(&self, game_objects: &HashMap<String, Tile>, player: &Player) {
+    fn draw_map(&self, map: &Map, player: &Player) {
         let mid_y = self.height / 2;
         let mid_x = self.width / 2;
         // Use players position and map size to determine the loop size
         for ty in player.y-mid_y..player.y+mid_y {
             for tx in player.x-mid_x..player.x+mid_x {
-                // Hashmap stores keys as string "Ypos" + "x" + "Xpos"
-                let key = ty.to_string() + "x" + &tx.to_string();
-                // Check if key exists to avoid crashes (this might let a corrupted map run)
-                match game_objects.get(&key) {
-                    Some(_tile_name) => {
-                        // Draw and color each map tile relative to player position
-                        wattr_on(self.window, self.color_tile(game_objects[&key].c));
-                        mvwaddch(self.window, game_objects[&key].y-player.y+mid_y, game_objects[&key].x-player.x+mid_x, game_objects[&key].c as chtype);
-                        wattr_off(self.window, self.color_tile(game_objects[&key].c));
-                    },
-                    None => {}
+                // Check if the cell exists to avoid crashes (this might let a corrupted map run)
+                if let Some(cell) = map.cell(Coord::new(ty, tx)) {
+                    // Draw and color each map tile relative to player position
+                    wattr_on(self.window, self.color_tile(cell));
+                    mvwaddch(self.window, ty-player.y+mid_y, tx-player.x+mid_x, cell.c as chtype);
+                    wattr_off(self.window, self.color_tile(cell));
                 }
             }
         }
+        // Draw the exit above the terrain
+        if let Some(exit) = map.map_metadata.exit {
+            if (exit.y - player.y).abs() < mid_y && (exit.x - player.x).abs() < mid_x {
+                wattr_on(self.window, COLOR_PAIR(self.curse_color_exit));
+                mvwaddch(self.window, exit.y-player.y+mid_y, exit.x-player.x+mid_x, map.map_metadata.palette.exit as chtype);
+                wattr_off(self.window, COLOR_PAIR(self.curse_color_exit));
+            }
+        }
+        // Draw entities above the terrain
+        for tile in map.map_entities.iter() {
+            if (tile.y - player.y).abs() < mid_y && (tile.x - player.x).abs() < mid_x {
+                wattr_on(self.window, COLOR_PAIR(self.curse_color_entity));
+                mvwaddch(self.window, tile.y-player.y+mid_y, tile.x-player.x+mid_x, tile.c as chtype);
+                wattr_off(self.window, COLOR_PAIR(self.curse_color_entity));
+            }
+        }
         // Draw map border and player on top of the map
         box_(self.window, 0,0);
         wattr_on(self.window, COLOR_PAIR(self.curse_color_player));
@@ -447,13 +702,13 @@ impl CursesMap {
         wrefresh(win);
     }
     // Get player input, draw map view, main loop
-    pub fn play_map(&self, game_objects: &HashMap<String, Tile>) {
+    pub fn play_map(&self, map: &Map) {
         let win = self.window;
         let stats = Curses::make_stats_windows(self.width);
         nodelay(win, true);
         // Create player and add offset position
         let mut player = Player::new(self.height/2, self.width/2, self.curse_player);
-        self.draw_map(&game_objects, &player);
+        self.draw_map(map, &player);
         wrefresh(win);
         let mut ch = 0;
         while ch != 'q' as i32 {
@@ -461,9 +716,8 @@ impl CursesMap {
             flushinp();
             // Force map to always be same size, no matter if window resized
             wresize(win, self.height, self.width);
-            Player::get_keyboard_input(win, stats, ch, self.curse_floor, &game_objects, &mut player);
-            self.draw_map(&game_objects, &player);
-            //self.draw_map(win, &game_objects, &player);
+            Player::get_keyboard_input(win, stats, ch, self.curse_floor, map, &mut player);
+            self.draw_map(map, &player);
             self.draw_stats(stats, &player);
             wnoutrefresh(win);
             thread::sleep(Duration::from_millis(30)); // reduces screen flicker a little, slows player input
@@ -473,20 +727,27 @@ impl CursesMap {
         self.end();
     }
     // Is used to color floors, walls, and players different set colors (color pairs must be created first)
-    fn color_tile(&self, c: char) -> attr_t {
-        if c == self.curse_wall {
+    // Floors with a biome use the biome color instead of the plain floor color
+    fn color_tile(&self, cell: &Cell) -> attr_t {
+        if cell.c == self.curse_wall {
             COLOR_PAIR(self.curse_color_wall)
-        } else if c == self.curse_floor {
-            COLOR_PAIR(self.curse_color_floor)
+        } else if cell.c == self.curse_floor {
+            match cell.biome {
+                Some(Biome::Forest) => COLOR_PAIR(self.curse_color_forest),
+                Some(Biome::Desert) => COLOR_PAIR(self.curse_color_desert),
+                Some(Biome::Swamp) => COLOR_PAIR(self.curse_color_swamp),
+                Some(Biome::Tundra) => COLOR_PAIR(self.curse_color_tundra),
+                None => COLOR_PAIR(self.curse_color_floor)
+            }
         } else {
-            COLOR_PAIR(self.curse_color_player)
+            COLOR_PAIR(self.curse_color_unknown)
         }
      }
 }
 
 impl Tile {
     pub fn new(y: i32, x: i32, c: char, neighbors: Vec<String>) -> Tile {
-        Tile { y: y, x: x, c: c, neighbors: neighbors }
+        Tile { y: y, x: x, c: c, neighbors: neighbors, entity: None, biome: None, region: None, region_info: None }
     }
     // Create new tile key string, xy coordinate with separator
     pub fn get_tile_key(&self) -> String {
@@ -513,13 +774,136 @@ impl Tile {
     }
 }
 
+impl MapFormat {
+    // Format names used on the command line
+    pub fn from_name(name: &str) -> Option<MapFormat> {
+        match name {
+            "json" => Some(MapFormat::Json),
+            "legacy" => Some(MapFormat::Legacy),
+            "bin" => Some(MapFormat::Binary),
+            _ => None
+        }
+    }
+}
+
+impl Cell {
+    pub fn new(c: char) -> Cell {
+        Cell { c, biome: None, region: None }
+    }
+    // Terrain tiles in json files only carry these fields, anything else has to stay a keyed object
+    pub fn fits(tile: &Tile) -> bool {
+        tile.neighbors.is_empty() && tile.entity.is_none() && tile.region_info.is_none() && tile.region.is_none_or(|r| r <= u32::MAX as usize)
+    }
+    pub fn from_tile(tile: &Tile) -> Cell {
+        Cell { c: tile.c, biome: tile.biome, region: tile.region.filter(|r| *r <= u32::MAX as usize).map(|r| r as u32) }
+    }
+    // The Tile written to json for this cell
+    pub fn to_tile(&self, coord: Coord) -> Tile {
+        let mut tile = Tile::new(coord.y, coord.x, self.c, Vec::new());
+        tile.biome = self.biome;
+        tile.region = self.region.map(|r| r as usize);
+        tile
+    }
+}
+
+impl EntityKind {
+    // Character drawn for this kind of entity
+    pub fn glyph(&self) -> char {
+        match self {
+            EntityKind::Monster => 'm',
+            EntityKind::Chest => 'c',
+            EntityKind::Npc => 'n'
+        }
+    }
+}
+
+impl Biome {
+    // One letter per biome, used for the biome rows in version 2 map files
+    pub fn letter(&self) -> char {
+        match self {
+            Biome::Forest => 'f',
+            Biome::Desert => 'd',
+            Biome::Swamp => 's',
+            Biome::Tundra => 't'
+        }
+    }
+    pub fn from_letter(letter: char) -> Option<Biome> {
+        match letter {
+            'f' => Some(Biome::Forest),
+            'd' => Some(Biome::Desert),
+            's' => Some(Biome::Swamp),
+            't' => Some(Biome::Tundra),
+            _ => None
+        }
+    }
+    // Latitude rule: tundra up north, desert down south, forest or swamp in between
+    // Noise shifts each region's latitude a little so the bands don't have straight edges
+    pub fn pick<R: Rng>(y: i32, sizey: i32, noise: f64, rng: &mut R) -> Biome {
+        let mut latitude = y as f64 / sizey.max(1) as f64;
+        if noise > 0.0 {
+            latitude += rng.gen_range(-noise, noise);
+        }
+        if latitude < 0.25 {
+            Biome::Tundra
+        } else if latitude > 0.75 {
+            Biome::Desert
+        } else if rng.gen::<f64>() < 0.3 {
+            Biome::Swamp
+        } else {
+            Biome::Forest
+        }
+    }
+}
+
+impl Entity {
+    pub fn new(kind: EntityKind) -> Entity {
+        Entity { kind, properties: serde_json::Map::new() }
+    }
+}
+
+impl EntityRules {
+    pub fn new() -> EntityRules {
+        EntityRules {
+            density: 0.01,
+            min_spawn_distance: 10,
+            kinds: vec![EntityKind::Monster, EntityKind::Chest, EntityKind::Npc]
+        }
+    }
+}
+
+impl Default for EntityRules {
+    fn default() -> EntityRules {
+        EntityRules::new()
+    }
+}
+
+impl MapOptions {
+    pub fn new() -> MapOptions {
+        MapOptions {
+            entities: EntityRules::new(),
+            exit_percentile: 1.0,
+            biome_noise: 0.15
+        }
+    }
+}
+
+impl Default for MapOptions {
+    fn default() -> MapOptions {
+        MapOptions::new()
+    }
+}
+
 // Structure to store map into file or load from file
 impl Map {
-    // Gen map is used to create a variable sized map using voronoi regions (can be very slow) (needs rewrite plz)
+    // Gen map with the default generation options
     pub fn new(sizey: i32, sizex: i32, map_wall: char, map_floor: char, map_player: char) -> Map {
+        Map::new_with_options(sizey, sizex, map_wall, map_floor, map_player, &MapOptions::new())
+    }
+    // Gen map is used to create a variable sized map using voronoi regions (can be very slow) (needs rewrite plz)
+    pub fn new_with_options(sizey: i32, sizex: i32, map_wall: char, map_floor: char, map_player: char, options: &MapOptions) -> Map {
         // Random numbers
         let mut rng = rand::thread_rng();
-        // Create a new set of game_objects for final results
+        // v-region seed tiles by v# key, they end up in the metadata
         let mut go = HashMap::new();
         // Generate v-regions based on size of map (# of regions matches maps.py YOU LIED HERE, HALF AS MANY!)
         let mut v_regions = Vec::new();
@@ -541,7 +925,14 @@ impl Map {
             }
         }
         // Add extra region for spawn location
-        v_regions.push(Tile::new(12, 35, map_floor, Vec::new()));
+        v_regions.push(Tile::new(SPAWN_Y, SPAWN_X, map_floor, Vec::new()));
+        // Give each v-region a biome, copied to its v# key so the region keeps it in the json
+        for (v, region) in v_regions.iter_mut().enumerate() {
+            region.biome = Some(Biome::pick(region.y, sizey, options.biome_noise, &mut rng));
+            if let Some(seed) = go.get_mut(&(String::from("v") + &v.to_string())) {
+                seed.biome = region.biome;
+            }
+        }
         // Connect vregions through triangulation (update v-region tile closest neighbors)
         for v in 0..number_of_regions {
             let mut closest1 = String::new();
@@ -577,15 +968,11 @@ impl Map {
                     closest2 = closest1.clone();
                 }
             }
-            // Add two closest neighbors to go map
-            let y = go[&vkey].y;
-            let x = go[&vkey].x;
-            let c = go[&vkey].c;
+            // Add two closest neighbors to the in memory v-region (the json v# keys don't keep them yet)
             let mut neighbors = Vec::new();
             neighbors.push(closest1.clone());
             neighbors.push(closest2.clone());
-            let t = Tile::new(y, x, c, neighbors);
-            go.insert(t.get_tile_key(), t);
+            v_regions[v as usize].neighbors = neighbors;
         }
         // Need to do multiple passes before storing in go!
         // You need to modify not only the tile you are working on, BUT ALL THE NEIGHBORS MUST BE UPDATED WITH THIS TILE
@@ -593,99 +980,395 @@ impl Map {
         // This could be done by making an additional pass over the neighbors and eliminating any that have bad characteristics
         // This could also lead to a situation where, if a point cannot be used in any valid triangles, we destroy it
         //take a break from this for now, lost motivatio    n
-        let mapsize = Tile::new(sizey, sizex, '$', Vec::new());
-        let player = Tile::new(0, 0, map_player, Vec::new());
-        // Additional metadata to save in case another programs needs to know the map size
-        go.insert(String::from("mapsize"), mapsize);
-        go.insert(String::from("player"), player);
-        // Manually add each v-region Tile
-        // Hashmap for storing tiles and map data
-        let mut game_objects = HashMap::new();
-        // Generate temporary tiles
-        for y in 0..sizey {
-            for x in 0..sizex {
-                let t = Tile::new(y, x, map_floor, Vec::new());
-                game_objects.insert(t.get_tile_key(), t);
-            }
-        }
-        // Create voronoi regions, convert tiles in game_objects to closest v-region tile type
+        // Create voronoi regions, set each grid cell to the closest v-region tile type
         // This loop plus the distance calc might be slowest part of gen_map
-        for k in game_objects.keys() {
-            let mut closest: usize = 0;
-            for i in 0..number_of_regions+1 { // Add +1 extra region for spawn location
-                // Distance calc code
-                let cur: usize = i as usize;
-                let diff = Tile::distance(&v_regions[cur], &game_objects[k]);
-                let old_diff = Tile::distance(&v_regions[closest], &game_objects[k]);
-                if diff < old_diff {
-                    closest = cur;
-                }
-            }
+        let mut grid = Grid::new(sizey, sizex, None);
+        let seeds: Vec<Coord> = v_regions.iter().map(|v| Coord::new(v.y, v.x)).collect();
+        for coord in grid.coords() {
+            let closest = Map::closest_region(&seeds, coord);
             // Add walls on edges because why not
-            let ttype: char;
-            if game_objects[k].y <= 0 || game_objects[k].y >= sizey - 1 || game_objects[k].x <= 0 || game_objects[k].x >= sizex - 1 {
-                ttype = map_wall;
+            let ttype = if coord.y <= 0 || coord.y >= sizey - 1 || coord.x <= 0 || coord.x >= sizex - 1 {
+                map_wall
             } else {
-                ttype = v_regions[closest].c;
-            }
-            let t = Tile {y: game_objects[k].y, x: game_objects[k].x, c: ttype, neighbors: Vec::new()};
-            // Modifying game_objects tiles is a pain, so we make the changes to a mirror data structure called go
-            go.insert(k.to_string(), t);
+                v_regions[closest].c
+            };
+            let mut cell = Cell::new(ttype);
+            cell.biome = v_regions[closest].biome;
+            grid.set(coord, Some(cell));
+        }
+        // Metadata to save in case another programs needs to know the map size, palette or regions
+        let mut metadata = MapMetadata::new(sizey, sizex, Palette::new(map_wall, map_floor, map_player));
+        for v in 0..number_of_regions {
+            metadata.region_seeds.push(RegionSeed::from_tile(&go[&(String::from("v") + &v.to_string())]));
+        }
+        // The spawn region is kept last like the others, with its biome
+        if let Some(spawn) = v_regions.last() {
+            metadata.region_seeds.push(RegionSeed::from_tile(spawn));
         }
+        metadata.generator = Some(metadata::Generator::current());
+        metadata.created = Some(metadata::now());
+        metadata.modified = metadata.created;
         // return the modified map data structure
-        //go
-        let map: Map = Map {
-            map_wall: '#',
-            map_floor: '.',
-            map_player: 'p',
-            map_game_objects: go
+        let mut map: Map = Map {
+            map_metadata: metadata,
+            map_grid: grid,
+            map_entities: Vec::new(),
+            map_objects: HashMap::new()
         };
+        map.place_exit(options.exit_percentile);
+        map.place_entities(&options.entities);
+        analysis::label_regions(&mut map);
         map
     }
-    // Opens a file for reading to decompress, deserialize, and store as hashmap
-    pub fn load_map(filename: &str, compression: bool) -> Map {
-        let mut f = File::open(filename).expect("Unable to open file");
-        let mut s = String::new();
-        if compression {
-            s = Map::decompress(&f);
-        } else {
-            f.read_to_string(&mut s).unwrap();
-        }
-        //GzDecoder::new(f).read_to_string(&mut s).unwrap();
-        let game_objects: HashMap<String, Tile> = serde_json::from_str(&s).unwrap();
-        let map: Map = Map {
-            map_wall: '#',
-            map_floor: '.',
-            map_player: 'p',
-            map_game_objects: game_objects
+    // Number of tiles in a map size read from a file, sizes that are negative or over MAX_TILES are schema errors
+    pub fn check_size(sizey: i64, sizex: i64) -> Result<usize, MapError> {
+        let fits = |size: i64| (0..=i32::MAX as i64).contains(&size);
+        let tiles = if fits(sizey) && fits(sizex) { (sizey as usize).checked_mul(sizex as usize) } else { None };
+        match tiles {
+            Some(tiles) if tiles <= MAX_TILES => Ok(tiles),
+            _ => Err(MapError::Schema(format!("map size {}x{} is not between 0 and {} tiles", sizey, sizex, MAX_TILES)))
+        }
+    }
+    // Stamp the map as changed now, save::save_map does this for every file it writes
+    pub fn mark_modified(&mut self) {
+        self.map_metadata.modified = Some(metadata::now());
+    }
+    // Map with no tiles yet, for importers to fill in
+    pub fn empty(sizey: i32, sizex: i32, palette: Palette) -> Map {
+        let mut map_metadata = MapMetadata::new(sizey, sizex, palette);
+        map_metadata.created = Some(metadata::now());
+        map_metadata.modified = map_metadata.created;
+        Map {
+            map_metadata,
+            map_grid: Grid::new(sizey, sizex, None),
+            map_entities: Vec::new(),
+            map_objects: HashMap::new()
+        }
+    }
+    // Build a map from the legacy json layout, where terrain and metadata share one hashmap
+    // Terrain tiles ("YxX" keys inside mapsize) go in the grid, known metadata keys are moved into
+    // MapMetadata and anything left over stays a keyed object
+    pub fn from_legacy(game_objects: HashMap<String, Tile>) -> Result<Map, MapError> {
+        let (sizey, sizex) = match game_objects.get("mapsize") {
+            Some(mapsize) => (mapsize.y as i64, mapsize.x as i64),
+            // No mapsize, so make the grid just big enough for every terrain key
+            None => game_objects.keys().filter_map(|k| Coord::from_key(k)).fold((0, 0), |(h, w), c| (h.max(c.y as i64 + 1), w.max(c.x as i64 + 1)))
         };
-        map
+        Map::check_size(sizey, sizex)?;
+        let (sizey, sizex) = (sizey as i32, sizex as i32);
+        let mut grid: Grid<Option<Cell>> = Grid::new(sizey, sizex, None);
+        let mut rest = HashMap::new();
+        for (key, tile) in game_objects {
+            match Map::terrain_cell(&key, &tile) {
+                Some((coord, cell)) if grid.contains(coord) => {
+                    grid.set(coord, Some(cell));
+                },
+                _ => {
+                    rest.insert(key, tile);
+                }
+            }
+        }
+        Ok(Map::from_legacy_grid(rest, grid))
+    }
+    // The grid cell a legacy key holds, if it's a terrain tile
+    pub fn terrain_cell(key: &str, tile: &Tile) -> Option<(Coord, Cell)> {
+        match Coord::from_key(key) {
+            Some(coord) if coord == Coord::new(tile.y, tile.x) && Cell::fits(tile) => Some((coord, Cell::from_tile(tile))),
+            _ => None
+        }
     }
-    // Serialize hashmap into string, open a file for writing, write to file with compressed bufwriter
-    pub fn save_map (filename: &str, map: &Map, compression: bool) {
-        let serialized = serde_json::to_string(&map.map_game_objects).unwrap();
-        let f = File::create(filename).expect("Unable to create file");
-        let enc: flate2::write::GzEncoder<std::fs::File>;
-        // if compression enabled, gzip here
-        if compression {
-            enc = Map::compress(f);
-            let mut buf = BufWriter::new(enc);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+    // The rest of from_legacy, for a grid already filled with the terrain tiles that fit in it
+    pub fn from_legacy_grid(mut game_objects: HashMap<String, Tile>, grid: Grid<Option<Cell>>) -> Map {
+        let mut metadata = MapMetadata::new(grid.height(), grid.width(), Palette::default());
+        // Only take metadata keys that look exactly like the ones to_legacy writes back
+        if game_objects.get("mapsize").is_some_and(|t| t.c == '$' && Cell::fits(t)) {
+            game_objects.remove("mapsize");
+        }
+        if game_objects.get("player").is_some_and(|t| t.y == 0 && t.x == 0 && Cell::fits(t)) {
+            metadata.palette.player = game_objects.remove("player").unwrap().c;
+        }
+        if game_objects.get("exit").is_some_and(Cell::fits) {
+            let exit = game_objects.remove("exit").unwrap();
+            metadata.exit = Some(Coord::new(exit.y, exit.x));
+            metadata.palette.exit = exit.c;
+        }
+        while let Some(seed) = game_objects.remove(&(String::from("v") + &metadata.region_seeds.len().to_string())) {
+            metadata.region_seeds.push(RegionSeed::from_tile(&seed));
+        }
+        while let Some(info) = game_objects.get(&analysis::region_key(metadata.regions.len())).and_then(|t| t.region_info.clone()) {
+            game_objects.remove(&analysis::region_key(info.id));
+            metadata.regions.push(info);
+        }
+        let mut entities = Vec::new();
+        while let Some(entity) = game_objects.get(&Map::entity_key(entities.len())).and_then(|t| t.entity.clone()) {
+            let tile = game_objects.remove(&Map::entity_key(entities.len())).unwrap();
+            entities.push(PlacedEntity { y: tile.y, x: tile.x, c: tile.c, entity });
+        }
+        Map {
+            map_metadata: metadata,
+            map_grid: grid,
+            map_entities: entities,
+            map_objects: game_objects
+        }
+    }
+    // Legacy json layout, the same hashmap from_legacy was given (or python would write)
+    pub fn to_legacy(&self) -> HashMap<String, Tile> {
+        let metadata = &self.map_metadata;
+        let mut game_objects = HashMap::new();
+        game_objects.insert(String::from("mapsize"), Tile::new(self.map_grid.height(), self.map_grid.width(), '$', Vec::new()));
+        game_objects.insert(String::from("player"), Tile::new(0, 0, metadata.palette.player, Vec::new()));
+        if let Some(exit) = metadata.exit {
+            game_objects.insert(String::from("exit"), Tile::new(exit.y, exit.x, metadata.palette.exit, Vec::new()));
+        }
+        for (v, seed) in metadata.region_seeds.iter().enumerate() {
+            game_objects.insert(String::from("v") + &v.to_string(), seed.to_tile());
+        }
+        for region in metadata.regions.iter() {
+            let mut tile = Tile::new(region.centroid_y.round() as i32, region.centroid_x.round() as i32, metadata.palette.floor, Vec::new());
+            tile.region = Some(region.id);
+            tile.region_info = Some(region.clone());
+            game_objects.insert(analysis::region_key(region.id), tile);
+        }
+        for (e, placed) in self.map_entities.iter().enumerate() {
+            let mut tile = Tile::new(placed.y, placed.x, placed.c, Vec::new());
+            tile.entity = Some(placed.entity.clone());
+            game_objects.insert(Map::entity_key(e), tile);
+        }
+        for (coord, cell) in self.map_grid.iter() {
+            if let Some(cell) = cell {
+                game_objects.insert(coord.key(), cell.to_tile(coord));
+            }
+        }
+        // Keys from_legacy didn't understand go back exactly as they were
+        for (key, tile) in self.map_objects.iter() {
+            game_objects.insert(key.clone(), tile.clone());
+        }
+        game_objects
+    }
+    // Where a legacy key goes in a saved file: metadata first, then v#, r# and e# by number, terrain in row major
+    // order and anything else by name
+    // The key itself breaks ties, "v2" and "v02" have the same number but must still always come out in one order
+    pub fn legacy_key_order(key: &str) -> (u8, i64, i64, &str) {
+        let number = |prefix: char| if key.starts_with(prefix) { key[1..].parse::<i64>().ok() } else { None };
+        match key {
+            "mapsize" => (0, 0, 0, key),
+            "player" => (1, 0, 0, key),
+            "exit" => (2, 0, 0, key),
+            _ => match (number('v'), number('r'), number('e'), Coord::from_key(key)) {
+                (Some(n), _, _, _) => (3, n, 0, key),
+                (_, Some(n), _, _) => (4, n, 0, key),
+                (_, _, Some(n), _) => (5, n, 0, key),
+                (_, _, _, Some(coord)) if coord.key() == key => (6, coord.y as i64, coord.x as i64, key),
+                _ => (7, 0, 0, key)
+            }
+        }
+    }
+    // Legacy json with the keys in legacy_key_order, so saving the same map always gives the same bytes
+    pub fn legacy_json(game_objects: &HashMap<String, Tile>) -> String {
+        let mut keys: Vec<&String> = game_objects.keys().collect();
+        keys.sort_by_key(|k| Map::legacy_key_order(k));
+        let mut out = String::from("{");
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&serde_json::to_string(key).unwrap());
+            out.push(':');
+            out.push_str(&serde_json::to_string(&game_objects[*key]).unwrap());
+        }
+        out.push('}');
+        out
+    }
+    // Height and width of the terrain grid
+    pub fn mapsize(&self) -> (i32, i32) {
+        (self.map_grid.height(), self.map_grid.width())
+    }
+    // Terrain at a coordinate, None outside the map or where a map file was missing a tile
+    pub fn cell(&self, coord: Coord) -> Option<&Cell> {
+        self.map_grid.get(coord).and_then(|cell| cell.as_ref())
+    }
+    // Check if a coordinate is floor, anything outside the map is not
+    pub fn is_floor(&self, coord: Coord) -> bool {
+        match self.cell(coord) {
+            Some(cell) => cell.c == self.map_metadata.palette.floor,
+            None => false
+        }
+    }
+    // Index of the v-region seed closest to a coordinate, ties go to the earliest region
+    fn closest_region(seeds: &[Coord], coord: Coord) -> usize {
+        let mut closest: usize = 0;
+        let mut closest_distance = i32::MAX;
+        for (i, seed) in seeds.iter().enumerate() {
+            let distance = coord.manhattan(*seed);
+            if distance < closest_distance {
+                closest = i;
+                closest_distance = distance;
+            }
+        }
+        closest
+    }
+    // Coordinates of the v-region seeds, the last one is the spawn region gen_map adds
+    // Maps saved without it (older files, imports) get one at the spawn
+    fn region_seeds(&self) -> Vec<Coord> {
+        let mut seeds: Vec<Coord> = self.map_metadata.region_seeds.iter().map(|v| Coord::new(v.y, v.x)).collect();
+        if !seeds.contains(&self.map_metadata.spawn) {
+            seeds.push(self.map_metadata.spawn);
+        }
+        seeds
+    }
+    // Entities are stored in legacy json as e0, e1, e2...
+    pub fn entity_key(n: usize) -> String {
+        String::from("e") + &n.to_string()
+    }
+    // Add an entity on top of the terrain
+    pub fn add_entity(&mut self, coord: Coord, entity: Entity) {
+        self.map_entities.push(PlacedEntity { y: coord.y, x: coord.x, c: entity.kind.glyph(), entity });
+    }
+    // Walking distance from a coordinate to every reachable floor tile, moving the same 4 ways as the viewer
+    pub fn distance_field(&self, from: Coord) -> Grid<Option<i32>> {
+        let mut distances = Grid::new(self.map_grid.height(), self.map_grid.width(), None);
+        let mut queue = VecDeque::new();
+        distances.set(from, Some(0));
+        queue.push_back((from, 0));
+        while let Some((coord, d)) = queue.pop_front() {
+            for next in coord.neighbors().iter() {
+                if self.is_floor(*next) && distances.get(*next) == Some(&None) {
+                    distances.set(*next, Some(d + 1));
+                    queue.push_back((*next, d + 1));
+                }
+            }
+        }
+        distances
+    }
+    // Put the exit on the floor tile at this percentile of walking distance from spawn, never on the spawn itself
+    // A spawn with no floor to walk to gets no exit
+    pub fn place_exit(&mut self, percentile: f64) {
+        let distances = self.distance_field(self.map_metadata.spawn);
+        // Row major order from the grid, sorting by distance keeps ties in that order so the same map always gets the same exit
+        let mut reachable: Vec<(i32, Coord)> = distances.iter().filter_map(|(coord, d)| d.filter(|d| *d > 0).map(|d| (d, coord))).collect();
+        if reachable.is_empty() {
+            return;
+        }
+        reachable.sort_by_key(|(d, _)| *d);
+        let index = ((reachable.len() - 1) as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        self.map_metadata.exit = Some(reachable[index].1);
+    }
+    // Check if the exit is on a coordinate
+    pub fn is_exit(&self, coord: Coord) -> bool {
+        self.map_metadata.exit == Some(coord)
+    }
+    // Spread entities over the floor of each v-region, skipping tiles too close to spawn
+    pub fn place_entities(&mut self, rules: &EntityRules) {
+        if rules.kinds.is_empty() {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        let v_regions = self.region_seeds();
+        let spawn = self.map_metadata.spawn;
+        // Group the floor tiles that can hold an entity by v-region
+        let mut candidates: Vec<Vec<Coord>> = vec![Vec::new(); v_regions.len()];
+        for coord in self.map_grid.coords() {
+            if self.is_floor(coord) && coord.manhattan(spawn) >= rules.min_spawn_distance && !self.is_exit(coord) {
+                candidates[Map::closest_region(&v_regions, coord)].push(coord);
+            }
+        }
+        for region in candidates.iter_mut() {
+            // Round the expected count up or down at random so small regions still get their share
+            let expected = region.len() as f64 * rules.density;
+            let mut count = expected.floor() as usize;
+            if rng.gen::<f64>() < expected.fract() {
+                count += 1;
+            }
+            let count = count.min(region.len());
+            for _ in 0..count {
+                let coord = region.swap_remove(rng.gen_range(0, region.len()));
+                let kind = rules.kinds[rng.gen_range(0, rules.kinds.len())];
+                self.add_entity(coord, Entity::new(kind));
+            }
+        }
+    }
+    // Opens a file to decompress and deserialize, see stream::load_map
+    // The codec is found from the first bytes of the file
+    pub fn load_map(filename: &str) -> Result<Map, MapError> {
+        stream::load_map(filename, &mut |_| {})
+    }
+    // Map file contents, decompressed with whichever codec the first bytes say
+    pub fn read_map_file(filename: &str, compression: bool) -> Result<Vec<u8>, MapError> {
+        let mut buf = Vec::new();
+        File::open(filename)?.read_to_end(&mut buf)?;
+        match Codec::detect(&buf) {
+            Codec::None if compression => Err(MapError::Decompress(std::io::Error::new(std::io::ErrorKind::InvalidData, "file is not compressed with gzip, zstd or xz"))),
+            Codec::None => Ok(buf),
+            codec => codec.decompress(&buf).map_err(MapError::Decompress)
+        }
+    }
+    // Read any map format, binary files start with magic bytes and everything else is json
+    pub fn from_bytes(buf: &[u8]) -> Result<Map, MapError> {
+        if binary::is_binary(buf) {
+            binary::decode(buf)
         } else {
-            //enc = f;
-            let mut buf = BufWriter::new(f);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+            match std::str::from_utf8(buf) {
+                Ok(s) => Map::from_json(s),
+                Err(_) => Err(MapError::Parse(String::from("map file is not binary or utf-8 json")))
+            }
+        }
+    }
+    // Read either json layout, version 2 files have a metadata object with a version, legacy files get migrated
+    pub fn from_json(s: &str) -> Result<Map, MapError> {
+        let value: serde_json::Value = serde_json::from_str(s)?;
+        match value.get("metadata").and_then(|m| m.get("version")).and_then(|v| v.as_u64()) {
+            Some(version) => {
+                metadata::check_version(version)?;
+                let file: MapFile = serde_json::from_value(value)?;
+                file.into_map()
+            },
+            None => Map::from_legacy(serde_json::from_value(value)?)
+        }
+    }
+    // Serialize the map in one of the json layouts, binary isn't json so it gets the version 2 layout
+    // Both layouts are canonical: the same map always serializes to the same bytes, so map files diff well in git
+    pub fn to_json(&self, format: MapFormat) -> String {
+        match format {
+            MapFormat::Json | MapFormat::Binary => serde_json::to_string(&MapFile::from_map(self)).unwrap(),
+            MapFormat::Legacy => Map::legacy_json(&self.to_legacy())
+        }
+    }
+    // File contents for a map in any format
+    pub fn to_bytes(&self, format: MapFormat) -> Vec<u8> {
+        match format {
+            MapFormat::Binary => binary::encode(self),
+            _ => self.to_json(format).into_bytes()
         }
     }
+    // Save in the version 2 layout, the metadata is written as it is, save::save_map also sets the modified time
+    pub fn save_map (filename: &str, map: &Map, compression: bool) -> Result<(), MapError> {
+        Map::save_map_as(filename, map, compression, MapFormat::Json)
+    }
+    // Serialize map into string, open a file for writing, write to file with compressed bufwriter
+    // A file that's already there is replaced, the same as --force
+    pub fn save_map_as (filename: &str, map: &Map, compression: bool, format: MapFormat) -> Result<(), MapError> {
+        let codec = if compression { Codec::Gzip } else { Codec::None };
+        Map::save_map_with(filename, map, &SaveOptions { codec, format, force: true, backup: false })
+    }
+    // Save with any compression codec, load_map finds it again from the file
+    // The old file is replaced in one step once the new one is written, and only with force or backup
+    pub fn save_map_with(filename: &str, map: &Map, options: &SaveOptions) -> Result<(), MapError> {
+        save::save_file(filename, options.force, options.backup, |file| Map::write_map(file, map, options.codec, options.format))
+    }
+    // Write a map file to anything, like stdout
+    pub fn write_map<W: Write>(out: W, map: &Map, codec: Codec, format: MapFormat) -> Result<(), MapError> {
+        codec.write(out, &map.to_bytes(format))?;
+        Ok(())
+    }
     // Write wrapper to compress file, return encoder file
     pub fn compress(file: File) -> flate2::write::GzEncoder<std::fs::File>  {
         let enc = GzEncoder::new(file, Compression::default());
         enc
     }
     // Write wrapper to decompress file, return string
-    pub fn decompress(f: &std::fs::File) -> String{
+    pub fn decompress(f: &std::fs::File) -> Result<String, MapError> {
         let mut s = String::new();
-        GzDecoder::new(f).read_to_string(&mut s).unwrap();
-        s
+        GzDecoder::new(f).read_to_string(&mut s).map_err(MapError::Decompress)?;
+        Ok(s)
     }
 }
@@ -1,12 +1,14 @@
 extern crate maps;
 extern crate clap;
+extern crate serde_json;
 
 use clap::{Arg, App, SubCommand, AppSettings};
+use std::io::{IsTerminal, Write};
 
 // What's next:
 // Command line args (Done kinda)
-// Support stdout/stderr, there is not very much output, but currently might not work
-// Add some loading info or a timer?
+// Support stdout/stderr (Done, --file - for create and view)
+// Add some loading info or a timer? (Done, big maps show progress)
 // Test godot GDNative shared library
 
 // Initialize command line argument parser and run the program
@@ -17,13 +19,19 @@ fn main() {
                         .version("1.0")
                         .author("Oatley")
                         .about("Map generator and viewer")
+                        .arg(Arg::with_name("dir")
+                            .long("dir")
+                            .value_name("DIR")
+                            .help("Directory of map files named without a directory (default $MAPS_DIR or ./resources/maps)")
+                            .takes_value(true)
+                            .global(true))
                         .subcommand(SubCommand::with_name("create")
                                     .about("create new map")
                                     .arg(Arg::with_name("file")
                                         .short("f")
                                         .long("file")
                                         .value_name("FILE")
-                                        .help("Name of file to make")
+                                        .help("Name of file to make, - writes the map to stdout")
                                         .takes_value(true)
                                         .required(true))
                                     .arg(Arg::with_name("size")
@@ -36,30 +44,214 @@ fn main() {
                                     .arg(Arg::with_name("compress")
                                         .short("c")
                                         .long("compress")
-                                        .help("Compress output file with gzip")))
+                                        .value_name("CODEC")
+                                        .help("Compress output file with gzip (default), zstd or xz")
+                                        .possible_values(&["gzip", "zstd", "xz"])
+                                        .takes_value(true)
+                                        .min_values(0)
+                                        .require_equals(true))
+                                    .arg(Arg::with_name("format")
+                                        .long("format")
+                                        .value_name("FORMAT")
+                                        .help("File layout: json (default), legacy for the python curses-map-generator or bin for compact binary")
+                                        .possible_values(&["json", "legacy", "bin"])
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("force")
+                                        .long("force")
+                                        .help("Replace the map file if it's already there"))
+                                    .arg(Arg::with_name("backup")
+                                        .long("backup")
+                                        .help("Replace the map file if it's already there, keeping the old one as FILE.bak"))
+                                    .arg(Arg::with_name("exit")
+                                        .short("e")
+                                        .long("exit")
+                                        .value_name("PERCENTILE")
+                                        .help("Place exit at this percentile of distance from spawn (0-100, default 100)")
+                                        .takes_value(true)))
+                        .subcommand(SubCommand::with_name("analyze")
+                                    .about("print json report of chokepoints, dead ends and loops")
+                                    .arg(Arg::with_name("file")
+                                        .short("f")
+                                        .long("file")
+                                        .value_name("FILE")
+                                        .help("Name of file to analyze")
+                                        .takes_value(true)
+                                        .required(true)))
+                        .subcommand(SubCommand::with_name("check")
+                                    .about("print json report of structural problems in a map file")
+                                    .arg(Arg::with_name("file")
+                                        .short("f")
+                                        .long("file")
+                                        .value_name("FILE")
+                                        .help("Name of file to check")
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("allow-missing")
+                                        .long("allow-missing")
+                                        .help("Report missing tiles as warnings, for imported maps with holes")))
+                        .subcommand(SubCommand::with_name("export")
+                                    .about("write map to another file type")
+                                    .arg(Arg::with_name("file")
+                                        .short("f")
+                                        .long("file")
+                                        .value_name("FILE")
+                                        .help("Name of map to export")
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("to")
+                                        .short("t")
+                                        .long("to")
+                                        .value_name("TYPE")
+                                        .help("File type to write")
+                                        .possible_values(&["txt", "png", "tmj", "tmx", "tscn", "ldtk", "xp"])
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("tile-ids")
+                                        .long("tile-ids")
+                                        .value_name("IDS")
+                                        .help("Godot TileSet tile id of each map character for tscn, like '#=0,.=1' (default walls 0, floors 1)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("cell-size")
+                                        .long("cell-size")
+                                        .value_name("PIXELS")
+                                        .help("Cell size for tscn and ldtk (1-256, default 16)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("tileset")
+                                        .long("tileset")
+                                        .value_name("RES_PATH")
+                                        .help("Godot TileSet resource for tscn, like res://tileset.tres")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("scale")
+                                        .long("scale")
+                                        .value_name("PIXELS")
+                                        .help("Pixels per tile for png (1-64, default 8)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("force")
+                                        .long("force")
+                                        .help("Replace the exported file and tileset if they're already there"))
+                                    .arg(Arg::with_name("backup")
+                                        .long("backup")
+                                        .help("Replace the exported file and tileset if they're already there, keeping the old ones as PATH.bak"))
+                                    .arg(Arg::with_name("out")
+                                        .short("o")
+                                        .long("out")
+                                        .value_name("PATH")
+                                        .help("Path of file to write")
+                                        .takes_value(true)
+                                        .required(true)))
+                        .subcommand(SubCommand::with_name("import")
+                                    .about("create map from another file type")
+                                    .arg(Arg::with_name("in")
+                                        .short("i")
+                                        .long("in")
+                                        .value_name("PATH")
+                                        .help("Path of file to read")
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("from")
+                                        .long("from")
+                                        .value_name("TYPE")
+                                        .help("File type to read (default txt)")
+                                        .possible_values(&["txt", "png", "tmj", "tmx", "xp"])
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("legend")
+                                        .short("l")
+                                        .long("legend")
+                                        .value_name("LEGEND")
+                                        .help("Characters of a txt or xp file, like '#=wall,.=floor,p=spawn,>=exit' (roles: wall, floor, spawn, exit, monster, chest, npc, empty), or tile characters of png colors, like '000000=#,ffffff=.,0000ff= '")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("spawn-color")
+                                        .long("spawn-color")
+                                        .value_name("RRGGBB")
+                                        .help("Color marking the spawn in a png (default 00af5f, the viewer player color)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("scale")
+                                        .long("scale")
+                                        .value_name("PIXELS")
+                                        .help("Pixels per tile in a png (1-64, found from the image by default)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("file")
+                                        .short("f")
+                                        .long("file")
+                                        .value_name("FILE")
+                                        .help("Name of map to make")
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("compress")
+                                        .short("c")
+                                        .long("compress")
+                                        .value_name("CODEC")
+                                        .help("Compress output file with gzip (default), zstd or xz")
+                                        .possible_values(&["gzip", "zstd", "xz"])
+                                        .takes_value(true)
+                                        .min_values(0)
+                                        .require_equals(true))
+                                    .arg(Arg::with_name("format")
+                                        .long("format")
+                                        .value_name("FORMAT")
+                                        .help("File layout: json (default), legacy for the python curses-map-generator or bin for compact binary")
+                                        .possible_values(&["json", "legacy", "bin"])
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("force")
+                                        .long("force")
+                                        .help("Replace the map file if it's already there"))
+                                    .arg(Arg::with_name("backup")
+                                        .long("backup")
+                                        .help("Replace the map file if it's already there, keeping the old one as FILE.bak")))
+                        .subcommand(SubCommand::with_name("render")
+                                    .about("draw map as an svg image or html page")
+                                    .arg(Arg::with_name("file")
+                                        .short("f")
+                                        .long("file")
+                                        .value_name("FILE")
+                                        .help("Name of map to render")
+                                        .takes_value(true)
+                                        .required(true))
+                                    .arg(Arg::with_name("format")
+                                        .long("format")
+                                        .value_name("FORMAT")
+                                        .help("svg image (default) or html page with tile tooltips")
+                                        .possible_values(&["svg", "html"])
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("cell-size")
+                                        .long("cell-size")
+                                        .value_name("PIXELS")
+                                        .help("Pixels per tile for svg (1-256, default 8)")
+                                        .takes_value(true))
+                                    .arg(Arg::with_name("force")
+                                        .long("force")
+                                        .help("Replace the rendered file if it's already there"))
+                                    .arg(Arg::with_name("backup")
+                                        .long("backup")
+                                        .help("Replace the rendered file if it's already there, keeping the old one as PATH.bak"))
+                                    .arg(Arg::with_name("out")
+                                        .short("o")
+                                        .long("out")
+                                        .value_name("PATH")
+                                        .help("Path of file to write")
+                                        .takes_value(true)
+                                        .required(true)))
                         .subcommand(SubCommand::with_name("view")
                                     .about("preview map in curses")
                                     .arg(Arg::with_name("file")
                                         .short("f")
                                         .long("file")
                                         .value_name("FILE")
-                                        .help("Name of file to make")
+                                        .help("Name of file to view, - reads the map from stdin")
                                         .takes_value(true)))
                         .get_matches();
+    let dir = maps::Validation::map_dir(matches.value_of("dir"));
     // Create is used to create a new map and save it to a file
     if let Some(matches) = matches.subcommand_matches("create") {
         let mut file_path = String::new();
         let mut size = 0;
-        let compression: bool = matches.is_present("compress");
-        if matches.is_present("file") {
+        let codec = codec(matches);
+        let stdout = matches.value_of("file") == Some(maps::STDIO);
+        if matches.is_present("file") && !stdout {
             let file = matches.value_of("file").unwrap();
-            let validation = maps::Validation::new(&file);
-            if validation.file_exists {
-                println!("error: file {} already exists", &validation.file_path);
-                std::process::exit(1);
-            } else if ! validation.file_valid {
-                println!("error: file name must use only letters and numbers");
-                std::process::exit(1);
+            let validation = maps::Validation::in_dir(&dir, file);
+            if let Err(error) = validation.check_target(replaces(matches)) {
+                fail(error);
             }
             file_path = validation.file_path.clone();
         }
@@ -68,46 +260,352 @@ fn main() {
                 Ok(number) => {
                     size = number;
                     if size < 49 {
-                        println!("error: size must be integer 50 or larger");
+                        eprintln!("error: size must be integer 50 or larger");
                         std::process::exit(1);
                     }
                 },
                 Err(_error) => {
-                    println!("error: size must be integer 50 or larger");
+                    eprintln!("error: size must be integer 50 or larger");
                     std::process::exit(1);
                 },
             }
         }
+        let mut options = maps::MapOptions::new();
+        if matches.is_present("exit") {
+            match matches.value_of("exit").unwrap().trim().parse::<f64>() {
+                Ok(percentile) if (0.0..=100.0).contains(&percentile) => options.exit_percentile = percentile / 100.0,
+                _ => {
+                    eprintln!("error: exit must be a number from 0 to 100");
+                    std::process::exit(1);
+                }
+            }
+        }
+        let format = maps::MapFormat::from_name(matches.value_of("format").unwrap_or("json")).unwrap();
         // gen map with data recieved
-        let map = maps::Map::new(size, size, '#', '.', 'p');
-        maps::Map::save_map(&file_path, &map, compression);
+        let mut map = maps::Map::new_with_options(size, size, '#', '.', 'p', &options);
+        let saved = if stdout {
+            maps::Map::write_map(std::io::stdout().lock(), &map, codec, format)
+        } else {
+            maps::save::save_map(&file_path, &mut map, &save_options(matches, codec, format))
+        };
+        if let Err(error) = saved {
+            fail(error);
+        }
         std::process::exit(0);
     }
+    // Analyze prints a json layout report, so bad maps can be rejected without opening them
+    else if let Some(matches) = matches.subcommand_matches("analyze") {
+        let file_name = matches.value_of("file").unwrap();
+        let validation = maps::Validation::in_dir(&dir, file_name);
+        if let Err(error) = validation.check_existing() {
+            fail(error);
+        }
+        let map = load(&validation);
+        let report = maps::analysis::analyze(&map);
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    }
+    // Check prints a json report of what's broken in a map file, and fails if anything is
+    else if let Some(matches) = matches.subcommand_matches("check") {
+        let file_name = matches.value_of("file").unwrap();
+        let validation = maps::Validation::in_dir(&dir, file_name);
+        if let Err(error) = validation.check_existing() {
+            fail(error);
+        }
+        let mut report = maps::check::check_file(&validation.file_path).unwrap_or_else(|error| fail(error));
+        if matches.is_present("allow-missing") {
+            report = report.allow_missing();
+        }
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        if ! report.ok {
+            fail(maps::MapError::Validation(format!("{} has {} errors", file_name, report.errors)));
+        }
+    }
+    // Export writes a map as another type of file, for looking at maps outside the viewer
+    else if let Some(matches) = matches.subcommand_matches("export") {
+        let file_name = matches.value_of("file").unwrap();
+        let validation = maps::Validation::in_dir(&dir, file_name);
+        if let Err(error) = validation.check_existing() {
+            fail(error);
+        }
+        let map = load(&validation);
+        let out = matches.value_of("out").unwrap();
+        let mut scale = 8;
+        if matches.is_present("scale") {
+            match matches.value_of("scale").unwrap().trim().parse::<u32>() {
+                Ok(number) if (1..=maps::image::MAX_SCALE).contains(&number) => scale = number,
+                _ => {
+                    eprintln!("error: scale must be an integer from 1 to {}", maps::image::MAX_SCALE);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let mut cell_size = 16;
+        if matches.is_present("cell-size") {
+            match matches.value_of("cell-size").unwrap().trim().parse::<i32>() {
+                Ok(number) if (1..=maps::render::MAX_CELL_SIZE).contains(&number) => cell_size = number,
+                _ => {
+                    eprintln!("error: cell size must be an integer from 1 to {}", maps::render::MAX_CELL_SIZE);
+                    std::process::exit(1);
+                }
+            }
+        }
+        // Tiled maps need a tileset image next to them, named after the map
+        let tileset = std::path::Path::new(out).with_extension("tileset.png");
+        let tileset_name = tileset.file_name().unwrap().to_string_lossy().to_string();
+        let contents = match matches.value_of("to").unwrap() {
+            "txt" => maps::text::export(&map).into_bytes(),
+            "png" => maps::image::export_png(&map, scale).unwrap_or_else(|error| fail(error)),
+            "tmj" => maps::tiled::export_tmj(&map, &tileset_name).into_bytes(),
+            "tmx" => maps::tiled::export_tmx(&map, &tileset_name).into_bytes(),
+            "tscn" => {
+                let mut options = maps::godot::GodotOptions::new(map.map_metadata.palette.wall, map.map_metadata.palette.floor);
+                if let Some(spec) = matches.value_of("tile-ids") {
+                    match maps::godot::GodotOptions::parse_tile_ids(spec) {
+                        Ok(tile_ids) => options.tile_ids = tile_ids,
+                        Err(error) => {
+                            eprintln!("error: {}", error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                options.cell_size = cell_size;
+                options.tileset = matches.value_of("tileset").map(String::from);
+                maps::godot::export_tscn(&map, &options).into_bytes()
+            },
+            "ldtk" => maps::ldtk::export_ldtk(&map, cell_size).into_bytes(),
+            "xp" => maps::rexpaint::export_xp(&map),
+            other => {
+                eprintln!("error: can't export to {}", other);
+                std::process::exit(1);
+            }
+        };
+        let mut outputs = vec![(out.to_string(), contents)];
+        if matches.value_of("to") == Some("tmj") || matches.value_of("to") == Some("tmx") {
+            let png = maps::tiled::TiledMap::from_map(&map).tileset_png().unwrap_or_else(|error| fail(error));
+            outputs.push((tileset.to_string_lossy().to_string(), png));
+        }
+        // Nothing is written unless every file can be
+        if let Some((path, _)) = outputs.iter().find(|(path, _)| std::path::Path::new(path).exists() && !replaces(matches)) {
+            fail(maps::MapError::Validation(format!("file {} already exists, --force replaces it", path)));
+        }
+        for (path, contents) in outputs.iter() {
+            if let Err(error) = write_output(matches, path, contents) {
+                fail(error);
+            }
+        }
+    }
+    // Import turns a map drawn in another type of file into a map file
+    else if let Some(matches) = matches.subcommand_matches("import") {
+        let validation = maps::Validation::in_dir(&dir, matches.value_of("file").unwrap());
+        if let Err(error) = validation.check_target(replaces(matches)) {
+            fail(error);
+        }
+        let input = matches.value_of("in").unwrap();
+        let imported = match matches.value_of("from").unwrap_or("txt") {
+            "txt" | "xp" => {
+                let legend = match matches.value_of("legend").map(maps::text::Legend::parse) {
+                    Some(Ok(legend)) => Some(legend),
+                    Some(Err(error)) => {
+                        eprintln!("error: {}", error);
+                        std::process::exit(1);
+                    },
+                    None => None
+                };
+                match read_input(input) {
+                    Ok(bytes) if matches.value_of("from") == Some("xp") => maps::rexpaint::import_xp(&bytes, legend.as_ref()),
+                    Ok(bytes) => match String::from_utf8(bytes) {
+                        Ok(text) => maps::text::import(&text, legend.as_ref()),
+                        Err(_) => Err(maps::MapError::Parse(format!("'{}' is not a utf-8 text file", input)))
+                    },
+                    Err(error) => Err(error)
+                }
+            },
+            "png" => {
+                let legend = match matches.value_of("legend").map(maps::image::ColorLegend::parse) {
+                    Some(Ok(legend)) => legend,
+                    Some(Err(error)) => {
+                        eprintln!("error: {}", error);
+                        std::process::exit(1);
+                    },
+                    None => maps::image::ColorLegend::default()
+                };
+                let spawn_color = match matches.value_of("spawn-color").map(maps::image::parse_color) {
+                    Some(Some(color)) => color,
+                    Some(None) => {
+                        eprintln!("error: spawn color must be RRGGBB hex");
+                        std::process::exit(1);
+                    },
+                    None => maps::image::xterm_rgb(maps::COLOR_PLAYER.0)
+                };
+                let mut scale = None;
+                if matches.is_present("scale") {
+                    match matches.value_of("scale").unwrap().trim().parse::<u32>() {
+                        Ok(number) if (1..=maps::image::MAX_SCALE).contains(&number) => scale = Some(number),
+                        _ => {
+                            eprintln!("error: scale must be an integer from 1 to {}", maps::image::MAX_SCALE);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                read_input(input).and_then(|bytes| maps::image::import_png(&bytes, &legend, spawn_color, scale))
+            },
+            "tmj" | "tmx" => match read_input(input).map(String::from_utf8) {
+                Ok(Ok(text)) if matches.value_of("from") == Some("tmj") => maps::tiled::import_tmj(&text),
+                Ok(Ok(text)) => maps::tiled::import_tmx(&text),
+                Ok(Err(_)) => Err(maps::MapError::Parse(format!("'{}' is not a utf-8 text file", input))),
+                Err(error) => Err(error)
+            },
+            other => {
+                eprintln!("error: can't import from {}", other);
+                std::process::exit(1);
+            }
+        };
+        let mut map = match imported {
+            Ok(map) => map,
+            Err(error) => fail(error)
+        };
+        let format = maps::MapFormat::from_name(matches.value_of("format").unwrap_or("json")).unwrap();
+        if let Err(error) = maps::save::save_map(&validation.file_path, &mut map, &save_options(matches, codec(matches), format)) {
+            fail(error);
+        }
+    }
+    // Render draws a map for reading outside the terminal, in a browser or a document
+    else if let Some(matches) = matches.subcommand_matches("render") {
+        let file_name = matches.value_of("file").unwrap();
+        let validation = maps::Validation::in_dir(&dir, file_name);
+        if let Err(error) = validation.check_existing() {
+            fail(error);
+        }
+        let map = load(&validation);
+        let mut cell_size = 8;
+        if matches.is_present("cell-size") {
+            match matches.value_of("cell-size").unwrap().trim().parse::<i32>() {
+                Ok(number) if (1..=maps::render::MAX_CELL_SIZE).contains(&number) => cell_size = number,
+                _ => {
+                    eprintln!("error: cell size must be an integer from 1 to {}", maps::render::MAX_CELL_SIZE);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let contents = match matches.value_of("format").unwrap_or("svg") {
+            "svg" => maps::render::export_svg(&map, cell_size),
+            "html" => maps::render::export_html(&map, file_name),
+            other => {
+                eprintln!("error: can't render {}", other);
+                std::process::exit(1);
+            }
+        };
+        if let Err(error) = write_output(matches, matches.value_of("out").unwrap(), contents.as_bytes()) {
+            fail(error);
+        }
+    }
     // View is used to view a previously generated map in a ncurses window viewer
     else if let Some(matches) = matches.subcommand_matches("view") {
         if matches.is_present("file") {
             let file_name = matches.value_of("file").unwrap();
-            let validation = maps::Validation::new(&file_name);
-            if validation.file_valid && validation.file_exists {
+            // Load before curses starts so errors print to a normal terminal
+            let map: maps::Map = if file_name == maps::STDIO {
+                load_stdin()
+            } else {
+                let validation = maps::Validation::in_dir(&dir, file_name);
+                if let Err(error) = validation.check_existing() {
+                    fail(error);
+                }
+                load(&validation)
+            };
+            // Keys can't come from stdin when the map did
+            if file_name == maps::STDIO {
+                if let Err(error) = maps::Curses::start_curses_tty() {
+                    eprintln!("error: could not open the terminal for the viewer: {}", error);
+                    std::process::exit(1);
+                }
+            } else {
                 maps::Curses::start_curses();
-                let map: maps::Map = maps::Map::load_map(&validation.file_path, validation.file_compressed.clone());
-                let curses_map: maps::CursesMap = maps::CursesMap::new(24, 70, map.map_wall, map.map_floor, map.map_player);
-                curses_map.play_map(&map.map_game_objects);
-            } else if ! validation.file_exists {
-                println!("error: file '{}' does not exist", &validation.file_path);
-                std::process::exit(1);
-            } else if ! validation.file_valid {
-                println!("error: file name must use only letters and numbers");
-                std::process::exit(1);
             }
+            let curses_map: maps::CursesMap = maps::CursesMap::new(24, 70, map.map_metadata.palette.wall, map.map_metadata.palette.floor, map.map_metadata.palette.player);
+            curses_map.play_map(&map);
         } else {
             maps::Curses::start_curses();
-            let validation: maps::Validation = maps::Curses::get_map_file_name();
-            let map = maps::Map::load_map(&validation.file_path, validation.file_compressed.clone());
-            let curses_map = maps::CursesMap::new(24, 70, map.map_wall, map.map_floor, map.map_player);
-            curses_map.play_map(&map.map_game_objects);
+            let validation: maps::Validation = maps::Curses::get_map_file_name(&dir);
+            let map = maps::Map::load_map(&validation.file_path).unwrap_or_else(|error| {
+                maps::Curses::end_curses();
+                fail(error)
+            });
+            let curses_map = maps::CursesMap::new(24, 70, map.map_metadata.palette.wall, map.map_metadata.palette.floor, map.map_metadata.palette.player);
+            curses_map.play_map(&map);
             maps::Curses::end_curses()
         }
     }
     std::process::exit(0);
 }
+
+// Compression for a command that writes a map, --compress on its own is gzip
+fn codec(matches: &clap::ArgMatches) -> maps::Codec {
+    if !matches.is_present("compress") {
+        return maps::Codec::None;
+    }
+    maps::Codec::from_name(matches.value_of("compress").unwrap_or("gzip")).unwrap()
+}
+
+// Contents of a file to import, the error names the file since it isn't a map file
+fn read_input(path: &str) -> Result<Vec<u8>, maps::MapError> {
+    std::fs::read(path).map_err(|error| maps::MapError::Io(std::io::Error::new(error.kind(), format!("could not read '{}': {}", path, error))))
+}
+
+// A command may write over a map that's already there, --backup keeps the old one so it replaces too
+fn replaces(matches: &clap::ArgMatches) -> bool {
+    matches.is_present("force") || matches.is_present("backup")
+}
+
+// How a command that writes a map saves it
+fn save_options(matches: &clap::ArgMatches, codec: maps::Codec, format: maps::MapFormat) -> maps::SaveOptions {
+    maps::SaveOptions {
+        codec,
+        format,
+        force: matches.is_present("force"),
+        backup: matches.is_present("backup")
+    }
+}
+
+// Write a file that isn't a map, like an export, replacing one that's already there the same way as a map
+fn write_output(matches: &clap::ArgMatches, path: &str, contents: &[u8]) -> Result<(), maps::MapError> {
+    maps::save::save_file(path, matches.is_present("force"), matches.is_present("backup"), |file| {
+        file.write_all(contents).map_err(|error| maps::MapError::Io(std::io::Error::new(error.kind(), format!("could not write '{}': {}", path, error))))
+    })
+}
+
+// Load a map for a command, big maps take a while so progress is shown when stderr is a terminal
+fn load(validation: &maps::Validation) -> maps::Map {
+    let terminal = std::io::stderr().is_terminal();
+    let mut shown = None;
+    let mut progress = |progress: maps::stream::Progress| {
+        // Maps too small for a report before the end load too fast to need one
+        if !terminal || (shown.is_none() && progress.tiles < maps::stream::REPORT_EVERY) {
+            return;
+        }
+        let percent = progress.fraction().map(|f| (f * 100.0) as u32);
+        if percent != shown {
+            eprint!("\rloading {}: {}%", validation.file_name, percent.unwrap_or(0));
+            shown = percent;
+        }
+        if progress.done {
+            eprintln!();
+        }
+    };
+    maps::stream::load_map(&validation.file_path, &mut progress).unwrap_or_else(|error| fail(error))
+}
+
+// Load a map piped to stdin, whatever its format or compression
+fn load_stdin() -> maps::Map {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        fail(maps::MapError::Validation(String::from("no map on stdin, pipe one in like: maps create --file - --size 50 | maps view --file -")));
+    }
+    maps::stream::read_map(stdin.lock(), None, &mut |_| {}).unwrap_or_else(|error| fail(error))
+}
+
+// Print a map error to stderr and exit with its code
+fn fail(error: maps::MapError) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(error.exit_code());
+}
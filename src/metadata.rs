@@ -0,0 +1,240 @@
+// Typed map metadata and the version 2 file schema, where metadata is kept apart from the terrain
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Serializer, Deserialize};
+use super::analysis::Region;
+use super::{Biome, Cell, Coord, Grid, Map, MapError, PlacedEntity, Tile, SPAWN_Y, SPAWN_X};
+
+// Version written by this program, the legacy layout shared with the python generator is version 1
+pub const FORMAT_VERSION: u32 = 2;
+
+// Characters used to draw each kind of tile
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub wall: char,
+    pub floor: char,
+    pub player: char,
+    pub exit: char
+}
+
+// Voronoi region seed from map generation, the v# keys in legacy maps
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegionSeed {
+    pub y: i32,
+    pub x: i32,
+    pub c: char,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biome: Option<Biome>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub neighbors: Vec<String>
+}
+
+// Program that generated the map
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Generator {
+    pub name: String,
+    pub version: String
+}
+
+// Everything about a map that isn't terrain or entities
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapMetadata {
+    pub version: u32,
+    pub height: i32,
+    pub width: i32,
+    pub spawn: Coord,
+    pub palette: Palette,
+    #[serde(default)]
+    pub region_seeds: Vec<RegionSeed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit: Option<Coord>,
+    #[serde(default)]
+    pub regions: Vec<Region>, // room and corridor table, see analysis::label_regions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<Generator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>, // seconds since unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>
+}
+
+// Version 2 map file
+// Terrain is one string per row, a space is a missing tile. Biomes use one letter per tile the same way
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MapFile {
+    pub metadata: MapMetadata,
+    pub terrain: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub biomes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tile_regions: Vec<Vec<Option<usize>>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<PlacedEntity>,
+    // Legacy keys this program doesn't understand, kept so nothing is lost
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted_objects")]
+    pub objects: HashMap<String, Tile>
+}
+
+// Objects are written sorted by key, so saving the same map twice gives the same file
+fn sorted_objects<S: Serializer>(objects: &HashMap<String, Tile>, serializer: S) -> Result<S::Ok, S::Error> {
+    objects.iter().collect::<BTreeMap<&String, &Tile>>().serialize(serializer)
+}
+
+impl Palette {
+    pub fn new(wall: char, floor: char, player: char) -> Palette {
+        Palette { wall, floor, player, exit: '>' }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new('#', '.', 'p')
+    }
+}
+
+impl RegionSeed {
+    pub fn from_tile(tile: &Tile) -> RegionSeed {
+        RegionSeed { y: tile.y, x: tile.x, c: tile.c, biome: tile.biome, neighbors: tile.neighbors.clone() }
+    }
+    // The v# tile written to legacy json
+    pub fn to_tile(&self) -> Tile {
+        let mut tile = Tile::new(self.y, self.x, self.c, self.neighbors.clone());
+        tile.biome = self.biome;
+        tile
+    }
+}
+
+impl Generator {
+    // This program
+    pub fn current() -> Generator {
+        Generator {
+            name: String::from("maps"),
+            version: String::from(env!("CARGO_PKG_VERSION"))
+        }
+    }
+}
+
+impl MapMetadata {
+    pub fn new(height: i32, width: i32, palette: Palette) -> MapMetadata {
+        MapMetadata {
+            version: FORMAT_VERSION,
+            height,
+            width,
+            spawn: Coord::new(SPAWN_Y, SPAWN_X),
+            palette,
+            region_seeds: Vec::new(),
+            exit: None,
+            regions: Vec::new(),
+            generator: None,
+            created: None,
+            modified: None
+        }
+    }
+}
+
+// Files from a newer version of this program can't be read safely
+pub fn check_version(version: u64) -> Result<(), MapError> {
+    if version > FORMAT_VERSION as u64 {
+        return Err(MapError::Schema(format!("map file version {} is newer than this program supports ({})", version, FORMAT_VERSION)));
+    }
+    Ok(())
+}
+
+// Seconds since unix epoch, for the created and modified timestamps
+pub fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_error) => 0
+    }
+}
+
+impl MapFile {
+    pub fn from_map(map: &Map) -> MapFile {
+        let mut metadata = map.map_metadata.clone();
+        metadata.version = FORMAT_VERSION;
+        metadata.height = map.map_grid.height();
+        metadata.width = map.map_grid.width();
+        let mut terrain = Vec::new();
+        let mut biomes = Vec::new();
+        let mut tile_regions = Vec::new();
+        let mut has_biomes = false;
+        let mut has_regions = false;
+        for y in 0..map.map_grid.height() {
+            let mut row = String::new();
+            let mut biome_row = String::new();
+            let mut region_row = Vec::new();
+            for x in 0..map.map_grid.width() {
+                let cell = map.cell(Coord::new(y, x));
+                row.push(cell.map(|c| c.c).unwrap_or(' '));
+                let biome = cell.and_then(|c| c.biome);
+                has_biomes = has_biomes || biome.is_some();
+                biome_row.push(biome.map(|b| b.letter()).unwrap_or(' '));
+                let region = cell.and_then(|c| c.region).map(|r| r as usize);
+                has_regions = has_regions || region.is_some();
+                region_row.push(region);
+            }
+            terrain.push(row);
+            biomes.push(biome_row);
+            tile_regions.push(region_row);
+        }
+        if !has_biomes {
+            biomes.clear();
+        }
+        if !has_regions {
+            tile_regions.clear();
+        }
+        MapFile {
+            metadata,
+            terrain,
+            biomes,
+            tile_regions,
+            entities: map.map_entities.clone(),
+            objects: map.map_objects.clone()
+        }
+    }
+
+    // Rows longer than the metadata width, or past its height, are left out of the grid
+    pub fn into_map(self) -> Result<Map, MapError> {
+        Map::check_size(self.metadata.height as i64, self.metadata.width as i64)?;
+        let mut grid: Grid<Option<Cell>> = Grid::new(self.metadata.height, self.metadata.width, None);
+        for (y, row) in self.terrain.iter().enumerate() {
+            MapFile::set_terrain_row(&mut grid, y, row);
+        }
+        for (y, row) in self.biomes.iter().enumerate() {
+            MapFile::set_biome_row(&mut grid, y, row);
+        }
+        for (y, row) in self.tile_regions.iter().enumerate() {
+            MapFile::set_region_row(&mut grid, y, row);
+        }
+        Ok(Map {
+            map_metadata: self.metadata,
+            map_grid: grid,
+            map_entities: self.entities,
+            map_objects: self.objects
+        })
+    }
+
+    // One row of each layer, the streaming loader fills the grid with these as the rows are read
+    // Biomes and regions only go on tiles the terrain has already set
+    pub fn set_terrain_row(grid: &mut Grid<Option<Cell>>, y: usize, row: &str) {
+        for (x, c) in row.chars().enumerate() {
+            if c != ' ' {
+                grid.set(Coord::new(y as i32, x as i32), Some(Cell::new(c)));
+            }
+        }
+    }
+    pub fn set_biome_row(grid: &mut Grid<Option<Cell>>, y: usize, row: &str) {
+        for (x, letter) in row.chars().enumerate() {
+            if let Some(Some(cell)) = grid.get_mut(Coord::new(y as i32, x as i32)) {
+                cell.biome = Biome::from_letter(letter);
+            }
+        }
+    }
+    pub fn set_region_row(grid: &mut Grid<Option<Cell>>, y: usize, row: &[Option<usize>]) {
+        for (x, region) in row.iter().enumerate() {
+            if let Some(Some(cell)) = grid.get_mut(Coord::new(y as i32, x as i32)) {
+                cell.region = region.filter(|r| *r <= u32::MAX as usize).map(|r| r as u32);
+            }
+        }
+    }
+}
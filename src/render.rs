@@ -0,0 +1,166 @@
+// Maps as svg images and html pages, for putting maps in design docs and pull requests
+//
+// The svg is drawn like the png export, one square per tile in the viewer colors. The html page is the map as colored
+// characters, hovering a tile shows its key and what is on it
+use super::{Coord, Grid, Map, PlacedEntity};
+use super::image::{tile_color, tile_pair, xterm_rgb};
+use super::{COLOR_WALL, COLOR_FLOOR, COLOR_PLAYER, COLOR_ENTITY, COLOR_EXIT, COLOR_FOREST, COLOR_DESERT, COLOR_SWAMP, COLOR_TUNDRA, COLOR_UNKNOWN};
+
+// Largest pixels per tile of an svg, and of the cells in the tscn and ldtk exports
+pub const MAX_CELL_SIZE: i32 = 256;
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Svg image with cell_size pixels per tile, layered like the viewer: terrain, exit, entities then the player at spawn
+// Sizes outside 1 to MAX_CELL_SIZE are clamped, pixel positions are i64 so a long map doesn't overflow
+pub fn export_svg(map: &Map, cell_size: i32) -> String {
+    let (sizey, sizex) = map.mapsize();
+    let palette = map.map_metadata.palette;
+    let cell_size = cell_size.clamp(1, MAX_CELL_SIZE) as i64;
+    let (height, width) = (sizey as i64 * cell_size, sizex as i64 * cell_size);
+    let mut out = String::new();
+    out.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n", width, height, width, height));
+    out.push_str("<rect width=\"100%\" height=\"100%\" fill=\"#000000\"/>\n");
+    let rect = |out: &mut String, coord: Coord, length: i32, color: [u8; 3]| {
+        out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", coord.x as i64 * cell_size, coord.y as i64 * cell_size, length as i64 * cell_size, cell_size, hex(color)));
+    };
+    // Runs of same colored tiles in a row are one rect, which keeps big maps small
+    for y in 0..sizey {
+        let mut run: Option<(Coord, i32, [u8; 3])> = None;
+        for x in 0..sizex {
+            let color = map.cell(Coord::new(y, x)).map(|cell| tile_color(cell, &palette));
+            run = match (run, color) {
+                (Some((start, length, run_color)), Some(color)) if run_color == color => Some((start, length + 1, run_color)),
+                (previous, color) => {
+                    if let Some((start, length, run_color)) = previous {
+                        rect(&mut out, start, length, run_color);
+                    }
+                    color.map(|color| (Coord::new(y, x), 1, color))
+                }
+            };
+        }
+        if let Some((start, length, run_color)) = run {
+            rect(&mut out, start, length, run_color);
+        }
+    }
+    if let Some(exit) = map.map_metadata.exit {
+        rect(&mut out, exit, 1, xterm_rgb(COLOR_EXIT.0));
+    }
+    for entity in map.map_entities.iter() {
+        rect(&mut out, Coord::new(entity.y, entity.x), 1, xterm_rgb(COLOR_ENTITY.0));
+    }
+    rect(&mut out, map.map_metadata.spawn, 1, xterm_rgb(COLOR_PLAYER.0));
+    out.push_str("</svg>\n");
+    out
+}
+
+// Css class of each viewer color pair
+fn pair_class(pair: (i16, i16)) -> &'static str {
+    match pair {
+        COLOR_WALL => "wall",
+        COLOR_FOREST => "forest",
+        COLOR_DESERT => "desert",
+        COLOR_SWAMP => "swamp",
+        COLOR_TUNDRA => "tundra",
+        COLOR_ENTITY => "entity",
+        COLOR_EXIT => "exit",
+        COLOR_PLAYER => "player",
+        COLOR_UNKNOWN => "unknown",
+        _ => "floor"
+    }
+}
+
+// Entities standing on each tile in the order they were placed, found once instead of searching the list per tile
+pub fn entity_index(map: &Map) -> Grid<Vec<&PlacedEntity>> {
+    let (sizey, sizex) = map.mapsize();
+    let mut index: Grid<Vec<&PlacedEntity>> = Grid::new(sizey, sizex, Vec::new());
+    for entity in map.map_entities.iter() {
+        if let Some(here) = index.get_mut(Coord::new(entity.y, entity.x)) {
+            here.push(entity);
+        }
+    }
+    index
+}
+
+// Tooltip of a tile: its key, the terrain and anything standing on it
+pub fn describe(map: &Map, coord: Coord) -> String {
+    let entities: Vec<&PlacedEntity> = map.map_entities.iter().filter(|e| e.y == coord.y && e.x == coord.x).collect();
+    describe_tile(map, coord, &entities)
+}
+
+// describe with the entities on the tile already found
+fn describe_tile(map: &Map, coord: Coord, entities: &[&PlacedEntity]) -> String {
+    let palette = map.map_metadata.palette;
+    let mut parts = vec![coord.key()];
+    match map.cell(coord) {
+        Some(cell) if cell.c == palette.wall => parts.push(String::from("wall")),
+        Some(cell) if cell.c == palette.floor => parts.push(String::from("floor")),
+        Some(cell) => parts.push(format!("'{}'", cell.c)),
+        None => parts.push(String::from("empty"))
+    }
+    if let Some(cell) = map.cell(coord) {
+        if let Some(biome) = cell.biome {
+            parts.push(format!("{:?}", biome).to_lowercase());
+        }
+        if let Some(region) = cell.region {
+            parts.push(format!("region {}", region));
+        }
+    }
+    if map.map_metadata.spawn == coord {
+        parts.push(String::from("spawn"));
+    }
+    if map.map_metadata.exit == Some(coord) {
+        parts.push(String::from("exit"));
+    }
+    for entity in entities.iter() {
+        parts.push(format!("{:?}", entity.entity.kind).to_lowercase());
+    }
+    parts.join(" ")
+}
+
+// Self contained html page with the map as a grid of colored characters and a tooltip on every tile
+pub fn export_html(map: &Map, title: &str) -> String {
+    let (sizey, sizex) = map.mapsize();
+    let palette = map.map_metadata.palette;
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n<style>\n", escape(title)));
+    out.push_str("body { background: #000000; color: #c0c0c0; font-family: monospace; }\n");
+    out.push_str(".map { font-size: 14px; line-height: 1; }\n");
+    out.push_str(".map span:hover { outline: 1px solid #ffffff; }\n");
+    for pair in [COLOR_WALL, COLOR_FLOOR, COLOR_FOREST, COLOR_DESERT, COLOR_SWAMP, COLOR_TUNDRA, COLOR_ENTITY, COLOR_EXIT, COLOR_PLAYER, COLOR_UNKNOWN].iter() {
+        out.push_str(&format!(".{} {{ color: {}; background: {}; }}\n", pair_class(*pair), hex(xterm_rgb(pair.0)), hex(xterm_rgb(pair.1))));
+    }
+    out.push_str("</style>\n</head>\n<body>\n");
+    out.push_str(&format!("<h1>{}</h1>\n<pre class=\"map\">\n", escape(title)));
+    let index = entity_index(map);
+    for y in 0..sizey {
+        for x in 0..sizex {
+            let coord = Coord::new(y, x);
+            let entities = index.get(coord).map_or(&[][..], |here| here.as_slice());
+            // Markers are drawn over the terrain like the txt export does
+            let (c, pair) = if map.map_metadata.spawn == coord {
+                (palette.player, COLOR_PLAYER)
+            } else if map.map_metadata.exit == Some(coord) {
+                (palette.exit, COLOR_EXIT)
+            } else if let Some(entity) = entities.first() {
+                (entity.c, COLOR_ENTITY)
+            } else {
+                match map.cell(coord) {
+                    Some(cell) => (cell.c, tile_pair(cell, &palette)),
+                    None => (' ', COLOR_FLOOR)
+                }
+            };
+            out.push_str(&format!("<span class=\"{}\" title=\"{}\">{}</span>", pair_class(pair), escape(&describe_tile(map, coord, entities)), escape(&c.to_string())));
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
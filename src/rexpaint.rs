@@ -0,0 +1,266 @@
+// REXPaint .xp images, a gzipped list of layers where every cell has a CP437 glyph and a foreground and background color
+//
+// Exported images have two layers: the terrain in the viewer colors, then the spawn, exit and entities drawn over it.
+// Imported images are read from the bottom layer up, glyphs become tiles through a text legend and floor colors
+// become biomes. An image holds at most MAX_TILES cells over all its layers, so maps up to half of MAX_TILES tiles
+// export and import again
+use std::io::{Read, Write};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use super::{Biome, Cell, Coord, Entity, Map, MapError, Palette, MAX_TILES};
+use super::analysis;
+use super::image::{tile_pair, xterm_rgb};
+use super::text::{Legend, TileRole};
+use super::{COLOR_PLAYER, COLOR_ENTITY, COLOR_EXIT, COLOR_FOREST, COLOR_DESERT, COLOR_SWAMP, COLOR_TUNDRA};
+
+// Version number REXPaint writes at the start of its files
+pub const XP_VERSION: i32 = -1;
+
+// Most layers REXPaint draws in one image
+pub const XP_MAX_LAYERS: usize = 9;
+
+// Most bytes read out of the gzip stream: the version and layer count, each layer's width and height, and 10 bytes
+// for each of MAX_TILES cells. Anything longer is turned down before it fills memory
+pub const XP_MAX_BYTES: u64 = 8 + 8 * XP_MAX_LAYERS as u64 + 10 * MAX_TILES as u64;
+
+// Background color REXPaint uses for cells with nothing drawn in them
+pub const TRANSPARENT: [u8; 3] = [255, 0, 255];
+
+// Code page 437 characters 128 to 255, 0 to 127 are the same as ascii
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XpCell {
+    pub glyph: u32,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3]
+}
+
+// Cells in row major order, .xp files store them column by column
+#[derive(Debug, Clone)]
+pub struct XpLayer {
+    pub width: i32,
+    pub height: i32,
+    pub cells: Vec<XpCell>
+}
+
+#[derive(Debug, Clone)]
+pub struct XpImage {
+    pub version: i32,
+    pub layers: Vec<XpLayer>
+}
+
+// Character of a CP437 glyph, glyph 0 is drawn as a space
+pub fn glyph_char(glyph: u32) -> Option<char> {
+    match glyph {
+        0 => Some(' '),
+        1..=127 => Some(glyph as u8 as char),
+        128..=255 => CP437_HIGH.chars().nth(glyph as usize - 128),
+        _ => None
+    }
+}
+
+// CP437 glyph of a character, characters not in the code page become '?'
+pub fn char_glyph(c: char) -> u32 {
+    if (c as u32) < 128 {
+        return c as u32;
+    }
+    match CP437_HIGH.chars().position(|h| h == c) {
+        Some(i) => 128 + i as u32,
+        None => '?' as u32
+    }
+}
+
+impl XpCell {
+    pub fn new(c: char, color: (i16, i16)) -> XpCell {
+        XpCell { glyph: char_glyph(c), fg: xterm_rgb(color.0), bg: xterm_rgb(color.1) }
+    }
+    pub fn transparent() -> XpCell {
+        XpCell { glyph: 0, fg: [0, 0, 0], bg: TRANSPARENT }
+    }
+    pub fn is_transparent(&self) -> bool {
+        self.bg == TRANSPARENT
+    }
+}
+
+impl XpLayer {
+    pub fn new(height: i32, width: i32) -> XpLayer {
+        XpLayer { width, height, cells: vec![XpCell::transparent(); (width.max(0) * height.max(0)) as usize] }
+    }
+    pub fn get(&self, coord: Coord) -> &XpCell {
+        &self.cells[(coord.y * self.width + coord.x) as usize]
+    }
+    pub fn set(&mut self, coord: Coord, cell: XpCell) {
+        if coord.y >= 0 && coord.x >= 0 && coord.y < self.height && coord.x < self.width {
+            self.cells[(coord.y * self.width + coord.x) as usize] = cell;
+        }
+    }
+}
+
+fn read_i32(bytes: &[u8], pos: &mut usize) -> Result<i32, MapError> {
+    match bytes.get(*pos..*pos + 4) {
+        Some(b) => {
+            *pos += 4;
+            Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        },
+        None => Err(MapError::Parse(String::from("xp file ends in the middle of the image")))
+    }
+}
+
+fn read_rgb(bytes: &[u8], pos: &mut usize) -> Result<[u8; 3], MapError> {
+    match bytes.get(*pos..*pos + 3) {
+        Some(b) => {
+            *pos += 3;
+            Ok([b[0], b[1], b[2]])
+        },
+        None => Err(MapError::Parse(String::from("xp file ends in the middle of the image")))
+    }
+}
+
+impl XpImage {
+    // Read gzipped .xp file contents
+    pub fn from_bytes(bytes: &[u8]) -> Result<XpImage, MapError> {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes).take(XP_MAX_BYTES + 1).read_to_end(&mut data).map_err(MapError::Decompress)?;
+        if data.len() as u64 > XP_MAX_BYTES {
+            return Err(MapError::Parse(format!("xp image is over {} bytes unpacked", XP_MAX_BYTES)));
+        }
+        let mut pos = 0;
+        let version = read_i32(&data, &mut pos)?;
+        let count = read_i32(&data, &mut pos)?;
+        if count < 1 || count as usize > XP_MAX_LAYERS {
+            return Err(MapError::Parse(format!("xp file has {} layers", count)));
+        }
+        let mut layers = Vec::new();
+        for _ in 0..count {
+            let width = read_i32(&data, &mut pos)?;
+            let height = read_i32(&data, &mut pos)?;
+            let size = (width as usize).checked_mul(height as usize).and_then(|cells| cells.checked_mul(10));
+            if width < 1 || height < 1 || size.is_none_or(|size| size > data.len() - pos) {
+                return Err(MapError::Parse(format!("xp layer size {}x{} doesn't fit in the file", width, height)));
+            }
+            let mut layer = XpLayer::new(height, width);
+            for x in 0..width {
+                for y in 0..height {
+                    let glyph = read_i32(&data, &mut pos)? as u32;
+                    let fg = read_rgb(&data, &mut pos)?;
+                    let bg = read_rgb(&data, &mut pos)?;
+                    layer.set(Coord::new(y, x), XpCell { glyph, fg, bg });
+                }
+            }
+            layers.push(layer);
+        }
+        Ok(XpImage { version, layers })
+    }
+    // Gzipped .xp file contents
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.extend_from_slice(&(self.layers.len() as i32).to_le_bytes());
+        for layer in self.layers.iter() {
+            data.extend_from_slice(&layer.width.to_le_bytes());
+            data.extend_from_slice(&layer.height.to_le_bytes());
+            for x in 0..layer.width {
+                for y in 0..layer.height {
+                    let cell = layer.get(Coord::new(y, x));
+                    data.extend_from_slice(&cell.glyph.to_le_bytes());
+                    data.extend_from_slice(&cell.fg);
+                    data.extend_from_slice(&cell.bg);
+                }
+            }
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    }
+}
+
+// Draw a map as a two layer REXPaint image
+pub fn export_xp(map: &Map) -> Vec<u8> {
+    let (sizey, sizex) = map.mapsize();
+    let palette = map.map_metadata.palette;
+    let mut terrain = XpLayer::new(sizey, sizex);
+    for (coord, cell) in map.map_grid.iter() {
+        if let Some(cell) = cell {
+            terrain.set(coord, XpCell::new(cell.c, tile_pair(cell, &palette)));
+        }
+    }
+    let mut markers = XpLayer::new(sizey, sizex);
+    if let Some(exit) = map.map_metadata.exit {
+        markers.set(exit, XpCell::new(palette.exit, COLOR_EXIT));
+    }
+    for entity in map.map_entities.iter() {
+        markers.set(Coord::new(entity.y, entity.x), XpCell::new(entity.c, COLOR_ENTITY));
+    }
+    markers.set(map.map_metadata.spawn, XpCell::new(palette.player, COLOR_PLAYER));
+    XpImage { version: XP_VERSION, layers: vec![terrain, markers] }.to_bytes()
+}
+
+// Biome drawn with a foreground color, floors in other colors get no biome
+fn color_biome(color: [u8; 3]) -> Option<Biome> {
+    let biomes = [(COLOR_FOREST, Biome::Forest), (COLOR_DESERT, Biome::Desert), (COLOR_SWAMP, Biome::Swamp), (COLOR_TUNDRA, Biome::Tundra)];
+    biomes.iter().find(|(pair, _)| xterm_rgb(pair.0) == color).map(|(_, biome)| *biome)
+}
+
+// Build a map from a REXPaint image with the standard palette, layers are read bottom up so higher layers draw over
+// lower ones, transparent cells and glyphs with the empty role leave the cell below alone
+// Without a legend the glyphs are read with the default #=wall,.=floor,p=spawn,>=exit
+pub fn import_xp(bytes: &[u8], legend: Option<&Legend>) -> Result<Map, MapError> {
+    let image = XpImage::from_bytes(bytes)?;
+    let default_legend = Legend::default();
+    let legend = legend.unwrap_or(&default_legend);
+    let (sizey, sizex) = (image.layers[0].height, image.layers[0].width);
+    if let Some(layer) = image.layers.iter().find(|l| l.height != sizey || l.width != sizex) {
+        return Err(MapError::Schema(format!("xp layers have different sizes, {}x{} and {}x{}", sizey, sizex, layer.height, layer.width)));
+    }
+    Map::check_size(sizey as i64, sizex as i64)?;
+    let palette = Palette::default();
+    let mut map = Map::empty(sizey, sizex, palette);
+    let mut spawn: Option<Coord> = None;
+    for (n, layer) in image.layers.iter().enumerate() {
+        for coord in map.map_grid.coords().collect::<Vec<Coord>>() {
+            let xp = layer.get(coord);
+            if xp.is_transparent() {
+                continue;
+            }
+            let c = match glyph_char(xp.glyph) {
+                Some(c) => c,
+                None => return Err(MapError::Schema(format!("glyph {} at {} in layer {} is not a CP437 character", xp.glyph, coord.key(), n)))
+            };
+            let role = match legend.role(c) {
+                Some(role) => role,
+                None => return Err(MapError::Schema(format!("glyph '{}' at {} in layer {} is not in the legend", c, coord.key(), n)))
+            };
+            match role {
+                TileRole::Empty => continue,
+                TileRole::Wall => { map.map_grid.set(coord, Some(Cell::new(palette.wall))); },
+                TileRole::Floor => {
+                    let mut cell = Cell::new(palette.floor);
+                    cell.biome = color_biome(xp.fg);
+                    map.map_grid.set(coord, Some(cell));
+                },
+                // Markers stand on floor, keep the floor below them if there is one
+                _ => if !map.is_floor(coord) {
+                    map.map_grid.set(coord, Some(Cell::new(palette.floor)));
+                }
+            }
+            match role {
+                TileRole::Spawn => {
+                    if let Some(first) = spawn {
+                        return Err(MapError::Schema(format!("xp image has more than one spawn, at {} and {}", first.key(), coord.key())));
+                    }
+                    spawn = Some(coord);
+                },
+                TileRole::Exit => map.map_metadata.exit = Some(coord),
+                TileRole::Entity(kind) => map.add_entity(coord, Entity::new(kind)),
+                _ => {}
+            }
+        }
+    }
+    if let Some(spawn) = spawn {
+        map.map_metadata.spawn = spawn;
+    }
+    analysis::label_regions(&mut map);
+    Ok(map)
+}
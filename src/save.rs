@@ -0,0 +1,128 @@
+// Saving map files so a save that stops part way never leaves a broken map
+//
+// The map is written to a temporary file in the same directory and synced to disk, then renamed over the map file.
+// A rename in one directory swaps the file in one step, so the map file is always the old map or the whole new one.
+// The previous version can be kept next to it as name.bak.
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::{Codec, Map, MapError, MapFormat};
+
+// Added to the name of a map file to name its backup
+pub const BACKUP_EXTENSION: &str = "bak";
+
+// Numbers temporary files, with the process id it keeps them apart between threads and processes
+static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// How save_map writes a map file, SaveOptions::new is an uncompressed version 2 file that won't replace another
+pub struct SaveOptions {
+    pub codec: Codec,
+    pub format: MapFormat,
+    pub force: bool, // replace a file that's already there
+    pub backup: bool // keep the file that's replaced as name.bak, replaces a file without force too
+}
+
+impl SaveOptions {
+    pub fn new() -> SaveOptions {
+        SaveOptions {
+            codec: Codec::None,
+            format: MapFormat::Json,
+            force: false,
+            backup: false
+        }
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> SaveOptions {
+        SaveOptions::new()
+    }
+}
+
+// Save a map, a file that's already there is only replaced with force or backup
+// The map's modified time is set to now, legacy files have nowhere to keep it
+pub fn save_map(filename: &str, map: &mut Map, options: &SaveOptions) -> Result<(), MapError> {
+    map.mark_modified();
+    Map::save_map_with(filename, map, options)
+}
+
+// Write any file a command makes, like an export or a render, with the same rules as a map file:
+// one that's already there is only replaced with force or backup, and a failed write leaves it alone
+pub fn save_file<F>(filename: &str, force: bool, backup: bool, write: F) -> Result<(), MapError>
+    where F: FnOnce(&mut File) -> Result<(), MapError> {
+    let path = Path::new(filename);
+    if path.exists() && !(force || backup) {
+        return Err(MapError::Validation(format!("file {} already exists", filename)));
+    }
+    write_file(path, backup, write)
+}
+
+// Name of the backup of a map file, cave.map.gz is kept as cave.map.gz.bak
+pub fn backup_path(filename: &str) -> String {
+    format!("{}.{}", filename, BACKUP_EXTENSION)
+}
+
+// Write a whole file or nothing, write gets the temporary file and the old file is only replaced if it succeeds
+pub fn write_file<F>(path: &Path, backup: bool, write: F) -> Result<(), MapError>
+    where F: FnOnce(&mut File) -> Result<(), MapError> {
+    let (temp_path, mut file) = temp_file(path)?;
+    let result = write(&mut file).and_then(|_| replace(path, &temp_path, file, backup).map_err(MapError::Io));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Move the finished temporary file over the map file
+fn replace(path: &Path, temp_path: &Path, file: File, backup: bool) -> io::Result<()> {
+    file.sync_all()?;
+    drop(file);
+    if let Ok(metadata) = fs::metadata(path) {
+        // A replaced map keeps its permissions
+        fs::set_permissions(temp_path, metadata.permissions())?;
+        if backup && metadata.is_file() {
+            keep_backup(path)?;
+        }
+    }
+    fs::rename(temp_path, path)?;
+    // The rename is only kept through a crash once the directory is synced, not every system can open one for it
+    if let Ok(dir) = File::open(parent(path)) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// The backup is a hard link so the map file is there the whole time, copied where links can't be made
+fn keep_backup(path: &Path) -> io::Result<()> {
+    let backup = PathBuf::from(backup_path(&path.to_string_lossy()));
+    match fs::remove_file(&backup) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+// A new hidden file next to the map, renames only work within one file system
+fn temp_file(path: &Path) -> io::Result<(PathBuf, File)> {
+    loop {
+        let count = TEMP_COUNT.fetch_add(1, Ordering::Relaxed);
+        let temp_path = parent(path).join(format!(".maps-{}-{}.tmp", std::process::id(), count));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            // Left behind by a process that had the same id, skip it
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error)
+        }
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    }
+}
@@ -0,0 +1,458 @@
+// Streaming map loader, json is decoded one key at a time straight from the file into the map
+//
+// Reading a whole map file into memory and parsing it into a hashmap of tiles takes several times the size of the
+// map, too much for 5000x5000 maps. This reads the (compressed) file once: legacy terrain tiles go into the grid as
+// soon as the mapsize key has been read (maps writes it first, python anywhere, tiles before it wait in a list) and
+// version 2 rows go into the grid one at a time once the metadata has been read (maps writes it first). Terrain rows
+// before the metadata are decoded into cells that become the grid when their size is known, biome and region rows
+// before the terrain wait as rows. Binary files are small enough to read whole.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::rc::Rc;
+use serde::Deserialize;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use super::{binary, compress, metadata, Biome, Codec, Cell, Coord, Grid, Map, MapError, MapFile, MapMetadata, PlacedEntity, Tile, MAX_TILES};
+
+// Tiles decoded between progress reports
+pub const REPORT_EVERY: usize = 65536;
+
+// How far a load has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub bytes: u64, // bytes of the file read so far, compressed bytes for compressed files
+    pub total: Option<u64>, // size of the file, when it's known
+    pub tiles: usize, // terrain tiles decoded so far
+    pub done: bool
+}
+
+impl Progress {
+    // Share of the file read, from 0 to 1
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.bytes as f64 / total as f64).min(1.0)),
+            None => None
+        }
+    }
+}
+
+// Open and load a map file in any format, the compression codec is found from the first bytes of the file
+pub fn load_map(filename: &str, progress: &mut dyn FnMut(Progress)) -> Result<Map, MapError> {
+    let file = File::open(filename)?;
+    let total = file.metadata().ok().map(|m| m.len());
+    read_map(file, total, progress)
+}
+
+// Load a map from anything readable, total is the number of bytes it will give if that's known
+pub fn read_map<R: Read>(reader: R, total: Option<u64>, progress: &mut dyn FnMut(Progress)) -> Result<Map, MapError> {
+    let bytes = Rc::new(std::cell::Cell::new(0));
+    let (reader, codec) = decompressed(Counting { inner: reader, bytes: bytes.clone() })?;
+    let mut loader = Loader::new(progress, bytes, total);
+    let map = decode(BufReader::new(reader), codec != Codec::None, &mut loader)?;
+    loader.report(true);
+    Ok(map)
+}
+
+// Contents of a map file as they're read, decompressed with the codec its first bytes say, for reading it
+// some other way than loading it
+pub fn open_map_file(filename: &str) -> Result<(Box<dyn Read>, Codec), MapError> {
+    decompressed(File::open(filename)?)
+}
+
+fn decompressed<'a, R: Read + 'a>(mut reader: R) -> Result<(Box<dyn Read + 'a>, Codec), MapError> {
+    let head = read_head(&mut reader, compress::MAGIC_LEN).map_err(MapError::Io)?;
+    let codec = Codec::detect(&head);
+    let reader = codec.reader(io::Cursor::new(head).chain(reader)).map_err(MapError::Decompress)?;
+    Ok((reader, codec))
+}
+
+// Up to the first count bytes, fewer only at the end of the file
+fn read_head<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(count);
+    reader.by_ref().take(count as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+fn decode<R: Read>(mut reader: R, compressed: bool, loader: &mut Loader) -> Result<Map, MapError> {
+    let read_error = |error: io::Error| if compressed { MapError::Decompress(error) } else { MapError::Io(error) };
+    let head = read_head(&mut reader, binary::MAGIC.len()).map_err(read_error)?;
+    if binary::is_binary(&head) {
+        let mut bytes = head;
+        reader.read_to_end(&mut bytes).map_err(read_error)?;
+        return binary::decode(&bytes);
+    }
+    let mut deserializer = serde_json::Deserializer::from_reader(io::Cursor::new(head).chain(reader));
+    let result = (&mut *loader).deserialize(&mut deserializer).and_then(|_| deserializer.end());
+    if let Some(error) = loader.error.take() {
+        return Err(error);
+    }
+    match result {
+        Ok(()) => loader.finish(),
+        Err(error) if error.is_io() => Err(read_error(error.into())),
+        Err(error) => Err(MapError::from(error))
+    }
+}
+
+// Counts bytes as they're read, for progress
+struct Counting<R> {
+    inner: R,
+    bytes: Rc<std::cell::Cell<u64>>
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+}
+
+// Version 2 fields, read as a legacy tile they would be an error instead
+const VERSION_2_FIELDS: [&str; 5] = ["terrain", "biomes", "tile_regions", "entities", "objects"];
+
+// Version 2 grid layers and the key each is written under
+const LAYERS: [(&str, Layer); 3] = [("terrain", Layer::Terrain), ("biomes", Layer::Biomes), ("tile_regions", Layer::Regions)];
+
+// The map being built while the json is read
+struct Loader<'p> {
+    progress: &'p mut dyn FnMut(Progress),
+    bytes: Rc<std::cell::Cell<u64>>,
+    total: Option<u64>,
+    tiles: usize,
+    reported: usize,
+    error: Option<MapError>, // an error found while decoding, more useful than the one serde reports for it
+    grid: Option<Grid<Option<Cell>>>, // made when the legacy mapsize key or the version 2 metadata is read
+    // Entities and objects, read before it's known which layout the file has
+    early: Vec<(String, serde_json::Value)>,
+    // Legacy layout
+    pending: Vec<(Coord, Cell)>, // terrain read before mapsize
+    objects: HashMap<String, Tile>,
+    // Version 2 layout
+    file: Option<MapFile>,
+    early_cells: Vec<Option<Cell>>, // terrain rows read before the metadata, one after another
+    early_rows: Vec<usize>, // where each of those rows starts in early_cells
+    biomes: Vec<String>, // biome and region rows read before the terrain, put on it at the end
+    regions: Vec<Vec<Option<usize>>>,
+    terrain_read: bool
+}
+
+impl<'p> Loader<'p> {
+    fn new(progress: &'p mut dyn FnMut(Progress), bytes: Rc<std::cell::Cell<u64>>, total: Option<u64>) -> Loader<'p> {
+        Loader {
+            progress, bytes, total, tiles: 0, reported: 0, error: None, grid: None, early: Vec::new(),
+            pending: Vec::new(), objects: HashMap::new(), file: None, early_cells: Vec::new(), early_rows: Vec::new(),
+            biomes: Vec::new(), regions: Vec::new(), terrain_read: false
+        }
+    }
+    fn report(&mut self, done: bool) {
+        self.reported = self.tiles;
+        (self.progress)(Progress { bytes: self.bytes.get(), total: self.total, tiles: self.tiles, done });
+    }
+    fn add_tiles(&mut self, count: usize) {
+        self.tiles += count;
+        if self.tiles - self.reported >= REPORT_EVERY {
+            self.report(false);
+        }
+    }
+    // A legacy key, terrain goes straight into the grid once its size is known
+    fn add_tile(&mut self, key: String, tile: Tile) -> Result<(), MapError> {
+        if key == "mapsize" && self.grid.is_none() {
+            Map::check_size(tile.y as i64, tile.x as i64)?;
+            let mut grid = Grid::new(tile.y, tile.x, None);
+            for (coord, cell) in self.pending.drain(..) {
+                if grid.contains(coord) {
+                    grid.set(coord, Some(cell));
+                } else {
+                    self.objects.insert(coord.key(), cell.to_tile(coord));
+                }
+            }
+            self.grid = Some(grid);
+        }
+        match (Map::terrain_cell(&key, &tile), self.grid.as_mut()) {
+            (Some((coord, cell)), Some(grid)) if grid.contains(coord) => {
+                grid.set(coord, Some(cell));
+            },
+            (Some((coord, cell)), None) => self.pending.push((coord, cell)),
+            _ => {
+                self.objects.insert(key, tile);
+                return Ok(());
+            }
+        }
+        self.add_tiles(1);
+        Ok(())
+    }
+    // The metadata object makes the file version 2, its grid is made and the fields read before it are used
+    fn start_version_2(&mut self, value: serde_json::Value) -> Result<(), MapError> {
+        if let Some(version) = value.get("version").and_then(|v| v.as_u64()) {
+            metadata::check_version(version)?;
+        }
+        let metadata: MapMetadata = serde_json::from_value(value)?;
+        Map::check_size(metadata.height as i64, metadata.width as i64)?;
+        self.grid = Some(self.early_grid(metadata.height, metadata.width));
+        // Keys read as legacy tiles aren't part of a version 2 map
+        self.pending.clear();
+        self.objects.clear();
+        self.file = Some(MapFile { metadata, terrain: Vec::new(), biomes: Vec::new(), tile_regions: Vec::new(), entities: Vec::new(), objects: HashMap::new() });
+        for (key, value) in std::mem::take(&mut self.early) {
+            match key.as_str() {
+                "entities" => self.file.as_mut().unwrap().entities = serde_json::from_value(value)?,
+                "objects" => self.file.as_mut().unwrap().objects = serde_json::from_value(value)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    // The grid for the metadata size, made from terrain rows read before the metadata if there were any
+    // Rows that fill it exactly become the grid as they are, others are cut to fit like any version 2 terrain
+    fn early_grid(&mut self, height: i32, width: i32) -> Grid<Option<Cell>> {
+        let cells = std::mem::take(&mut self.early_cells);
+        let rows = std::mem::take(&mut self.early_rows);
+        let exact = rows.len() == height as usize && cells.len() == rows.len() * width as usize
+            && rows.iter().enumerate().all(|(y, start)| *start == y * width as usize);
+        if exact {
+            return Grid::from_cells(height, width, cells).unwrap_or_else(|| Grid::new(height, width, None));
+        }
+        let mut grid = Grid::new(height, width, None);
+        for (y, start) in rows.iter().enumerate() {
+            let end = rows.get(y + 1).cloned().unwrap_or(cells.len());
+            for (x, cell) in cells[*start..end].iter().enumerate() {
+                grid.set(Coord::new(y as i32, x as i32), *cell);
+            }
+        }
+        grid
+    }
+    fn terrain_row(&mut self, y: usize, row: &str) -> Result<(), MapError> {
+        match (self.file.is_some(), self.grid.as_mut()) {
+            (true, Some(grid)) => MapFile::set_terrain_row(grid, y, row),
+            // No size yet, the row is kept as cells
+            _ => {
+                if self.early_cells.len() + row.len() > MAX_TILES {
+                    return Err(MapError::Schema(format!("terrain before the metadata is over {} tiles", MAX_TILES)));
+                }
+                self.early_rows.push(self.early_cells.len());
+                self.early_cells.extend(row.chars().map(|c| if c == ' ' { None } else { Some(Cell::new(c)) }));
+            }
+        }
+        self.add_tiles(row.chars().count());
+        Ok(())
+    }
+    // Cells of terrain row y read before the metadata
+    fn early_row(&mut self, y: usize) -> &mut [Option<Cell>] {
+        match self.early_rows.get(y) {
+            Some(start) => {
+                let end = self.early_rows.get(y + 1).cloned().unwrap_or(self.early_cells.len());
+                &mut self.early_cells[*start..end]
+            },
+            None => &mut []
+        }
+    }
+    fn biome_row(&mut self, y: usize, row: String) {
+        match (self.terrain_read, self.file.is_some()) {
+            (false, _) => self.biomes.push(row),
+            (true, true) => if let Some(grid) = self.grid.as_mut() {
+                MapFile::set_biome_row(grid, y, &row);
+            },
+            (true, false) => for (cell, letter) in self.early_row(y).iter_mut().zip(row.chars()) {
+                if let Some(cell) = cell {
+                    cell.biome = Biome::from_letter(letter);
+                }
+            }
+        }
+    }
+    fn region_row(&mut self, y: usize, row: Vec<Option<usize>>) {
+        match (self.terrain_read, self.file.is_some()) {
+            (false, _) => self.regions.push(row),
+            (true, true) => if let Some(grid) = self.grid.as_mut() {
+                MapFile::set_region_row(grid, y, &row);
+            },
+            (true, false) => for (cell, region) in self.early_row(y).iter_mut().zip(row.iter()) {
+                if let Some(cell) = cell {
+                    cell.region = region.filter(|r| *r <= u32::MAX as usize).map(|r| r as u32);
+                }
+            }
+        }
+    }
+    fn finish(&mut self) -> Result<Map, MapError> {
+        if let Some(file) = self.file.take() {
+            let mut grid = self.grid.take().unwrap_or_else(|| Grid::new(0, 0, None));
+            for (y, row) in self.biomes.iter().enumerate() {
+                MapFile::set_biome_row(&mut grid, y, row);
+            }
+            for (y, row) in self.regions.iter().enumerate() {
+                MapFile::set_region_row(&mut grid, y, row);
+            }
+            return Ok(Map { map_metadata: file.metadata, map_grid: grid, map_entities: file.entities, map_objects: file.objects });
+        }
+        if self.terrain_read || !self.biomes.is_empty() || !self.regions.is_empty() {
+            return Err(MapError::Schema(String::from("map has version 2 terrain layers but no metadata")));
+        }
+        // No metadata, so the keys kept for version 2 were legacy tiles after all
+        for (key, value) in std::mem::take(&mut self.early) {
+            let tile: Tile = serde_json::from_value(value)?;
+            self.add_tile(key, tile)?;
+        }
+        let mut objects = std::mem::take(&mut self.objects);
+        match self.grid.take() {
+            Some(grid) => Ok(Map::from_legacy_grid(objects, grid)),
+            // No mapsize, from_legacy sizes the grid to fit the terrain
+            None => {
+                for (coord, cell) in self.pending.drain(..) {
+                    objects.insert(coord.key(), cell.to_tile(coord));
+                }
+                Map::from_legacy(objects)
+            }
+        }
+    }
+}
+
+impl<'de, 'a, 'p> DeserializeSeed<'de> for &'a mut Loader<'p> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a, 'p> Visitor<'de> for &'a mut Loader<'p> {
+    type Value = ();
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map json object")
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<(), A::Error> {
+        while let Some(key) = access.next_key::<String>()? {
+            // Version 2 files have a metadata object, anywhere in the file since tools like jq -S sort the keys
+            if key == "metadata" {
+                let value: serde_json::Value = access.next_value()?;
+                if let Err(error) = self.start_version_2(value) {
+                    self.error = Some(error);
+                    return Err(de::Error::custom("bad metadata"));
+                }
+                continue;
+            }
+            let file = match self.file.as_mut() {
+                Some(file) => file,
+                None => {
+                    let added = if Coord::from_key(&key).is_some() {
+                        let tile: Tile = access.next_value()?;
+                        self.add_tile(key, tile)
+                    } else if let Some((_, layer)) = LAYERS.iter().find(|(name, _)| *name == key) {
+                        // Rows are decoded now, an object under the same key is a legacy tile
+                        access.next_value_seed(EarlyLayer { loader: &mut *self, key, layer: *layer })?;
+                        continue;
+                    } else {
+                        // Other keys wait until the layout is known if they could be version 2 fields
+                        let value: serde_json::Value = access.next_value()?;
+                        match Tile::deserialize(&value) {
+                            Ok(tile) if !VERSION_2_FIELDS.contains(&key.as_str()) => self.add_tile(key, tile),
+                            _ => {
+                                self.early.push((key, value));
+                                Ok(())
+                            }
+                        }
+                    };
+                    if let Err(error) = added {
+                        self.error = Some(error);
+                        return Err(de::Error::custom("bad map size"));
+                    }
+                    continue;
+                }
+            };
+            match key.as_str() {
+                "entities" => file.entities = access.next_value::<Vec<PlacedEntity>>()?,
+                "objects" => file.objects = access.next_value()?,
+                "terrain" => access.next_value_seed(Rows { loader: &mut *self, layer: Layer::Terrain })?,
+                "biomes" => access.next_value_seed(Rows { loader: &mut *self, layer: Layer::Biomes })?,
+                "tile_regions" => access.next_value_seed(Rows { loader: &mut *self, layer: Layer::Regions })?,
+                _ => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Version 2 grid layers, each is a list of rows
+#[derive(Clone, Copy, PartialEq)]
+enum Layer {
+    Terrain,
+    Biomes,
+    Regions
+}
+
+// Rows of one layer, put in the grid as they're decoded
+struct Rows<'a, 'p> {
+    loader: &'a mut Loader<'p>,
+    layer: Layer
+}
+
+impl<'de, 'a, 'p> DeserializeSeed<'de> for Rows<'a, 'p> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, 'p> Visitor<'de> for Rows<'a, 'p> {
+    type Value = ();
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of rows")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<(), A::Error> {
+        let mut y = 0;
+        match self.layer {
+            Layer::Terrain => {
+                while let Some(row) = access.next_element::<String>()? {
+                    if let Err(error) = self.loader.terrain_row(y, &row) {
+                        self.loader.error = Some(error);
+                        return Err(de::Error::custom("bad terrain"));
+                    }
+                    y += 1;
+                }
+                self.loader.terrain_read = true;
+            },
+            Layer::Biomes => while let Some(row) = access.next_element::<String>()? {
+                self.loader.biome_row(y, row);
+                y += 1;
+            },
+            Layer::Regions => while let Some(row) = access.next_element::<Vec<Option<usize>>>()? {
+                self.loader.region_row(y, row);
+                y += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A layer key read before the metadata, which could still be a legacy tile with that name
+struct EarlyLayer<'a, 'p> {
+    loader: &'a mut Loader<'p>,
+    key: String,
+    layer: Layer
+}
+
+impl<'de, 'a, 'p> DeserializeSeed<'de> for EarlyLayer<'a, 'p> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a, 'p> Visitor<'de> for EarlyLayer<'a, 'p> {
+    type Value = ();
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of rows or a tile")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, access: A) -> Result<(), A::Error> {
+        Rows { loader: self.loader, layer: self.layer }.visit_seq(access)
+    }
+    fn visit_map<A: MapAccess<'de>>(self, access: A) -> Result<(), A::Error> {
+        let tile = Tile::deserialize(de::value::MapAccessDeserializer::new(access))?;
+        if let Err(error) = self.loader.add_tile(self.key, tile) {
+            self.loader.error = Some(error);
+            return Err(de::Error::custom("bad map size"));
+        }
+        Ok(())
+    }
+}
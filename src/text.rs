@@ -0,0 +1,264 @@
+// Plain text maps, one row of characters per line so they can be drawn in a text editor and read in git diff
+//
+// Exported files start with a small header, everything after the --- line is the map:
+//   maps txt 1
+//   size: 24x70
+//   spawn: 12x35
+//   exit: 20x60
+//   legend: #=wall,.=floor,p=spawn,>=exit,m=monster,c=chest,n=npc
+//   ---
+// Files without the header are just rows, which is what a hand drawn sketch usually looks like
+use std::collections::HashMap;
+use super::{Cell, Coord, Entity, EntityKind, Map, MapError, Palette};
+use super::analysis;
+
+pub const HEADER: &str = "maps txt 1";
+pub const HEADER_END: &str = "---";
+
+// What a character in a text map stands for, spawn, exit and entities stand on floor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileRole {
+    Wall,
+    Floor,
+    Spawn,
+    Exit,
+    Entity(EntityKind),
+    Empty // no tile at all, spaces are always empty
+}
+
+// Characters of a text map and what they stand for
+#[derive(Debug, Clone)]
+pub struct Legend {
+    pub roles: HashMap<char, TileRole>
+}
+
+impl TileRole {
+    pub fn from_name(name: &str) -> Option<TileRole> {
+        match name {
+            "wall" => Some(TileRole::Wall),
+            "floor" => Some(TileRole::Floor),
+            "spawn" => Some(TileRole::Spawn),
+            "exit" => Some(TileRole::Exit),
+            "monster" => Some(TileRole::Entity(EntityKind::Monster)),
+            "chest" => Some(TileRole::Entity(EntityKind::Chest)),
+            "npc" => Some(TileRole::Entity(EntityKind::Npc)),
+            "empty" => Some(TileRole::Empty),
+            _ => None
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileRole::Wall => "wall",
+            TileRole::Floor => "floor",
+            TileRole::Spawn => "spawn",
+            TileRole::Exit => "exit",
+            TileRole::Entity(EntityKind::Monster) => "monster",
+            TileRole::Entity(EntityKind::Chest) => "chest",
+            TileRole::Entity(EntityKind::Npc) => "npc",
+            TileRole::Empty => "empty"
+        }
+    }
+}
+
+impl Legend {
+    pub fn new() -> Legend {
+        let mut roles = HashMap::new();
+        roles.insert(' ', TileRole::Empty);
+        Legend { roles }
+    }
+    // The characters a map is drawn with, used for export and as the default for import
+    pub fn from_palette(palette: &Palette) -> Legend {
+        let mut legend = Legend::new();
+        for kind in [EntityKind::Monster, EntityKind::Chest, EntityKind::Npc].iter() {
+            legend.insert(kind.glyph(), TileRole::Entity(*kind));
+        }
+        legend.insert(palette.exit, TileRole::Exit);
+        legend.insert(palette.player, TileRole::Spawn);
+        legend.insert(palette.floor, TileRole::Floor);
+        legend.insert(palette.wall, TileRole::Wall);
+        legend
+    }
+    // Read "c=role" pairs separated by commas, like "#=wall,.=floor,X=spawn"
+    // The character comes before the = so commas and spaces can be tiles too: ",=wall"
+    pub fn parse(spec: &str) -> Result<Legend, String> {
+        let mut legend = Legend::new();
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+        loop {
+            while i < chars.len() && (chars[i] == ',' || chars[i] == ' ') && chars.get(i + 1) != Some(&'=') {
+                i += 1;
+            }
+            if i >= chars.len() {
+                break;
+            }
+            let c = chars[i];
+            if chars.get(i + 1) != Some(&'=') {
+                return Err(format!("legend entry for '{}' must look like {}=wall", c, c));
+            }
+            i += 2;
+            let mut name = String::new();
+            while i < chars.len() && chars[i] != ',' {
+                name.push(chars[i]);
+                i += 1;
+            }
+            match TileRole::from_name(name.trim()) {
+                Some(role) => legend.insert(c, role),
+                None => return Err(format!("unknown legend role '{}' for '{}', use wall, floor, spawn, exit, monster, chest, npc or empty", name.trim(), c))
+            }
+        }
+        Ok(legend)
+    }
+    pub fn insert(&mut self, c: char, role: TileRole) {
+        self.roles.insert(c, role);
+    }
+    pub fn role(&self, c: char) -> Option<TileRole> {
+        self.roles.get(&c).cloned()
+    }
+    // Legend written in the export header, sorted so the same map always exports the same text
+    pub fn to_spec(&self) -> String {
+        let mut entries: Vec<(char, TileRole)> = self.roles.iter().filter(|(c, _)| **c != ' ').map(|(c, r)| (*c, *r)).collect();
+        entries.sort_by_key(|(c, role)| (Legend::order(*role), *c));
+        entries.iter().map(|(c, role)| format!("{}={}", c, role.name())).collect::<Vec<String>>().join(",")
+    }
+    fn order(role: TileRole) -> usize {
+        match role {
+            TileRole::Wall => 0,
+            TileRole::Floor => 1,
+            TileRole::Spawn => 2,
+            TileRole::Exit => 3,
+            TileRole::Entity(EntityKind::Monster) => 4,
+            TileRole::Entity(EntityKind::Chest) => 5,
+            TileRole::Entity(EntityKind::Npc) => 6,
+            TileRole::Empty => 7
+        }
+    }
+}
+
+impl Default for Legend {
+    fn default() -> Legend {
+        Legend::from_palette(&Palette::default())
+    }
+}
+
+// Draw a map as text, the spawn, exit and entities are drawn over the terrain like the viewer does
+pub fn export(map: &Map) -> String {
+    let palette = map.map_metadata.palette;
+    let (sizey, sizex) = map.mapsize();
+    let mut rows: Vec<Vec<char>> = (0..sizey).map(|y| {
+        (0..sizex).map(|x| map.cell(Coord::new(y, x)).map(|c| c.c).unwrap_or(' ')).collect()
+    }).collect();
+    let mut draw = |coord: Coord, c: char| {
+        if map.map_grid.contains(coord) {
+            rows[coord.y as usize][coord.x as usize] = c;
+        }
+    };
+    for entity in map.map_entities.iter() {
+        draw(Coord::new(entity.y, entity.x), entity.c);
+    }
+    if let Some(exit) = map.map_metadata.exit {
+        draw(exit, palette.exit);
+    }
+    draw(map.map_metadata.spawn, palette.player);
+
+    let mut out = String::new();
+    out.push_str(HEADER);
+    out.push('\n');
+    out.push_str(&format!("size: {}x{}\n", sizey, sizex));
+    out.push_str(&format!("spawn: {}\n", map.map_metadata.spawn.key()));
+    if let Some(exit) = map.map_metadata.exit {
+        out.push_str(&format!("exit: {}\n", exit.key()));
+    }
+    out.push_str(&format!("legend: {}\n", Legend::from_palette(&palette).to_spec()));
+    out.push_str(HEADER_END);
+    out.push('\n');
+    for row in rows.iter() {
+        out.extend(row.iter());
+        out.push('\n');
+    }
+    out
+}
+
+// Read a text map with the standard palette, legend overrides the one in the file header
+// Without a legend the header legend is used, then the default #=wall,.=floor,p=spawn,>=exit
+// A header that can't be read is a parse error, rows that don't fit the size or legend are schema errors
+pub fn import(text: &str, legend: Option<&Legend>) -> Result<Map, MapError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut header: HashMap<String, String> = HashMap::new();
+    let mut rows = &lines[..];
+    if lines.first().map(|l| l.trim_end()) == Some(HEADER) {
+        let end = match lines.iter().position(|l| l.trim_end() == HEADER_END) {
+            Some(end) => end,
+            None => return Err(MapError::Parse(format!("text map header has no '{}' line", HEADER_END)))
+        };
+        for line in lines[1..end].iter() {
+            match line.find(':') {
+                Some(i) => { header.insert(line[..i].trim().to_string(), line[i + 1..].trim().to_string()); },
+                None => return Err(MapError::Parse(format!("bad text map header line '{}'", line)))
+            }
+        }
+        rows = &lines[end + 1..];
+    }
+    let file_legend = match header.get("legend") {
+        Some(spec) => Legend::parse(spec).map_err(MapError::Parse)?,
+        None => Legend::default()
+    };
+    let legend = legend.unwrap_or(&file_legend);
+
+    let (sizey, sizex) = match header.get("size") {
+        Some(size) => match Coord::from_key(size) {
+            Some(size) if size.y > 0 && size.x > 0 => (size.y, size.x),
+            _ => return Err(MapError::Parse(format!("bad text map size '{}', should be HEIGHTxWIDTH", size)))
+        },
+        None => (rows.len() as i32, rows.iter().map(|r| r.chars().count()).max().unwrap_or(0) as i32)
+    };
+    Map::check_size(sizey as i64, sizex as i64)?;
+    if rows.len() as i32 > sizey {
+        return Err(MapError::Schema(format!("text map has {} rows but its size is only {} high", rows.len(), sizey)));
+    }
+
+    let palette = Palette::default();
+    let mut map = Map::empty(sizey, sizex, palette);
+    let mut spawn = None;
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let coord = Coord::new(y as i32, x as i32);
+            if x as i32 >= sizex {
+                return Err(MapError::Schema(format!("row {} is longer than the map width {}", y + 1, sizex)));
+            }
+            let role = match legend.role(c) {
+                Some(role) => role,
+                None => return Err(MapError::Schema(format!("character '{}' at {} is not in the legend", c, coord.key())))
+            };
+            match role {
+                TileRole::Empty => continue,
+                TileRole::Wall => { map.map_grid.set(coord, Some(Cell::new(palette.wall))); },
+                _ => { map.map_grid.set(coord, Some(Cell::new(palette.floor))); }
+            }
+            match role {
+                TileRole::Spawn => {
+                    if spawn.is_some() {
+                        return Err(MapError::Schema(format!("text map has more than one spawn, second one at {}", coord.key())));
+                    }
+                    spawn = Some(coord);
+                },
+                TileRole::Exit => map.map_metadata.exit = Some(coord),
+                TileRole::Entity(kind) => map.add_entity(coord, Entity::new(kind)),
+                _ => {}
+            }
+        }
+    }
+    // A spawn drawn on the map wins over the header
+    if let Some(header_spawn) = header.get("spawn") {
+        map.map_metadata.spawn = Coord::from_key(header_spawn).ok_or_else(|| MapError::Parse(format!("bad text map spawn '{}', should be YxX", header_spawn)))?;
+    }
+    if let Some(spawn) = spawn {
+        map.map_metadata.spawn = spawn;
+    }
+    if map.map_metadata.exit.is_none() {
+        if let Some(header_exit) = header.get("exit") {
+            map.map_metadata.exit = Some(Coord::from_key(header_exit).ok_or_else(|| MapError::Parse(format!("bad text map exit '{}', should be YxX", header_exit)))?);
+        }
+    }
+    analysis::label_regions(&mut map);
+    Ok(map)
+}
@@ -0,0 +1,551 @@
+// Tiled map editor files, the json .tmj and xml .tmx formats
+// Terrain is one tile layer, biomes are a second one and the spawn, exit, region seeds and entities are
+// objects on an object layer. Every terrain character gets a tile with a "char" property and every biome a
+// tile with a "biome" property, so maps drawn with any characters round trip
+use std::collections::BTreeMap;
+use serde_json::{json, Value};
+use super::{Biome, Cell, Coord, Entity, EntityKind, Map, MapError, Palette, RegionSeed};
+use super::analysis;
+use super::image::{self, Image};
+
+// Pixel size of one tile in the tileset image
+pub const TILE_SIZE: u32 = 16;
+
+// Tiled stores flipped and rotated tiles in the top bits of a tile id
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+// Something on the object layer, positions are in tiles
+#[derive(Debug, Clone)]
+pub struct TiledObject {
+    pub name: String,
+    pub kind: String, // spawn, exit, region_seed, monster, chest or npc
+    pub y: i32,
+    pub x: i32,
+    pub properties: Vec<(String, String)>
+}
+
+// What a tile in the tileset stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TiledTile {
+    Char(char),
+    Biome(Biome)
+}
+
+// What maps reads from or writes to a Tiled file
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+    pub height: i32,
+    pub width: i32,
+    pub tile_size: u32,
+    pub palette: Palette,
+    pub tiles: BTreeMap<u32, TiledTile>, // global tile id to what it stands for, 0 is no tile
+    pub data: Vec<u32>, // global tile id of every terrain tile in row major order
+    pub biomes: Vec<u32>, // same for the biome layer, empty if the map has no biomes
+    pub objects: Vec<TiledObject>
+}
+
+const BIOMES: [Biome; 4] = [Biome::Forest, Biome::Desert, Biome::Swamp, Biome::Tundra];
+
+// Escape text for an xml attribute
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn biome_name(biome: Biome) -> String {
+    serde_json::to_value(biome).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default()
+}
+
+impl TiledObject {
+    pub fn new(name: &str, kind: &str, coord: Coord) -> TiledObject {
+        TiledObject { name: name.to_string(), kind: kind.to_string(), y: coord.y, x: coord.x, properties: Vec::new() }
+    }
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+impl TiledMap {
+    pub fn from_map(map: &Map) -> TiledMap {
+        let palette = map.map_metadata.palette;
+        let (sizey, sizex) = map.mapsize();
+        // Wall and floor always come first so their tile ids are the same in every export
+        let mut chars = vec![palette.wall, palette.floor];
+        let mut others: Vec<char> = map.map_grid.iter().filter_map(|(_, cell)| cell.map(|c| c.c)).filter(|c| !chars.contains(c)).collect();
+        others.sort();
+        others.dedup();
+        chars.extend(others);
+        let mut tiles: BTreeMap<u32, TiledTile> = chars.iter().enumerate().map(|(i, c)| (i as u32 + 1, TiledTile::Char(*c))).collect();
+        let data = map.map_grid.iter().map(|(_, cell)| match cell {
+            Some(cell) => chars.iter().position(|c| *c == cell.c).map(|i| i as u32 + 1).unwrap_or(0),
+            None => 0
+        }).collect();
+        // Biome tiles go after the terrain tiles, only when the map has biomes
+        let mut biomes = Vec::new();
+        if map.map_grid.iter().any(|(_, cell)| cell.is_some_and(|c| c.biome.is_some())) {
+            let first = chars.len() as u32 + 1;
+            for (i, biome) in BIOMES.iter().enumerate() {
+                tiles.insert(first + i as u32, TiledTile::Biome(*biome));
+            }
+            biomes = map.map_grid.iter().map(|(_, cell)| match cell.and_then(|c| c.biome) {
+                Some(biome) => first + BIOMES.iter().position(|b| *b == biome).unwrap_or(0) as u32,
+                None => 0
+            }).collect();
+        }
+
+        let mut objects = vec![TiledObject::new("spawn", "spawn", map.map_metadata.spawn)];
+        if let Some(exit) = map.map_metadata.exit {
+            objects.push(TiledObject::new("exit", "exit", exit));
+        }
+        for (n, seed) in map.map_metadata.region_seeds.iter().enumerate() {
+            let mut object = TiledObject::new(&(String::from("v") + &n.to_string()), "region_seed", Coord::new(seed.y, seed.x));
+            object.properties.push((String::from("char"), seed.c.to_string()));
+            if let Some(biome) = seed.biome {
+                object.properties.push((String::from("biome"), biome_name(biome)));
+            }
+            if !seed.neighbors.is_empty() {
+                object.properties.push((String::from("neighbors"), seed.neighbors.join(",")));
+            }
+            objects.push(object);
+        }
+        for entity in map.map_entities.iter() {
+            let kind = serde_json::to_value(entity.entity.kind).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default();
+            let mut object = TiledObject::new(&kind, &kind, Coord::new(entity.y, entity.x));
+            if !entity.entity.properties.is_empty() {
+                object.properties.push((String::from("properties"), Value::Object(entity.entity.properties.clone()).to_string()));
+            }
+            objects.push(object);
+        }
+        TiledMap { height: sizey, width: sizex, tile_size: TILE_SIZE, palette, tiles, data, biomes, objects }
+    }
+
+    // The size is checked before anything is made, so a huge size in a small file is an error and not an allocation
+    pub fn into_map(self) -> Result<Map, MapError> {
+        if self.height <= 0 || self.width <= 0 {
+            return Err(MapError::Schema(format!("tiled map size {}x{} is empty", self.height, self.width)));
+        }
+        let tiles = Map::check_size(self.height as i64, self.width as i64)?;
+        for layer in [&self.data, &self.biomes].iter() {
+            if !layer.is_empty() && layer.len() != tiles {
+                return Err(MapError::Schema(format!("tile layer has {} tiles, a {}x{} map needs {}", layer.len(), self.height, self.width, tiles)));
+            }
+        }
+        let mut map = Map::empty(self.height, self.width, self.palette);
+        let coords: Vec<Coord> = map.map_grid.coords().collect();
+        for (coord, gid) in coords.into_iter().zip(self.data.iter()) {
+            let gid = gid & !FLIP_FLAGS;
+            if gid == 0 {
+                continue;
+            }
+            match self.tiles.get(&gid) {
+                Some(TiledTile::Char(c)) => { map.map_grid.set(coord, Some(Cell::new(*c))); },
+                _ => return Err(MapError::Schema(format!("terrain tile {} at {} has no \"char\" property in the tileset", gid, coord.key())))
+            }
+        }
+        let coords: Vec<Coord> = map.map_grid.coords().collect();
+        for (coord, gid) in coords.into_iter().zip(self.biomes.iter()) {
+            let gid = gid & !FLIP_FLAGS;
+            if gid == 0 {
+                continue;
+            }
+            let biome = match self.tiles.get(&gid) {
+                Some(TiledTile::Biome(biome)) => *biome,
+                _ => return Err(MapError::Schema(format!("biome tile {} at {} has no \"biome\" property in the tileset", gid, coord.key())))
+            };
+            if let Some(Some(cell)) = map.map_grid.get_mut(coord) {
+                cell.biome = Some(biome);
+            }
+        }
+        for object in self.objects.iter() {
+            let coord = Coord::new(object.y, object.x);
+            match object.kind.as_str() {
+                "spawn" => map.map_metadata.spawn = coord,
+                "exit" => map.map_metadata.exit = Some(coord),
+                "region_seed" => map.map_metadata.region_seeds.push(RegionSeed {
+                    y: coord.y,
+                    x: coord.x,
+                    c: object.property("char").and_then(|c| c.chars().next()).unwrap_or(self.palette.floor),
+                    biome: object.property("biome").and_then(|b| serde_json::from_value(Value::String(b.to_string())).ok()),
+                    neighbors: object.property("neighbors").map(|n| n.split(',').map(String::from).collect()).unwrap_or_default()
+                }),
+                kind => {
+                    // Objects maps doesn't know about are skipped
+                    let kind: EntityKind = match serde_json::from_value(Value::String(kind.to_string())) {
+                        Ok(kind) => kind,
+                        Err(_error) => continue
+                    };
+                    let mut entity = Entity::new(kind);
+                    if let Some(properties) = object.property("properties") {
+                        match serde_json::from_str(properties) {
+                            Ok(Value::Object(properties)) => entity.properties = properties,
+                            _ => return Err(MapError::Schema(format!("{} at {} has properties that aren't a json object", object.name, coord.key())))
+                        }
+                    }
+                    map.add_entity(coord, entity);
+                }
+            }
+        }
+        analysis::label_regions(&mut map);
+        Ok(map)
+    }
+
+    // One row tileset image with each tile in its viewer color
+    pub fn tileset_png(&self) -> Result<Vec<u8>, MapError> {
+        let mut image = Image::new(self.tiles.len() as u32 * self.tile_size, self.tile_size)?;
+        for (i, tile) in self.tiles.values().enumerate() {
+            let cell = match tile {
+                TiledTile::Char(c) => Cell::new(*c),
+                TiledTile::Biome(biome) => Cell { c: self.palette.floor, biome: Some(*biome), region: None }
+            };
+            image.fill_tile(Coord::new(0, i as i32), self.tile_size, image::tile_color(&cell, &self.palette));
+        }
+        Ok(image.to_png())
+    }
+
+    // Name and value of the property telling what a tileset tile stands for
+    fn tile_property(tile: &TiledTile) -> (&'static str, String) {
+        match tile {
+            TiledTile::Char(c) => ("char", c.to_string()),
+            TiledTile::Biome(biome) => ("biome", biome_name(*biome))
+        }
+    }
+
+    // Read a tileset tile back from its properties
+    fn tile_from_properties(properties: &[(String, String)]) -> Option<TiledTile> {
+        for (name, value) in properties.iter() {
+            match name.as_str() {
+                "char" => return value.chars().next().map(TiledTile::Char),
+                "biome" => return serde_json::from_value(Value::String(value.clone())).ok().map(TiledTile::Biome),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn palette_properties(&self) -> Vec<(&'static str, char)> {
+        vec![("wall", self.palette.wall), ("floor", self.palette.floor), ("player", self.palette.player), ("exit", self.palette.exit)]
+    }
+
+    // Read the palette back from map properties, missing ones keep the default
+    fn set_palette_property(&mut self, name: &str, value: &str) {
+        let c = match value.chars().next() {
+            Some(c) => c,
+            None => return
+        };
+        match name {
+            "wall" => self.palette.wall = c,
+            "floor" => self.palette.floor = c,
+            "player" => self.palette.player = c,
+            "exit" => self.palette.exit = c,
+            _ => {}
+        }
+    }
+
+    // Tiled json map, image is the tileset image path relative to the map file
+    pub fn to_tmj(&self, image: &str) -> String {
+        let size = self.tile_size as i32;
+        let tiles: Vec<Value> = self.tiles.iter().map(|(gid, tile)| {
+            let (name, value) = TiledMap::tile_property(tile);
+            json!({ "id": gid - 1, "properties": [{ "name": name, "type": "string", "value": value }] })
+        }).collect();
+        let objects: Vec<Value> = self.objects.iter().enumerate().map(|(i, object)| json!({
+            "id": i + 1,
+            "name": object.name,
+            "type": object.kind,
+            "x": object.x * size,
+            "y": object.y * size,
+            "width": size,
+            "height": size,
+            "rotation": 0,
+            "visible": true,
+            "properties": object.properties.iter().map(|(name, value)| json!({ "name": name, "type": "string", "value": value })).collect::<Vec<Value>>()
+        })).collect();
+        let mut layers = vec![json!({
+            "id": 1,
+            "name": "terrain",
+            "type": "tilelayer",
+            "x": 0,
+            "y": 0,
+            "height": self.height,
+            "width": self.width,
+            "opacity": 1,
+            "visible": true,
+            "data": self.data
+        })];
+        if !self.biomes.is_empty() {
+            layers.push(json!({
+                "id": 3,
+                "name": "biomes",
+                "type": "tilelayer",
+                "x": 0,
+                "y": 0,
+                "height": self.height,
+                "width": self.width,
+                "opacity": 0.5,
+                "visible": true,
+                "data": self.biomes
+            }));
+        }
+        layers.push(json!({
+            "id": 2,
+            "name": "objects",
+            "type": "objectgroup",
+            "draworder": "topdown",
+            "x": 0,
+            "y": 0,
+            "opacity": 1,
+            "visible": true,
+            "objects": objects
+        }));
+        let map = json!({
+            "type": "map",
+            "version": "1.8",
+            "orientation": "orthogonal",
+            "renderorder": "right-down",
+            "infinite": false,
+            "height": self.height,
+            "width": self.width,
+            "tileheight": size,
+            "tilewidth": size,
+            "nextlayerid": 4,
+            "nextobjectid": self.objects.len() + 1,
+            "properties": self.palette_properties().iter().map(|(name, c)| json!({ "name": name, "type": "string", "value": c.to_string() })).collect::<Vec<Value>>(),
+            "tilesets": [{
+                "firstgid": 1,
+                "name": "maps",
+                "tilewidth": size,
+                "tileheight": size,
+                "tilecount": self.tiles.len(),
+                "columns": self.tiles.len(),
+                "margin": 0,
+                "spacing": 0,
+                "image": image,
+                "imagewidth": self.tiles.len() as i32 * size,
+                "imageheight": size,
+                "tiles": tiles
+            }],
+            "layers": layers
+        });
+        serde_json::to_string(&map).unwrap()
+    }
+
+    // Tiled xml map with a csv tile layer, image is the tileset image path relative to the map file
+    pub fn to_tmx(&self, image: &str) -> String {
+        let size = self.tile_size;
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!("<map version=\"1.8\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"4\" nextobjectid=\"{}\">\n",
+            self.width, self.height, size, size, self.objects.len() + 1));
+        out.push_str(" <properties>\n");
+        for (name, c) in self.palette_properties() {
+            out.push_str(&format!("  <property name=\"{}\" value=\"{}\"/>\n", name, escape(&c.to_string())));
+        }
+        out.push_str(" </properties>\n");
+        out.push_str(&format!(" <tileset firstgid=\"1\" name=\"maps\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">\n", size, size, self.tiles.len(), self.tiles.len()));
+        out.push_str(&format!("  <image source=\"{}\" width=\"{}\" height=\"{}\"/>\n", escape(image), self.tiles.len() as u32 * size, size));
+        for (gid, tile) in self.tiles.iter() {
+            let (name, value) = TiledMap::tile_property(tile);
+            out.push_str(&format!("  <tile id=\"{}\">\n   <properties>\n    <property name=\"{}\" value=\"{}\"/>\n   </properties>\n  </tile>\n", gid - 1, name, escape(&value)));
+        }
+        out.push_str(" </tileset>\n");
+        out.push_str(&self.tmx_layer(1, "terrain", 1.0, &self.data));
+        if !self.biomes.is_empty() {
+            out.push_str(&self.tmx_layer(3, "biomes", 0.5, &self.biomes));
+        }
+        out.push_str(" <objectgroup id=\"2\" name=\"objects\">\n");
+        for (i, object) in self.objects.iter().enumerate() {
+            out.push_str(&format!("  <object id=\"{}\" name=\"{}\" type=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+                i + 1, escape(&object.name), escape(&object.kind), object.x * size as i32, object.y * size as i32, size, size));
+            if object.properties.is_empty() {
+                out.push_str("/>\n");
+                continue;
+            }
+            out.push_str(">\n   <properties>\n");
+            for (name, value) in object.properties.iter() {
+                out.push_str(&format!("    <property name=\"{}\" value=\"{}\"/>\n", escape(name), escape(value)));
+            }
+            out.push_str("   </properties>\n  </object>\n");
+        }
+        out.push_str(" </objectgroup>\n</map>\n");
+        out
+    }
+
+    // Xml tile layer with csv data, one map row per line
+    fn tmx_layer(&self, id: u32, name: &str, opacity: f64, data: &[u32]) -> String {
+        let rows: Vec<String> = data.chunks(self.width.max(1) as usize).map(|row| {
+            row.iter().map(|gid| gid.to_string()).collect::<Vec<String>>().join(",")
+        }).collect();
+        format!(" <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\" opacity=\"{}\">\n  <data encoding=\"csv\">\n{}\n</data>\n </layer>\n",
+            id, name, self.width, self.height, opacity, rows.join(",\n"))
+    }
+
+    fn empty(height: i32, width: i32, tile_size: u32) -> TiledMap {
+        TiledMap {
+            height,
+            width,
+            tile_size: tile_size.max(1),
+            palette: Palette::default(),
+            tiles: BTreeMap::new(),
+            data: Vec::new(),
+            biomes: Vec::new(),
+            objects: Vec::new()
+        }
+    }
+
+    // Read a Tiled json map, the layer named biomes is the biome layer and the first other tile layer is the terrain
+    // Json that can't be read is a parse error, a map that's missing what maps needs is a schema error
+    pub fn from_tmj(text: &str) -> Result<TiledMap, MapError> {
+        let value: Value = serde_json::from_str(text).map_err(|e| MapError::Parse(format!("bad tiled json: {}", e)))?;
+        let number = |v: &Value, name: &str| v.get(name).and_then(|n| n.as_f64()).ok_or_else(|| MapError::Schema(format!("tiled json is missing \"{}\"", name)));
+        let mut tiled = TiledMap::empty(number(&value, "height")? as i32, number(&value, "width")? as i32, number(&value, "tilewidth")? as u32);
+        let tile_height = number(&value, "tileheight")?.max(1.0);
+        let tile_width = tiled.tile_size as f64;
+        for (name, value) in json_properties(&value) {
+            tiled.set_palette_property(&name, &value);
+        }
+        for tileset in value.get("tilesets").and_then(|t| t.as_array()).map(|t| t.as_slice()).unwrap_or(&[]) {
+            if tileset.get("source").is_some() {
+                return Err(MapError::Schema(String::from("external tilesets aren't supported, embed the tileset in the map")));
+            }
+            let first = number(tileset, "firstgid")? as u32;
+            for tile in tileset.get("tiles").and_then(|t| t.as_array()).map(|t| t.as_slice()).unwrap_or(&[]) {
+                let id = number(tile, "id")? as u32;
+                if let Some(found) = TiledMap::tile_from_properties(&json_properties(tile)) {
+                    tiled.tiles.insert(first + id, found);
+                }
+            }
+        }
+        let layers = value.get("layers").and_then(|l| l.as_array()).map(|l| l.as_slice()).unwrap_or(&[]);
+        for layer in layers.iter().filter(|l| l.get("type").and_then(|t| t.as_str()) == Some("tilelayer")) {
+            let biomes = layer.get("name").and_then(|n| n.as_str()) == Some("biomes");
+            if (biomes && !tiled.biomes.is_empty()) || (!biomes && !tiled.data.is_empty()) {
+                continue;
+            }
+            if layer.get("encoding").and_then(|e| e.as_str()).unwrap_or("csv") != "csv" {
+                return Err(MapError::Schema(String::from("tile layers must be saved with the csv tile layer format")));
+            }
+            let data = layer.get("data").and_then(|d| d.as_array()).ok_or_else(|| MapError::Schema(String::from("tile layer has no data")))?
+                .iter().map(|gid| gid.as_u64().unwrap_or(0) as u32).collect();
+            if biomes {
+                tiled.biomes = data;
+            } else {
+                tiled.data = data;
+            }
+        }
+        if tiled.data.is_empty() {
+            return Err(MapError::Schema(String::from("tiled json has no terrain tile layer")));
+        }
+        for layer in layers.iter().filter(|l| l.get("type").and_then(|t| t.as_str()) == Some("objectgroup")) {
+            for object in layer.get("objects").and_then(|o| o.as_array()).map(|o| o.as_slice()).unwrap_or(&[]) {
+                let text = |name: &str| object.get(name).and_then(|v| v.as_str()).unwrap_or("").to_string();
+                let kind = if text("type").is_empty() { text("class") } else { text("type") };
+                let mut found = TiledObject::new(&text("name"), &kind, Coord::new(
+                    (number(object, "y")? / tile_height).floor() as i32,
+                    (number(object, "x")? / tile_width).floor() as i32
+                ));
+                found.properties = json_properties(object);
+                tiled.objects.push(found);
+            }
+        }
+        Ok(tiled)
+    }
+
+    // Read a Tiled xml map, layers are picked like from_tmj and must be saved as csv or plain xml
+    pub fn from_tmx(text: &str) -> Result<TiledMap, MapError> {
+        let doc = roxmltree::Document::parse(text).map_err(|e| MapError::Parse(format!("bad tmx file: {}", e)))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "map" {
+            return Err(MapError::Schema(String::from("tmx file has no map")));
+        }
+        let number = |node: roxmltree::Node, name: &str| -> Result<f64, MapError> {
+            node.attribute(name).and_then(|n| n.parse::<f64>().ok()).ok_or_else(|| MapError::Schema(format!("tmx {} is missing \"{}\"", node.tag_name().name(), name)))
+        };
+        let mut tiled = TiledMap::empty(number(root, "height")? as i32, number(root, "width")? as i32, number(root, "tilewidth")? as u32);
+        let tile_height = number(root, "tileheight")?.max(1.0);
+        let tile_width = tiled.tile_size as f64;
+        for (name, value) in xml_properties(root) {
+            tiled.set_palette_property(&name, &value);
+        }
+        for tileset in root.children().filter(|n| n.has_tag_name("tileset")) {
+            if tileset.attribute("source").is_some() {
+                return Err(MapError::Schema(String::from("external tilesets aren't supported, embed the tileset in the map")));
+            }
+            let first = number(tileset, "firstgid")? as u32;
+            for tile in tileset.children().filter(|n| n.has_tag_name("tile")) {
+                let id = number(tile, "id")? as u32;
+                if let Some(found) = TiledMap::tile_from_properties(&xml_properties(tile)) {
+                    tiled.tiles.insert(first + id, found);
+                }
+            }
+        }
+        for layer in root.children().filter(|n| n.has_tag_name("layer")) {
+            let biomes = layer.attribute("name") == Some("biomes");
+            if (biomes && !tiled.biomes.is_empty()) || (!biomes && !tiled.data.is_empty()) {
+                continue;
+            }
+            let data = match layer.children().find(|n| n.has_tag_name("data")) {
+                Some(data) => data,
+                None => continue
+            };
+            let data = match data.attribute("encoding") {
+                Some("csv") => data.text().unwrap_or("").split(',').map(|gid| gid.trim().parse::<u32>().unwrap_or(0)).collect(),
+                None => data.children().filter(|n| n.has_tag_name("tile")).map(|t| t.attribute("gid").and_then(|g| g.parse().ok()).unwrap_or(0)).collect(),
+                Some(_) => return Err(MapError::Schema(String::from("tile layers must be saved with the csv tile layer format")))
+            };
+            if biomes {
+                tiled.biomes = data;
+            } else {
+                tiled.data = data;
+            }
+        }
+        if tiled.data.is_empty() {
+            return Err(MapError::Schema(String::from("tmx file has no terrain tile layer")));
+        }
+        for group in root.children().filter(|n| n.has_tag_name("objectgroup")) {
+            for object in group.children().filter(|n| n.has_tag_name("object")) {
+                let kind = object.attribute("type").or_else(|| object.attribute("class")).unwrap_or("");
+                let mut found = TiledObject::new(object.attribute("name").unwrap_or(""), kind, Coord::new(
+                    (number(object, "y")? / tile_height).floor() as i32,
+                    (number(object, "x")? / tile_width).floor() as i32
+                ));
+                found.properties = xml_properties(object);
+                tiled.objects.push(found);
+            }
+        }
+        Ok(tiled)
+    }
+}
+
+// Custom properties of a json map, tile or object, values that aren't strings are turned into text
+fn json_properties(value: &Value) -> Vec<(String, String)> {
+    value.get("properties").and_then(|p| p.as_array()).map(|p| p.as_slice()).unwrap_or(&[]).iter().filter_map(|p| {
+        let name = p.get("name")?.as_str()?.to_string();
+        let value = match p.get("value")? {
+            Value::String(s) => s.clone(),
+            other => other.to_string()
+        };
+        Some((name, value))
+    }).collect()
+}
+
+// Custom properties of an xml map, tile or object, multi line values are stored as text
+fn xml_properties(node: roxmltree::Node) -> Vec<(String, String)> {
+    node.children().filter(|n| n.has_tag_name("properties")).flat_map(|p| p.children().filter(|n| n.has_tag_name("property"))).filter_map(|p| {
+        let value = p.attribute("value").map(String::from).or_else(|| p.text().map(String::from)).unwrap_or_default();
+        Some((p.attribute("name")?.to_string(), value))
+    }).collect()
+}
+
+pub fn export_tmj(map: &Map, image: &str) -> String {
+    TiledMap::from_map(map).to_tmj(image)
+}
+
+pub fn export_tmx(map: &Map, image: &str) -> String {
+    TiledMap::from_map(map).to_tmx(image)
+}
+
+pub fn import_tmj(text: &str) -> Result<Map, MapError> {
+    TiledMap::from_tmj(text)?.into_map()
+}
+
+pub fn import_tmx(text: &str) -> Result<Map, MapError> {
+    TiledMap::from_tmx(text)?.into_map()
+}
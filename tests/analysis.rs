@@ -0,0 +1,172 @@
+// Analysis of small hand drawn maps with known answers
+extern crate maps;
+
+use maps::analysis::{self, RegionKind};
+use maps::{Coord, Map, Tile};
+use std::collections::HashMap;
+
+// Map drawn a row at a time, p is floor
+fn draw(rows: &[&str]) -> Map {
+    let mut go = HashMap::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let tile = Tile::new(y as i32, x as i32, if c == 'p' { '.' } else { c }, Vec::new());
+            go.insert(tile.get_tile_key(), tile);
+        }
+    }
+    go.insert(String::from("mapsize"), Tile::new(rows.len() as i32, rows[0].len() as i32, '$', Vec::new()));
+    Map::from_legacy(go).unwrap()
+}
+
+fn coords(pairs: &[(i32, i32)]) -> Vec<Coord> {
+    pairs.iter().map(|(y, x)| Coord::new(*y, *x)).collect()
+}
+
+fn corridor() -> Map {
+    draw(&[
+        "#######",
+        "#p....#",
+        "#######"
+    ])
+}
+
+// Two rooms joined by a corridor along the top and another along the bottom
+fn one_loop() -> Map {
+    draw(&[
+        "##########",
+        "#........#",
+        "#.######.#",
+        "#p.####..#",
+        "#..####..#",
+        "#.######.#",
+        "#........#",
+        "##########"
+    ])
+}
+
+// Two rooms with one doorway between them
+fn two_rooms() -> Map {
+    draw(&[
+        "#########",
+        "#...#...#",
+        "#.p.....#",
+        "#...#...#",
+        "#########"
+    ])
+}
+
+#[test]
+fn corridor_regions() {
+    let map = corridor();
+    let found = analysis::find_regions(&map);
+    assert_eq!(found.regions.len(), 1);
+    let region = &found.regions[0];
+    assert_eq!(region.kind, RegionKind::Corridor);
+    assert_eq!((region.size, region.component), (5, 0));
+    assert_eq!((region.min_y, region.min_x, region.max_y, region.max_x), (1, 1, 1, 5));
+    assert_eq!((region.centroid_y, region.centroid_x), (1.0, 3.0));
+    assert!(!analysis::is_room_tile(&map, Coord::new(1, 3)));
+}
+
+#[test]
+fn corridor_layout() {
+    let map = corridor();
+    // Every tile but the two ends splits the corridor
+    assert_eq!(analysis::chokepoints(&map), coords(&[(1, 2), (1, 3), (1, 4)]));
+    let ends: Vec<(i32, i32, usize)> = analysis::dead_ends(&map).iter().map(|e| (e.y, e.x, e.length)).collect();
+    assert_eq!(ends, [(1, 1, 4), (1, 5, 4)]);
+    let found = analysis::find_regions(&map);
+    assert_eq!(analysis::loop_count(&found), 0);
+    // One region has nothing to walk to
+    assert_eq!(analysis::average_path_length(&map, &found), 0.0);
+}
+
+#[test]
+fn loop_regions() {
+    let map = one_loop();
+    let found = analysis::find_regions(&map);
+    let kinds: Vec<(RegionKind, usize)> = found.regions.iter().map(|r| (r.kind, r.size)).collect();
+    assert_eq!(kinds, [(RegionKind::Corridor, 10), (RegionKind::Room, 4), (RegionKind::Room, 4), (RegionKind::Corridor, 10)]);
+    assert_eq!(found.tile_regions.get(Coord::new(2, 8)), Some(&Some(0)));
+    assert_eq!(found.tile_regions.get(Coord::new(4, 2)), Some(&Some(1)));
+    assert_eq!(found.tile_regions.get(Coord::new(3, 7)), Some(&Some(2)));
+    assert_eq!(found.tile_regions.get(Coord::new(5, 1)), Some(&Some(3)));
+    assert!(found.regions.iter().all(|r| r.component == 0));
+}
+
+#[test]
+fn loop_layout() {
+    let map = one_loop();
+    let found = analysis::find_regions(&map);
+    assert_eq!(analysis::loop_count(&found), 1);
+    assert!(analysis::chokepoints(&map).is_empty());
+    assert!(analysis::dead_ends(&map).is_empty());
+    // From 1x4, 3x1, 3x7 and 6x4: 5 + 7 + 11 + 12 + 6 + 8 steps over 6 pairs
+    assert!((analysis::average_path_length(&map, &found) - 49.0 / 6.0).abs() < 1e-9);
+}
+
+#[test]
+fn corridor_back_into_the_same_room() {
+    // The corridor leaves the room on the left and comes back in on the right
+    let map = draw(&[
+        "########",
+        "#......#",
+        "#.####.#",
+        "#.#..#.#",
+        "#..p...#",
+        "########"
+    ]);
+    let report = analysis::analyze(&map);
+    assert_eq!((report.rooms, report.corridors), (1, 1));
+    assert_eq!(report.loops, 1);
+    // Around a loop no tile splits the floor
+    assert!(report.chokepoints.is_empty());
+    assert!(report.dead_ends.is_empty());
+}
+
+#[test]
+fn two_rooms_layout() {
+    let map = two_rooms();
+    let report = analysis::analyze(&map);
+    assert_eq!((report.floor_tiles, report.components, report.rooms, report.corridors), (19, 1, 2, 1));
+    assert!((report.corridor_ratio - 1.0 / 19.0).abs() < 1e-9);
+    assert_eq!(report.loops, 0);
+    // The doorway and the room tiles on each side of it
+    assert_eq!(report.chokepoints, coords(&[(2, 3), (2, 4), (2, 5)]));
+    assert!(report.dead_ends.is_empty());
+    // 4 between the rooms and 2 from each room to the doorway
+    assert!((report.average_path_length - 8.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn separate_areas_are_separate_components() {
+    let map = draw(&[
+        "#######",
+        "#..#..#",
+        "#p.#..#",
+        "#######"
+    ]);
+    let components = analysis::floor_components(&map);
+    assert_eq!(components.get(Coord::new(1, 1)), Some(&Some(0)));
+    assert_eq!(components.get(Coord::new(2, 5)), Some(&Some(1)));
+    assert_eq!(components.get(Coord::new(1, 3)), Some(&None));
+    let found = analysis::find_regions(&map);
+    let rooms: Vec<(RegionKind, usize)> = found.regions.iter().map(|r| (r.kind, r.component)).collect();
+    assert_eq!(rooms, [(RegionKind::Room, 0), (RegionKind::Room, 1)]);
+    let report = analysis::analyze(&map);
+    assert_eq!((report.components, report.rooms, report.loops), (2, 2, 0));
+    // Nothing connects the two rooms
+    assert_eq!(report.average_path_length, 0.0);
+}
+
+#[test]
+fn labels_are_stored_on_the_map() {
+    let mut map = two_rooms();
+    let regions = analysis::label_regions(&mut map);
+    assert_eq!(regions.len(), 3);
+    assert_eq!(analysis::regions(&map).len(), 3);
+    assert_eq!(analysis::region_at(&map, Coord::new(1, 1)), Some(0));
+    assert_eq!(analysis::region_at(&map, Coord::new(3, 7)), Some(1));
+    assert_eq!(analysis::region_at(&map, Coord::new(2, 4)), Some(2));
+    assert_eq!(analysis::region_at(&map, Coord::new(0, 0)), None);
+}
@@ -0,0 +1,91 @@
+// Round trips between the binary and json map formats
+extern crate maps;
+extern crate serde_json;
+
+use maps::{Map, MapError, MapFormat};
+use std::path::PathBuf;
+
+// Compare maps through their version 2 json so unordered legacy keys don't matter
+fn as_json(map: &Map) -> serde_json::Value {
+    serde_json::from_str(&map.to_json(MapFormat::Json)).unwrap()
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("maps-test-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn generated_map_round_trips() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    let bytes = map.to_bytes(MapFormat::Binary);
+    assert!(maps::binary::is_binary(&bytes));
+    assert_eq!(as_json(&Map::from_bytes(&bytes).unwrap()), as_json(&map));
+}
+
+#[test]
+fn legacy_map_round_trips() {
+    let map = Map::load_map("resources/maps/50x50.map.gz").unwrap();
+    let back = Map::from_bytes(&map.to_bytes(MapFormat::Binary)).unwrap();
+    assert_eq!(as_json(&back), as_json(&map));
+    let legacy: serde_json::Value = serde_json::from_str(&map.to_json(MapFormat::Legacy)).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&back.to_json(MapFormat::Legacy)).unwrap(), legacy);
+}
+
+#[test]
+fn binary_is_smaller_than_json() {
+    let map = Map::load_map("resources/maps/200x200.map.gz").unwrap();
+    let binary = map.to_bytes(MapFormat::Binary);
+    assert!(binary.len() < map.to_bytes(MapFormat::Json).len());
+    assert!(binary.len() < map.to_bytes(MapFormat::Legacy).len() / 10);
+}
+
+#[test]
+fn load_map_detects_format() {
+    let map = Map::new(50, 50, '#', '.', 'p');
+    let expected = as_json(&map);
+    for (name, format, compression) in [("json", MapFormat::Json, false), ("bin", MapFormat::Binary, false), ("bin.gz", MapFormat::Binary, true)].iter() {
+        let path = temp_file(name);
+        let path = path.to_str().unwrap();
+        Map::save_map_as(path, &map, *compression, *format).unwrap();
+        assert_eq!(as_json(&Map::load_map(path).unwrap()), expected, "{}", name);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn missing_tiles_round_trip() {
+    let mut map = Map::new(50, 50, '#', '.', 'p');
+    map.map_grid.set(maps::Coord::new(0, 0), None);
+    map.map_grid.set(maps::Coord::new(49, 49), None);
+    assert!(map.map_grid.contains(map.map_metadata.spawn));
+    let back = Map::from_bytes(&map.to_bytes(MapFormat::Binary)).unwrap();
+    assert!(back.cell(maps::Coord::new(0, 0)).is_none());
+    assert!(back.cell(maps::Coord::new(49, 49)).is_none());
+    assert_eq!(as_json(&back), as_json(&map));
+}
+
+#[test]
+fn truncated_file_is_an_error() {
+    let map = Map::new(50, 50, '#', '.', 'p');
+    let bytes = map.to_bytes(MapFormat::Binary);
+    match Map::from_bytes(&bytes[..bytes.len() / 2]) {
+        Err(MapError::Parse(message)) => assert!(message.contains("truncated"), "{}", message),
+        other => panic!("expected a parse error, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn unknown_biome_code_is_an_error() {
+    // A 2x2 map of floor, all of it in biome 9
+    let mut bytes = maps::binary::MAGIC.to_vec();
+    bytes.push(maps::binary::BINARY_VERSION);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.push(1);
+    bytes.extend_from_slice(&('.' as u32).to_le_bytes());
+    bytes.extend_from_slice(&[1, 4, 9, 4, 0, 4]);
+    match Map::from_bytes(&bytes) {
+        Err(MapError::Parse(message)) => assert!(message.contains("unknown biome code 9"), "{}", message),
+        other => panic!("expected a parse error, got {:?}", other.map(|_| ()))
+    }
+}
@@ -0,0 +1,74 @@
+// Every v-region gets a biome by latitude, its tiles share it and it's kept in saved maps
+extern crate maps;
+
+use maps::{Biome, Coord, Map, MapFormat, MapOptions};
+
+fn without_noise(sizey: i32, sizex: i32) -> Map {
+    let mut options = MapOptions::new();
+    options.biome_noise = 0.0;
+    Map::new_with_options(sizey, sizex, '#', '.', 'p', &options)
+}
+
+// Index of the closest v-region seed the way generation picks it, ties go to the earliest
+fn closest_seed(map: &Map, coord: Coord) -> usize {
+    let seeds = &map.map_metadata.region_seeds;
+    (0..seeds.len()).min_by_key(|i| (coord.manhattan(Coord::new(seeds[*i].y, seeds[*i].x)), *i)).unwrap()
+}
+
+#[test]
+fn latitude_bands_without_noise() {
+    let map = without_noise(80, 60);
+    for seed in map.map_metadata.region_seeds.iter() {
+        let latitude = seed.y as f64 / 80.0;
+        let biome = seed.biome.unwrap();
+        if latitude < 0.25 {
+            assert_eq!(biome, Biome::Tundra, "{}x{}", seed.y, seed.x);
+        } else if latitude > 0.75 {
+            assert_eq!(biome, Biome::Desert, "{}x{}", seed.y, seed.x);
+        } else {
+            assert!(biome == Biome::Forest || biome == Biome::Swamp, "{}x{} is {:?}", seed.y, seed.x, biome);
+        }
+    }
+}
+
+#[test]
+fn spawn_region_is_the_last_seed() {
+    let map = Map::new(40, 60, '#', '.', 'p');
+    let spawn = map.map_metadata.region_seeds.last().unwrap();
+    assert_eq!(Coord::new(spawn.y, spawn.x), map.map_metadata.spawn);
+    assert_eq!(spawn.c, '.');
+    assert!(spawn.biome.is_some());
+}
+
+#[test]
+fn floor_biome_is_its_region_biome() {
+    let map = Map::new(60, 80, '#', '.', 'p');
+    let mut floors = 0;
+    for (coord, cell) in map.map_grid.iter() {
+        let cell = cell.unwrap();
+        if cell.c == '.' {
+            let seed = &map.map_metadata.region_seeds[closest_seed(&map, coord)];
+            assert_eq!(cell.biome, seed.biome, "{} is closest to {}x{}", coord.key(), seed.y, seed.x);
+            floors += 1;
+        }
+    }
+    assert!(floors > 0);
+}
+
+#[test]
+fn biomes_survive_a_save_and_load() {
+    let dir = std::env::temp_dir().join(format!("maps-biomes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let map = Map::new(40, 60, '#', '.', 'p');
+    for (format, compression) in [(MapFormat::Json, false), (MapFormat::Legacy, true), (MapFormat::Binary, false)].iter() {
+        let path = dir.join(format!("{:?}.map", format));
+        let path = path.to_str().unwrap();
+        Map::save_map_as(path, &map, *compression, *format).unwrap();
+        let loaded = Map::load_map(path).unwrap();
+        for (coord, cell) in map.map_grid.iter() {
+            assert_eq!(loaded.cell(coord).map(|c| c.biome), cell.map(|c| c.biome), "{:?} {}", format, coord.key());
+        }
+        assert_eq!(loaded.map_metadata.region_seeds, map.map_metadata.region_seeds, "{:?}", format);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,66 @@
+// Saved maps are canonical: the same map always gives the same bytes
+extern crate maps;
+
+use maps::{Map, MapFormat};
+
+const FORMATS: [MapFormat; 3] = [MapFormat::Json, MapFormat::Legacy, MapFormat::Binary];
+
+fn map_with_objects() -> Map {
+    let mut map = Map::new(60, 60, '#', '.', 'p');
+    for key in ["notes", "zone", "a", "500x500"].iter() {
+        map.map_objects.insert(key.to_string(), maps::Tile::new(1, 2, '?', vec![String::from("v0")]));
+    }
+    map
+}
+
+#[test]
+fn saving_twice_gives_the_same_bytes() {
+    let map = map_with_objects();
+    for format in FORMATS.iter() {
+        assert!(map.to_bytes(*format) == map.to_bytes(*format), "{:?}", format);
+    }
+}
+
+#[test]
+fn loading_and_saving_gives_the_same_bytes() {
+    let map = map_with_objects();
+    for format in FORMATS.iter() {
+        let bytes = map.to_bytes(*format);
+        let again = Map::from_bytes(&bytes).unwrap().to_bytes(*format);
+        assert!(bytes == again, "{:?}", format);
+    }
+}
+
+#[test]
+fn legacy_keys_are_in_canonical_order() {
+    let map = map_with_objects();
+    let json = map.to_json(MapFormat::Legacy);
+    let at = |key: &str| json.find(&format!("\"{}\":", key)).unwrap();
+    assert!(json.starts_with("{\"mapsize\":"));
+    assert!(at("mapsize") < at("player") && at("player") < at("v0") && at("v0") < at("0x0"));
+    assert!(at("0x0") < at("0x1") && at("0x1") < at("0x59") && at("0x59") < at("1x0") && at("1x0") < at("59x59"));
+    assert!(at("59x59") < at("500x500") && at("500x500") < at("a") && at("a") < at("notes") && at("notes") < at("zone"));
+}
+
+#[test]
+fn numbered_keys_sort_by_number() {
+    let mut keys = vec!["v10", "e2", "10x2", "v2", "r1", "2x10", "exit", "zone"];
+    keys.sort_by_key(|k| Map::legacy_key_order(k));
+    assert_eq!(keys, vec!["exit", "v2", "v10", "r1", "e2", "2x10", "10x2", "zone"]);
+}
+
+#[test]
+fn keys_with_the_same_number_have_one_order() {
+    let mut keys = vec!["v2", "v02", "e7", "e07", "r1", "r01", "v+2", "e007"];
+    keys.sort_by_key(|k| Map::legacy_key_order(k));
+    assert_eq!(keys, vec!["v+2", "v02", "v2", "r01", "r1", "e007", "e07", "e7"]);
+    // Kept as objects, their order used to follow the hashmap, so each load could save different bytes
+    let mut map = Map::new(50, 50, '#', '.', 'p');
+    for key in ["v02", "v002", "e07", "e007", "r01", "r001"].iter() {
+        map.map_objects.insert(key.to_string(), maps::Tile::new(1, 2, '?', Vec::new()));
+    }
+    let bytes = map.to_bytes(MapFormat::Legacy);
+    for _ in 0..30 {
+        assert!(Map::from_bytes(&bytes).unwrap().to_bytes(MapFormat::Legacy) == bytes);
+    }
+}
@@ -0,0 +1,184 @@
+// Each kind of problem check finds, in small map files broken on purpose
+extern crate maps;
+extern crate serde_json;
+
+use maps::check::{self, CheckReport, ProblemKind, Severity};
+use serde_json::{json, Map as JsonMap, Value};
+
+// A legacy map with walls around the edge, big enough that the fixed legacy spawn 12x35 is floor
+fn legacy() -> JsonMap<String, Value> {
+    let (height, width) = (14, 37);
+    let mut objects = JsonMap::new();
+    objects.insert(String::from("mapsize"), json!({"y": height, "x": width, "c": "$", "neighbors": []}));
+    objects.insert(String::from("player"), json!({"y": 0, "x": 0, "c": "p", "neighbors": []}));
+    for y in 0..height {
+        for x in 0..width {
+            let c = if y == 0 || x == 0 || y == height - 1 || x == width - 1 { "#" } else { "." };
+            objects.insert(format!("{}x{}", y, x), json!({"y": y, "x": x, "c": c, "neighbors": []}));
+        }
+    }
+    objects
+}
+
+// A version 2 map, spawn at 1x1
+fn version_2(terrain: &[&str]) -> Value {
+    json!({
+        "metadata": {"version": 2, "height": terrain.len(), "width": terrain[0].len(), "spawn": {"y": 1, "x": 1},
+            "palette": {"wall": "#", "floor": ".", "player": "p", "exit": ">"}, "region_seeds": [], "regions": []},
+        "terrain": terrain
+    })
+}
+
+fn check(text: &str) -> CheckReport {
+    check::check_bytes(text.as_bytes()).unwrap()
+}
+
+// Kinds and keys of every problem found
+fn found(report: &CheckReport) -> Vec<(ProblemKind, String)> {
+    report.problems.iter().map(|p| (p.kind, p.key.clone())).collect()
+}
+
+#[test]
+fn clean_maps_have_no_problems() {
+    let report = check(&Value::Object(legacy()).to_string());
+    assert!(report.ok && report.problems.is_empty(), "{:?}", found(&report));
+    let report = check(&version_2(&["####", "#..#", "####"]).to_string());
+    assert!(report.ok && report.problems.is_empty(), "{:?}", found(&report));
+}
+
+#[test]
+fn missing_tile() {
+    let mut objects = legacy();
+    objects.remove("3x4");
+    let report = check(&Value::Object(objects).to_string());
+    assert_eq!(found(&report), [(ProblemKind::MissingTile, String::from("3x4"))]);
+    assert!(!report.ok);
+    assert_eq!((report.errors, report.warnings), (1, 0));
+    assert_eq!(report.problems[0].severity, Severity::Error);
+    // Imported maps can have holes on purpose, then they are only warnings
+    let report = report.allow_missing();
+    assert!(report.ok);
+    assert_eq!((report.errors, report.warnings), (0, 1));
+    assert_eq!(report.problems[0].severity, Severity::Warning);
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut objects = legacy();
+    objects.insert(String::from("20x5"), json!({"y": 20, "x": 5, "c": ".", "neighbors": []}));
+    objects.insert(String::from("exit"), json!({"y": 3, "x": 90, "c": ">", "neighbors": []}));
+    let report = check(&Value::Object(objects).to_string());
+    assert_eq!(found(&report), [(ProblemKind::OutOfBounds, String::from("20x5")), (ProblemKind::OutOfBounds, String::from("exit"))]);
+    assert!(!report.ok);
+    // Version 2 terrain rows past the map size
+    let mut file = version_2(&["####", "#..#", "####"]);
+    file["terrain"] = json!(["####", "#..#..", "####", "####"]);
+    assert_eq!(found(&check(&file.to_string())), [
+        (ProblemKind::OutOfBounds, String::from("terrain[1]")),
+        (ProblemKind::OutOfBounds, String::from("terrain[3]"))
+    ]);
+}
+
+#[test]
+fn key_mismatch() {
+    let mut objects = legacy();
+    objects.insert(String::from("3x4"), json!({"y": 5, "x": 4, "c": ".", "neighbors": []}));
+    let report = check(&Value::Object(objects).to_string());
+    // The real 3x4 tile is gone as well
+    assert_eq!(found(&report), [(ProblemKind::KeyMismatch, String::from("3x4")), (ProblemKind::MissingTile, String::from("3x4"))]);
+    assert!(report.problems[0].message.contains("y=5"));
+    assert_eq!(report.errors, 2);
+}
+
+#[test]
+fn unknown_character() {
+    let mut objects = legacy();
+    objects["2x2"]["c"] = json!("?");
+    let report = check(&Value::Object(objects).to_string());
+    assert_eq!(found(&report), [(ProblemKind::UnknownCharacter, String::from("2x2"))]);
+    assert!(report.problems[0].message.contains("'?'"));
+}
+
+#[test]
+fn duplicate_key() {
+    let text = Value::Object(legacy()).to_string();
+    let text = text.replacen('{', "{\"3x4\":{\"y\":3,\"x\":4,\"c\":\"#\",\"neighbors\":[]},", 1);
+    let report = check(&text);
+    assert_eq!(found(&report), [(ProblemKind::DuplicateKey, String::from("3x4"))]);
+    assert!(!report.ok);
+    let paths = check::duplicate_keys("{\"metadata\":{\"spawn\":1,\"spawn\":2},\"rows\":[{\"a\":1},{\"a\":1,\"a\":2}],\"b\":1}").unwrap();
+    assert_eq!(paths, ["metadata.spawn", "rows[1].a"]);
+    assert!(check::duplicate_keys("{\"a\":").is_err());
+}
+
+#[test]
+fn spawn_on_wall() {
+    let mut objects = legacy();
+    objects["12x35"]["c"] = json!("#");
+    assert_eq!(found(&check(&Value::Object(objects).to_string())), [(ProblemKind::SpawnOnWall, String::from("spawn"))]);
+    let mut file = version_2(&["####", "#..#", "####"]);
+    file["metadata"]["spawn"] = json!({"y": 0, "x": 1});
+    assert_eq!(found(&check(&file.to_string())), [(ProblemKind::SpawnOnWall, String::from("spawn"))]);
+}
+
+#[test]
+fn compressed_files_are_checked_as_a_stream() {
+    let dir = std::env::temp_dir().join(format!("maps-check-stream-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut file = version_2(&["####", "#..#", "####"]);
+    file["terrain"] = json!(["####", "#..#..", "####", "####"]);
+    let text = file.to_string().replacen("\"terrain\"", "\"objects\":{},\"objects\":{},\"terrain\"", 1);
+    let expected = found(&check(&text));
+    assert_eq!(expected.len(), 3);
+    for codec in [maps::Codec::None, maps::Codec::Gzip, maps::Codec::Zstd, maps::Codec::Xz].iter() {
+        let path = dir.join(format!("broken.{}", codec.name()));
+        std::fs::write(&path, codec.compress(text.as_bytes()).unwrap()).unwrap();
+        let report = check::check_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(found(&report), expected, "{:?}", codec);
+    }
+    // A file cut short is an error, not a report
+    let bytes = maps::Codec::Gzip.compress(text.as_bytes()).unwrap();
+    std::fs::write(dir.join("cut.gzip"), &bytes[..bytes.len() / 2]).unwrap();
+    assert!(matches!(check::check_file(dir.join("cut.gzip").to_str().unwrap()), Err(maps::MapError::Decompress(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_command_report_and_exit_code() {
+    let dir = std::env::temp_dir().join(format!("maps-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut objects = legacy();
+    objects["2x2"]["c"] = json!("?");
+    objects.remove("3x4");
+    std::fs::write(dir.join("broken.map"), Value::Object(objects).to_string()).unwrap();
+    std::fs::write(dir.join("clean.map"), Value::Object(legacy()).to_string()).unwrap();
+    let run = |args: &[&str]| std::process::Command::new(env!("CARGO_BIN_EXE_maps")).arg("--dir").arg(&dir).arg("check").args(args).output().unwrap();
+
+    let output = run(&["--file", "broken.map"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("broken.map has 2 errors"));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report, json!({
+        "ok": false,
+        "errors": 2,
+        "warnings": 0,
+        "problems": [
+            {"kind": "unknown_character", "severity": "error", "key": "2x2", "message": "tile '?' at 2x2 is not the wall '#' or floor '.'"},
+            {"kind": "missing_tile", "severity": "error", "key": "3x4", "message": "no tile at 3x4"}
+        ]
+    }));
+
+    // Allowing holes leaves only the unknown character
+    let output = run(&["--allow-missing", "--file", "broken.map"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("broken.map has 1 errors"));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((report["errors"].clone(), report["warnings"].clone()), (json!(1), json!(1)));
+    assert_eq!(report["problems"][1]["severity"], json!("warning"));
+
+    let output = run(&["--file", "clean.map"]);
+    assert_eq!(output.status.code(), Some(0));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report, json!({"ok": true, "errors": 0, "warnings": 0, "problems": []}));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,114 @@
+// Golden files in the python curses-map-generator layout, loaded and saved back to check the layouts still match
+extern crate maps;
+extern crate serde_json;
+extern crate flate2;
+
+use maps::compat;
+use maps::{Coord, Map, MapFormat};
+use serde_json::Value;
+use std::io::Read;
+use std::path::PathBuf;
+
+const FIXTURE_DIR: &str = "tests/fixtures/python";
+const SKETCH: &str = "tests/fixtures/python/sketch.map";
+
+// Every map file in the fixture directory, so files saved by the python tool can be dropped in, see its README.md
+fn fixtures() -> Vec<String> {
+    let mut paths: Vec<String> = std::fs::read_dir(FIXTURE_DIR).unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_string())
+        .filter(|path| path.ends_with(".map") || path.ends_with(".map.gz"))
+        .collect();
+    paths.sort();
+    assert!(paths.len() >= 2, "fixtures are missing from {}", FIXTURE_DIR);
+    paths
+}
+
+// Fixture json as python wrote it, gunzipped if needed
+fn fixture_json(path: &str) -> String {
+    let bytes = std::fs::read(path).unwrap();
+    if !path.ends_with(".gz") {
+        return String::from_utf8(bytes).unwrap();
+    }
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(&bytes[..]).read_to_string(&mut text).unwrap();
+    text
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("maps-compat-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn fixtures_follow_the_python_layout() {
+    for path in fixtures().iter() {
+        let value: Value = serde_json::from_str(&fixture_json(path)).unwrap();
+        assert_eq!(compat::layout_problems(&value), Vec::<String>::new(), "{}", path);
+    }
+}
+
+#[test]
+fn fixtures_load_tile_for_tile() {
+    for path in fixtures().iter() {
+        let value: Value = serde_json::from_str(&fixture_json(path)).unwrap();
+        let map = Map::load_map(path).unwrap();
+        assert_eq!(map.mapsize(), (value["mapsize"]["y"].as_i64().unwrap() as i32, value["mapsize"]["x"].as_i64().unwrap() as i32), "{}", path);
+        assert_eq!(map.map_metadata.palette.player.to_string(), value["player"]["c"].as_str().unwrap(), "{}", path);
+        assert_eq!(map.map_metadata.spawn, compat::PYTHON_SPAWN, "{}", path);
+        for (coord, cell) in map.map_grid.iter() {
+            assert_eq!(cell.unwrap().c.to_string(), value[coord.key()]["c"].as_str().unwrap(), "{} {}", path, coord.key());
+        }
+        for (v, seed) in map.map_metadata.region_seeds.iter().enumerate() {
+            let tile = &value[format!("v{}", v)];
+            assert_eq!(Coord::new(seed.y, seed.x), Coord::new(tile["y"].as_i64().unwrap() as i32, tile["x"].as_i64().unwrap() as i32));
+            assert_eq!(serde_json::to_value(&seed.neighbors).unwrap(), tile["neighbors"]);
+        }
+        assert!(map.map_objects.is_empty(), "{} has keys that weren't understood", path);
+    }
+}
+
+#[test]
+fn fixtures_save_back_unchanged() {
+    for (n, path) in fixtures().iter().enumerate() {
+        let compression = path.ends_with(".gz");
+        let map = Map::load_map(path).unwrap();
+        let saved = temp_file(&format!("save-{}", n));
+        let saved = saved.to_str().unwrap();
+        Map::save_map_as(saved, &map, compression, MapFormat::Legacy).unwrap();
+        let back = String::from_utf8(Map::read_map_file(saved, compression).unwrap()).unwrap();
+        std::fs::remove_file(saved).unwrap();
+        assert!(compat::same_map(&back, &fixture_json(path)).unwrap(), "{} changed when saved back", path);
+    }
+}
+
+#[test]
+fn fixtures_survive_the_other_formats() {
+    for path in fixtures().iter() {
+        let map = Map::load_map(path).unwrap();
+        for format in [MapFormat::Json, MapFormat::Binary].iter() {
+            let back = Map::from_bytes(&map.to_bytes(*format)).unwrap();
+            assert!(compat::same_map(&back.to_json(MapFormat::Legacy), &fixture_json(path)).unwrap(), "{} through {:?}", path, format);
+        }
+    }
+}
+
+#[test]
+fn generated_maps_follow_the_python_layout() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    let value: Value = serde_json::from_str(&map.to_json(MapFormat::Legacy)).unwrap();
+    assert_eq!(compat::layout_problems(&value), Vec::<String>::new());
+}
+
+#[test]
+fn layout_problems_are_found() {
+    let mut value: Value = serde_json::from_str(&fixture_json(SKETCH)).unwrap();
+    value["3x4"]["y"] = Value::from(5);
+    value.as_object_mut().unwrap().remove("player");
+    value.as_object_mut().unwrap().remove("v1");
+    value.as_object_mut().unwrap().remove("1x1");
+    assert_eq!(compat::layout_problems(&value), vec![
+        String::from("1 of the 640 terrain tiles are missing"),
+        String::from("3x4 has a different y or x than its key"),
+        String::from("no player key"),
+        String::from("region seeds skip v1")
+    ]);
+}
@@ -0,0 +1,83 @@
+// Map files in every compression codec load again, and the codec is found from the first bytes
+extern crate maps;
+extern crate serde_json;
+
+use maps::{Codec, Map, MapError, MapFormat, SaveOptions, Validation};
+use std::path::PathBuf;
+
+const CODECS: [Codec; 4] = [Codec::None, Codec::Gzip, Codec::Zstd, Codec::Xz];
+
+fn as_json(map: &Map) -> serde_json::Value {
+    serde_json::from_str(&map.to_json(MapFormat::Json)).unwrap()
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("maps-compress-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn every_codec_round_trips() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    for codec in CODECS.iter() {
+        for format in [MapFormat::Json, MapFormat::Legacy, MapFormat::Binary].iter() {
+            let path = temp_file(&format!("{}-{:?}", codec.name(), format));
+            let path = path.to_str().unwrap();
+            Map::save_map_with(path, &map, &SaveOptions { codec: *codec, format: *format, ..SaveOptions::new() }).unwrap();
+            assert_eq!(Codec::detect_file(path).unwrap(), *codec);
+            assert_eq!(Validation::file_compressed(path), *codec != Codec::None);
+            let expected = as_json(&Map::from_bytes(&map.to_bytes(*format)).unwrap());
+            assert_eq!(as_json(&Map::load_map(path).unwrap()), expected, "{:?} {:?}", codec, format);
+            assert_eq!(Map::read_map_file(path, false).unwrap(), map.to_bytes(*format), "{:?} {:?}", codec, format);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
+
+#[test]
+fn codecs_are_detected_from_magic_bytes() {
+    for codec in CODECS.iter() {
+        let bytes = codec.compress(b"{}").unwrap();
+        assert_eq!(Codec::detect(&bytes), *codec);
+        assert_eq!(codec.decompress(&bytes).unwrap(), b"{}");
+    }
+    // A few bytes are enough, the rest of the file isn't needed
+    assert_eq!(Codec::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]), Codec::Xz);
+    assert_eq!(Codec::detect(&[0x28, 0xb5, 0x2f, 0xfd]), Codec::Zstd);
+    assert_eq!(Codec::detect(&[0x1f, 0x8b]), Codec::Gzip);
+    assert_eq!(Codec::detect(b"{\"metadata\""), Codec::None);
+    assert_eq!(Codec::detect(b""), Codec::None);
+}
+
+#[test]
+fn codec_names() {
+    for codec in CODECS.iter() {
+        assert_eq!(Codec::from_name(codec.name()), Some(*codec));
+    }
+    assert_eq!(Codec::from_name("gz"), Some(Codec::Gzip));
+    assert_eq!(Codec::from_name("zst"), Some(Codec::Zstd));
+    assert_eq!(Codec::from_name("lz4"), None);
+}
+
+#[test]
+fn cut_short_files_are_decompress_errors() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    for codec in [Codec::Gzip, Codec::Zstd, Codec::Xz].iter() {
+        let path = temp_file(&format!("cut-{}", codec.name()));
+        let path = path.to_str().unwrap();
+        let bytes = codec.compress(&map.to_bytes(MapFormat::Legacy)).unwrap();
+        std::fs::write(path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(Map::load_map(path), Err(MapError::Decompress(_))), "{:?}", codec);
+        assert!(matches!(Map::read_map_file(path, true), Err(MapError::Decompress(_))), "{:?}", codec);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn uncompressed_file_read_as_compressed_is_an_error() {
+    let path = temp_file("plain");
+    let path = path.to_str().unwrap();
+    Map::save_map_with(path, &Map::new(50, 50, '#', '.', 'p'), &SaveOptions::new()).unwrap();
+    assert!(matches!(Map::read_map_file(path, true), Err(MapError::Decompress(_))));
+    assert!(Map::read_map_file(path, false).is_ok());
+    std::fs::remove_file(path).unwrap();
+}
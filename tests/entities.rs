@@ -0,0 +1,104 @@
+// Entities placed during generation keep away from spawn and follow the density of each v-region
+extern crate maps;
+
+use maps::{Cell, Coord, EntityKind, EntityRules, Grid, Map, MapMetadata, MapOptions, Palette, RegionSeed, Tile};
+use std::collections::{HashMap, HashSet};
+
+// A 20x40 floor with walls around it and two v-regions, the spawn region is added after them
+fn two_regions() -> Map {
+    let mut map = Map {
+        map_metadata: MapMetadata::new(20, 40, Palette::default()),
+        map_grid: Grid::new(20, 40, None),
+        map_entities: Vec::new(),
+        map_objects: HashMap::new()
+    };
+    for coord in map.map_grid.coords().collect::<Vec<Coord>>() {
+        let edge = coord.y == 0 || coord.x == 0 || coord.y == 19 || coord.x == 39;
+        map.map_grid.set(coord, Some(Cell::new(if edge { '#' } else { '.' })));
+    }
+    for (y, x) in [(5, 5), (15, 20)].iter() {
+        map.map_metadata.region_seeds.push(RegionSeed::from_tile(&Tile::new(*y, *x, '.', Vec::new())));
+    }
+    map.map_metadata.spawn = Coord::new(12, 35);
+    map.map_metadata.exit = Some(Coord::new(1, 1));
+    map
+}
+
+// Closest v-region of a tile the way placement picks it, ties go to the earliest and spawn is the last region
+fn region(map: &Map, coord: Coord) -> usize {
+    let mut seeds: Vec<Coord> = map.map_metadata.region_seeds.iter().map(|s| Coord::new(s.y, s.x)).collect();
+    seeds.push(map.map_metadata.spawn);
+    (0..seeds.len()).min_by_key(|i| (coord.manhattan(seeds[*i]), *i)).unwrap()
+}
+
+// Tiles that could hold an entity in each region
+fn candidates(map: &Map, rules: &EntityRules) -> Vec<usize> {
+    let mut counts = vec![0; map.map_metadata.region_seeds.len() + 1];
+    for coord in map.map_grid.coords() {
+        if map.is_floor(coord) && coord.manhattan(map.map_metadata.spawn) >= rules.min_spawn_distance && !map.is_exit(coord) {
+            counts[region(map, coord)] += 1;
+        }
+    }
+    counts
+}
+
+fn assert_placed_by_the_rules(map: &Map, rules: &EntityRules) {
+    let spawn = map.map_metadata.spawn;
+    let mut seen = HashSet::new();
+    for entity in map.map_entities.iter() {
+        let coord = Coord::new(entity.y, entity.x);
+        assert!(coord.manhattan(spawn) >= rules.min_spawn_distance, "entity at {} is {} from spawn", coord.key(), coord.manhattan(spawn));
+        assert!(map.is_floor(coord) && !map.is_exit(coord), "entity at {}", coord.key());
+        assert!(seen.insert(coord), "two entities at {}", coord.key());
+        assert!(rules.kinds.contains(&entity.entity.kind));
+        assert_eq!(entity.c, entity.entity.kind.glyph());
+    }
+}
+
+#[test]
+fn generated_entities_keep_away_from_spawn() {
+    let rules = EntityRules { density: 0.2, min_spawn_distance: 15, kinds: vec![EntityKind::Monster, EntityKind::Npc] };
+    let options = MapOptions { entities: rules, ..MapOptions::new() };
+    for _ in 0..5 {
+        let map = Map::new_with_options(50, 60, '#', '.', 'p', &options);
+        assert!(!map.map_entities.is_empty());
+        assert_placed_by_the_rules(&map, &options.entities);
+    }
+}
+
+#[test]
+fn each_region_gets_its_share() {
+    let rules = EntityRules { density: 0.1, min_spawn_distance: 6, kinds: vec![EntityKind::Chest] };
+    let map = two_regions();
+    let available = candidates(&map, &rules);
+    assert!(available.iter().all(|n| *n > 0), "{:?}", available);
+    for _ in 0..20 {
+        let mut placed = two_regions();
+        placed.place_entities(&rules);
+        assert_placed_by_the_rules(&placed, &rules);
+        let mut counts = vec![0; available.len()];
+        for entity in placed.map_entities.iter() {
+            counts[region(&placed, Coord::new(entity.y, entity.x))] += 1;
+        }
+        // The expected count rounded down or up
+        for (count, n) in counts.iter().zip(available.iter()) {
+            let expected = *n as f64 * rules.density;
+            assert!(*count == expected.floor() as usize || *count == expected.ceil() as usize, "{} entities for {} tiles", count, n);
+        }
+    }
+}
+
+#[test]
+fn density_limits() {
+    let mut map = two_regions();
+    map.place_entities(&EntityRules { density: 0.0, ..EntityRules::new() });
+    assert!(map.map_entities.is_empty());
+    // Every tile far enough from spawn, and nothing when there are no kinds to place
+    let rules = EntityRules { density: 1.0, ..EntityRules::new() };
+    map.place_entities(&rules);
+    assert_eq!(map.map_entities.len(), candidates(&map, &rules).iter().sum::<usize>());
+    assert_placed_by_the_rules(&map, &rules);
+    let mut map = two_regions();
+    map.place_entities(&EntityRules { density: 1.0, kinds: Vec::new(), ..EntityRules::new() });
+    assert!(map.map_entities.is_empty());
+}
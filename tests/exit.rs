@@ -0,0 +1,54 @@
+// The exit goes on reachable floor at a percentile of walking distance from spawn
+extern crate maps;
+
+use maps::{Cell, Coord, Grid, Map, MapMetadata, Palette};
+use std::collections::HashMap;
+
+// Map drawn a row at a time, the spawn is at p
+fn draw(rows: &[&str]) -> Map {
+    let (height, width) = (rows.len() as i32, rows[0].len() as i32);
+    let mut map = Map {
+        map_metadata: MapMetadata::new(height, width, Palette::default()),
+        map_grid: Grid::new(height, width, None),
+        map_entities: Vec::new(),
+        map_objects: HashMap::new()
+    };
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let coord = Coord::new(y as i32, x as i32);
+            if c == 'p' {
+                map.map_metadata.spawn = coord;
+            }
+            map.map_grid.set(coord, Some(Cell::new(if c == 'p' { '.' } else { c })));
+        }
+    }
+    map
+}
+
+fn corridor() -> Map {
+    draw(&["########", "#p.....#", "########"])
+}
+
+#[test]
+fn percentiles_of_walking_distance() {
+    let mut map = corridor();
+    // Five tiles from 1 to 5 steps away, the spawn isn't one of them
+    for (percentile, x) in [(0.0, 2), (0.5, 4), (1.0, 6), (-1.0, 2), (2.0, 6)].iter() {
+        map.place_exit(*percentile);
+        assert_eq!(map.map_metadata.exit, Some(Coord::new(1, *x)), "{}", percentile);
+    }
+}
+
+#[test]
+fn exit_is_never_on_spawn() {
+    let mut map = draw(&["#####", "#p#.#", "#####"]);
+    map.place_exit(0.0);
+    assert_eq!(map.map_metadata.exit, None);
+    let options = maps::MapOptions { exit_percentile: 0.0, ..maps::MapOptions::new() };
+    for _ in 0..5 {
+        let map = Map::new_with_options(50, 60, '#', '.', 'p', &options);
+        if let Some(exit) = map.map_metadata.exit {
+            assert_eq!(exit.manhattan(map.map_metadata.spawn), 1);
+        }
+    }
+}
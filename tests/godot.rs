@@ -0,0 +1,128 @@
+// Parse the Godot scenes written by the tscn exporter and check them against the map
+extern crate maps;
+
+use maps::godot::{self, GodotOptions};
+use maps::{Coord, Map};
+use std::collections::HashMap;
+
+// A [section] of a tscn file with its heading attributes and key = value lines
+struct Section {
+    kind: String,
+    attributes: HashMap<String, String>,
+    values: HashMap<String, String>
+}
+
+fn parse_tscn(text: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            assert!(line.ends_with(']'), "unclosed heading {}", line);
+            let mut parts = line[1..line.len() - 1].split(' ');
+            let kind = parts.next().unwrap().to_string();
+            let mut attributes = HashMap::new();
+            for part in parts {
+                let (key, value) = part.split_at(part.find('=').expect("attribute without ="));
+                attributes.insert(key.to_string(), value[1..].trim_matches('"').to_string());
+            }
+            sections.push(Section { kind, attributes, values: HashMap::new() });
+        } else {
+            let (key, value) = line.split_at(line.find(" = ").expect("line without ="));
+            sections.last_mut().expect("value before any heading").values.insert(key.to_string(), value[3..].to_string());
+        }
+    }
+    sections
+}
+
+fn node<'a>(sections: &'a [Section], name: &str) -> &'a Section {
+    sections.iter().find(|s| s.kind == "node" && s.attributes.get("name").map(|n| n.as_str()) == Some(name)).expect(name)
+}
+
+// Numbers inside Constructor( a, b, c )
+fn arguments(value: &str) -> Vec<i32> {
+    let inside = &value[value.find('(').unwrap() + 1..value.rfind(')').unwrap()];
+    inside.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()).map(|n| n.parse().unwrap()).collect()
+}
+
+fn small_map() -> Map {
+    let mut map = maps::text::import("#####\n#.p.#\n#..>#\n#####\n", None).unwrap();
+    map.map_metadata.exit = Some(Coord::new(2, 3));
+    map
+}
+
+#[test]
+fn tile_data_matches_map() {
+    let map = small_map();
+    let sections = parse_tscn(&godot::export_tscn(&map, &GodotOptions::default()));
+    assert_eq!(sections[0].kind, "gd_scene");
+    assert_eq!(sections[0].attributes["format"], "2");
+    let tilemap = node(&sections, "TileMap");
+    assert_eq!(tilemap.attributes["type"], "TileMap");
+    assert_eq!(tilemap.attributes["parent"], ".");
+    assert_eq!(tilemap.values["format"], "1");
+    assert_eq!(arguments(&tilemap.values["cell_size"]), vec![16, 16]);
+    assert!(!tilemap.values.contains_key("tile_set"));
+    let data = arguments(&tilemap.values["tile_data"]);
+    assert_eq!(data.len(), 4 * 5 * 3);
+    for cell in data.chunks(3) {
+        let coord = Coord::new(cell[0] >> 16, cell[0] & 0xffff);
+        let expected = if map.is_floor(coord) { 1 } else { 0 };
+        assert_eq!(cell[1], expected, "tile at {}", coord.key());
+        assert_eq!(cell[2], 0);
+    }
+}
+
+#[test]
+fn markers_are_at_cell_centers() {
+    let map = small_map();
+    let mut options = GodotOptions::new('#', '.');
+    options.cell_size = 32;
+    let sections = parse_tscn(&godot::export_tscn(&map, &options));
+    let spawn = node(&sections, "Spawn");
+    assert_eq!(spawn.attributes["type"], "Position2D");
+    assert_eq!(arguments(&spawn.values["position"]), vec![2 * 32 + 16, 32 + 16]);
+    let exit = node(&sections, "Exit");
+    assert_eq!(arguments(&exit.values["position"]), vec![3 * 32 + 16, 2 * 32 + 16]);
+}
+
+#[test]
+fn tile_ids_and_tileset_are_configurable() {
+    let map = small_map();
+    let mut options = GodotOptions::new('#', '.');
+    options.tile_ids = GodotOptions::parse_tile_ids("#=4,.=9").unwrap();
+    options.tileset = Some(String::from("res://tiles/dungeon.tres"));
+    let sections = parse_tscn(&godot::export_tscn(&map, &options));
+    assert_eq!(sections[0].attributes["load_steps"], "2");
+    let resource = sections.iter().find(|s| s.kind == "ext_resource").unwrap();
+    assert_eq!(resource.attributes["path"], "res://tiles/dungeon.tres");
+    assert_eq!(resource.attributes["type"], "TileSet");
+    let tilemap = node(&sections, "TileMap");
+    assert_eq!(arguments(&tilemap.values["tile_set"]), vec![1]);
+    let data = arguments(&tilemap.values["tile_data"]);
+    assert!(data.chunks(3).all(|cell| cell[1] == 4 || cell[1] == 9));
+    assert_eq!(data.chunks(3).filter(|cell| cell[1] == 9).count(), 6);
+}
+
+#[test]
+fn characters_without_ids_are_left_out() {
+    let map = small_map();
+    let mut options = GodotOptions::new('#', '.');
+    options.tile_ids = GodotOptions::parse_tile_ids(".=1").unwrap();
+    let data = arguments(&node(&parse_tscn(&godot::export_tscn(&map, &options)), "TileMap").values["tile_data"]);
+    assert_eq!(data.len(), 6 * 3);
+    assert!(GodotOptions::parse_tile_ids("#=wall").is_err());
+    assert!(GodotOptions::parse_tile_ids("#0").is_err());
+}
+
+#[test]
+fn generated_map_exports_every_tile() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    let data = arguments(&node(&parse_tscn(&godot::export_tscn(&map, &GodotOptions::default())), "TileMap").values["tile_data"]);
+    assert_eq!(data.len(), 60 * 60 * 3);
+    let cells: Vec<i32> = data.chunks(3).map(|cell| cell[0]).collect();
+    assert_eq!(cells[0], godot::cell_index(Coord::new(0, 0)));
+    assert_eq!(cells[60], 1 << 16);
+}
@@ -0,0 +1,82 @@
+// Map files that claim sizes too big to load are errors, not crashes
+extern crate maps;
+extern crate serde_json;
+
+use maps::{Map, MapError};
+
+fn read(bytes: &[u8]) -> Result<Map, MapError> {
+    maps::stream::read_map(bytes, None, &mut |_| {})
+}
+
+fn binary_header(height: u32, width: u32) -> Vec<u8> {
+    let mut bytes = b"MAPB".to_vec();
+    bytes.push(maps::binary::BINARY_VERSION);
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes
+}
+
+// Both loaders give a schema error for the file
+fn assert_schema_error(bytes: &[u8]) {
+    let name = String::from_utf8_lossy(bytes).to_string();
+    assert!(matches!(Map::from_bytes(bytes), Err(MapError::Schema(_))), "{}", name);
+    assert!(matches!(read(bytes), Err(MapError::Schema(_))), "{}", name);
+}
+
+// Both loaders turn the file down for its size, not for a tile they couldn't read
+fn assert_size_error(bytes: &[u8]) {
+    let name = String::from_utf8_lossy(bytes).to_string();
+    for result in [Map::from_bytes(bytes), read(bytes)].iter() {
+        match result {
+            Err(MapError::Schema(message)) => assert!(message.starts_with("map size "), "{}: {}", name, message),
+            _ => panic!("{}: expected a size error", name)
+        }
+    }
+}
+
+#[test]
+fn huge_binary_sizes() {
+    for (height, width) in [(0x7fffffff, 0x7fffffff), (u32::MAX, u32::MAX), (u32::MAX, 1), (100_001, 1000)].iter() {
+        let mut bytes = binary_header(*height, *width);
+        // 16 bytes, nothing after the header
+        bytes.extend_from_slice(&[0, 0, 0]);
+        assert_eq!(bytes.len(), 16);
+        assert_schema_error(&bytes);
+    }
+}
+
+#[test]
+fn binary_size_at_the_limit_is_not_trusted() {
+    // Allowed size, but the runs aren't there, it's cut short instead of allocating the whole grid first
+    let mut bytes = binary_header(10000, 10000);
+    bytes.push(0);
+    assert!(matches!(Map::from_bytes(&bytes), Err(MapError::Parse(_))));
+}
+
+#[test]
+fn huge_legacy_sizes() {
+    let tile = |y: i64, x: i64| format!("{{\"y\":{},\"x\":{},\"c\":\"$\",\"neighbors\":[]}}", y, x);
+    assert_size_error(format!("{{\"mapsize\":{}}}", tile(2000000000, 2000000000)).as_bytes());
+    assert_size_error(format!("{{\"mapsize\":{}}}", tile(-1, 50)).as_bytes());
+    // Without mapsize the grid is sized to fit the terrain keys
+    assert_size_error(format!("{{\"2000000000x2000000000\":{}}}", tile(2000000000, 2000000000)).as_bytes());
+}
+
+#[test]
+fn huge_version_2_sizes() {
+    let map = Map::new(50, 50, '#', '.', 'p');
+    let mut value: serde_json::Value = serde_json::from_str(&map.to_json(maps::MapFormat::Json)).unwrap();
+    value["metadata"]["height"] = serde_json::json!(2000000000);
+    value["metadata"]["width"] = serde_json::json!(2000000000);
+    assert_size_error(value.to_string().as_bytes());
+}
+
+#[test]
+fn check_size_limits() {
+    assert_eq!(Map::check_size(50, 50).unwrap(), 2500);
+    assert_eq!(Map::check_size(0, i32::MAX as i64).unwrap(), 0);
+    assert_eq!(Map::check_size(10000, 10000).unwrap(), maps::MAX_TILES);
+    assert!(Map::check_size(10000, 10001).is_err());
+    assert!(Map::check_size(-5, 50).is_err());
+    assert!(Map::check_size(1, i32::MAX as i64 + 1).is_err());
+}
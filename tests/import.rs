@@ -0,0 +1,46 @@
+// Importers report the same error kinds as map files, and import exits with their codes
+extern crate maps;
+
+use maps::MapError;
+use std::process::Command;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("maps-import-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn unreadable_files_are_parse_errors() {
+    assert!(matches!(maps::text::import("maps txt 1\nsize: 3x3\n", None), Err(MapError::Parse(_))));
+    assert!(matches!(maps::image::import_png(b"not a png", &maps::image::ColorLegend::default(), [0, 0, 0], Some(1)), Err(MapError::Parse(_))));
+    assert!(matches!(maps::tiled::import_tmj("{"), Err(MapError::Parse(_))));
+    assert!(matches!(maps::tiled::import_tmx("<map"), Err(MapError::Parse(_))));
+    assert!(matches!(maps::rexpaint::import_xp(b"not gzip", None), Err(MapError::Decompress(_))));
+}
+
+#[test]
+fn files_that_dont_make_a_map_are_schema_errors() {
+    assert!(matches!(maps::text::import("#?#\n", None), Err(MapError::Schema(_))));
+    assert!(matches!(maps::text::import("p.p\n", None), Err(MapError::Schema(_))));
+    assert!(matches!(maps::text::import("maps txt 1\nsize: 100000x100000\n---\n#\n", None), Err(MapError::Schema(_))));
+    assert!(matches!(maps::tiled::import_tmj("{\"height\": 2}"), Err(MapError::Schema(_))));
+    assert!(matches!(maps::tiled::import_tmx("<map height=\"2\" width=\"2\" tilewidth=\"8\" tileheight=\"8\"/>"), Err(MapError::Schema(_))));
+}
+
+#[test]
+fn import_exit_codes() {
+    let dir = temp_dir("codes");
+    let text = dir.join("sketch.txt");
+    std::fs::write(&text, "#?#\n").unwrap();
+    let png = dir.join("sketch.png");
+    std::fs::write(&png, "not a png").unwrap();
+    for (from, input, code) in [("txt", dir.join("missing.txt"), 2), ("png", png, 4), ("txt", text, 5)].iter() {
+        let output = Command::new(env!("CARGO_BIN_EXE_maps")).arg("--dir").arg(&dir)
+            .args(["import", "--from", from, "--file", "out.map", "--in"]).arg(input).output().unwrap();
+        assert_eq!(output.status.code(), Some(*code), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+    }
+    assert!(!dir.join("out.map").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,105 @@
+// Parse the LDtk projects written by the ldtk exporter and check them against the map
+extern crate maps;
+extern crate serde_json;
+
+use maps::ldtk::{self, INT_FLOOR, INT_WALL};
+use maps::{Cell, Coord, Entity, EntityKind, Map};
+use serde_json::Value;
+use std::collections::HashSet;
+
+fn small_map() -> Map {
+    let mut map = maps::text::import("######\n#.p..#\n#....#\n######\n", None).unwrap();
+    map.map_grid.set(Coord::new(2, 4), Some(Cell::new('?')));
+    map.map_metadata.exit = Some(Coord::new(2, 3));
+    map.add_entity(Coord::new(1, 4), Entity::new(EntityKind::Chest));
+    map.add_entity(Coord::new(2, 1), Entity::new(EntityKind::Monster));
+    map
+}
+
+fn layer<'a>(project: &'a Value, identifier: &str) -> &'a Value {
+    project["levels"][0]["layerInstances"].as_array().unwrap().iter().find(|l| l["__identifier"] == identifier).expect(identifier)
+}
+
+// Every iid in a json value
+fn iids(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                match value {
+                    Value::String(iid) if key.ends_with("iid") || key.ends_with("Iid") => found.push(iid.clone()),
+                    _ => iids(value, found)
+                }
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|value| iids(value, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn terrain_matches_map() {
+    let map = small_map();
+    let project: Value = serde_json::from_str(&ldtk::export_ldtk(&map, 8)).unwrap();
+    let terrain = layer(&project, "Terrain");
+    assert_eq!(terrain["__type"], "IntGrid");
+    assert_eq!((terrain["__cHei"].as_i64(), terrain["__cWid"].as_i64()), (Some(4), Some(6)));
+    let values: Vec<i64> = terrain["intGridCsv"].as_array().unwrap().iter().map(|v| v.as_i64().unwrap()).collect();
+    assert_eq!(values.len(), 24);
+    for (i, (coord, cell)) in map.map_grid.iter().enumerate() {
+        let expected = match cell.map(|c| c.c) {
+            Some('#') => INT_WALL as i64,
+            Some('.') => INT_FLOOR as i64,
+            _ => 0
+        };
+        assert_eq!(values[i], expected, "{}", coord.key());
+    }
+    // The unknown character is an empty cell
+    assert_eq!(values[2 * 6 + 4], 0);
+}
+
+#[test]
+fn entities_match_map() {
+    let map = small_map();
+    let project: Value = serde_json::from_str(&ldtk::export_ldtk(&map, 8)).unwrap();
+    let instances = layer(&project, "Entities")["entityInstances"].as_array().unwrap();
+    let placed: Vec<(String, i64, i64)> = instances.iter()
+        .map(|e| (e["__identifier"].as_str().unwrap().to_string(), e["__grid"][1].as_i64().unwrap(), e["__grid"][0].as_i64().unwrap()))
+        .collect();
+    assert_eq!(placed, [
+        (String::from("Spawn"), 1, 2),
+        (String::from("Exit"), 2, 3),
+        (String::from("Chest"), 1, 4),
+        (String::from("Monster"), 2, 1)
+    ]);
+    let spawn = &instances[0];
+    assert_eq!((spawn["px"][1].as_i64(), spawn["px"][0].as_i64()), (Some(8), Some(16)));
+    // Each instance points at its definition
+    let defs = project["defs"]["entities"].as_array().unwrap();
+    for instance in instances.iter() {
+        let def = defs.iter().find(|d| d["uid"] == instance["defUid"]).unwrap();
+        assert_eq!(def["identifier"], instance["__identifier"]);
+    }
+}
+
+#[test]
+fn iids_are_unique_and_the_same_each_export() {
+    let map = small_map();
+    let text = ldtk::export_ldtk(&map, 8);
+    assert_eq!(ldtk::export_ldtk(&map, 8), text);
+    let mut found = Vec::new();
+    iids(&serde_json::from_str(&text).unwrap(), &mut found);
+    // Project, level, two layers, four entities and the dummy world
+    assert_eq!(found.len(), 9);
+    assert_eq!(found.iter().collect::<HashSet<_>>().len(), found.len());
+    for iid in found.iter() {
+        let parts: Vec<usize> = iid.split('-').map(|part| part.len()).collect();
+        assert_eq!(parts, [8, 4, 4, 4, 12], "{}", iid);
+        assert!(iid.chars().all(|c| c == '-' || c.is_ascii_hexdigit()), "{}", iid);
+    }
+    // A different map gets different iids
+    let mut other = small_map();
+    other.map_metadata.spawn = Coord::new(1, 3);
+    let mut other_found = Vec::new();
+    iids(&serde_json::from_str(&ldtk::export_ldtk(&other, 8)).unwrap(), &mut other_found);
+    assert!(other_found.iter().all(|iid| !found.contains(iid)));
+}
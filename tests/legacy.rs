@@ -0,0 +1,74 @@
+// The legacy json hashmap goes into the grid and metadata and comes back out unchanged
+extern crate maps;
+extern crate serde_json;
+
+use maps::{Coord, Map, MapError, Tile};
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn as_value(game_objects: &HashMap<String, Tile>) -> Value {
+    serde_json::to_value(game_objects).unwrap()
+}
+
+#[test]
+fn generated_maps_round_trip() {
+    let map = Map::new(50, 60, '#', '.', 'p');
+    let legacy = map.to_legacy();
+    assert!(legacy.contains_key("mapsize") && legacy.contains_key("player") && legacy.contains_key("0x0"));
+    assert!(legacy.contains_key("v0") && legacy.contains_key("e0") && legacy.contains_key("r0"));
+    let back = Map::from_legacy(legacy.clone()).unwrap();
+    assert_eq!(as_value(&back.to_legacy()), as_value(&legacy));
+    assert_eq!(back.map_grid, map.map_grid);
+    assert_eq!(back.map_metadata.spawn, map.map_metadata.spawn);
+    assert_eq!(back.map_metadata.exit, map.map_metadata.exit);
+    assert_eq!(back.map_metadata.palette, map.map_metadata.palette);
+    assert_eq!(serde_json::to_value(&back.map_metadata.region_seeds).unwrap(), serde_json::to_value(&map.map_metadata.region_seeds).unwrap());
+    assert_eq!(serde_json::to_value(&back.map_metadata.regions).unwrap(), serde_json::to_value(&map.map_metadata.regions).unwrap());
+    assert_eq!(serde_json::to_value(&back.map_entities).unwrap(), serde_json::to_value(&map.map_entities).unwrap());
+    assert!(back.map_objects.is_empty());
+}
+
+#[test]
+fn keys_it_doesnt_know_come_back() {
+    let mut legacy = HashMap::new();
+    for y in 0..3 {
+        for x in 0..3 {
+            legacy.insert(Coord::new(y, x).key(), Tile::new(y, x, if y == 1 && x == 1 { '.' } else { '#' }, Vec::new()));
+        }
+    }
+    legacy.insert(String::from("mapsize"), Tile::new(3, 3, '$', Vec::new()));
+    legacy.insert(String::from("player"), Tile::new(0, 0, 'p', Vec::new()));
+    legacy.insert(String::from("torch"), Tile::new(1, 1, '*', Vec::new()));
+    // A terrain key with neighbors isn't a plain grid cell, and a tile outside the map size isn't either
+    legacy.insert(String::from("1x1"), Tile::new(1, 1, '.', vec![String::from("0x1")]));
+    legacy.insert(String::from("7x7"), Tile::new(7, 7, '.', Vec::new()));
+    let back = Map::from_legacy(legacy.clone()).unwrap();
+    let mut objects: Vec<&String> = back.map_objects.keys().collect();
+    objects.sort();
+    assert_eq!(objects, ["1x1", "7x7", "torch"]);
+    assert!(back.cell(Coord::new(1, 1)).is_none());
+    assert_eq!(as_value(&back.to_legacy()), as_value(&legacy));
+}
+
+#[test]
+fn size_without_mapsize() {
+    let mut legacy = HashMap::new();
+    for (y, x) in [(0, 0), (2, 4)].iter() {
+        legacy.insert(Coord::new(*y, *x).key(), Tile::new(*y, *x, '#', Vec::new()));
+    }
+    let map = Map::from_legacy(legacy).unwrap();
+    assert_eq!(map.mapsize(), (3, 5));
+    assert_eq!(map.cell(Coord::new(2, 4)).map(|c| c.c), Some('#'));
+    assert!(map.cell(Coord::new(1, 1)).is_none());
+    // Written back it gets the mapsize and player keys
+    let legacy = map.to_legacy();
+    assert_eq!((legacy["mapsize"].y, legacy["mapsize"].x, legacy["mapsize"].c), (3, 5, '$'));
+    assert_eq!(legacy["player"].c, 'p');
+}
+
+#[test]
+fn huge_mapsize_is_an_error() {
+    let mut legacy = HashMap::new();
+    legacy.insert(String::from("mapsize"), Tile::new(100000, 100000, '$', Vec::new()));
+    assert!(matches!(Map::from_legacy(legacy), Err(MapError::Schema(_))));
+}
@@ -0,0 +1,59 @@
+// Legacy maps migrate to the version 2 layout with typed metadata, and saving stamps the modified time
+extern crate maps;
+extern crate serde_json;
+
+use maps::metadata::{self, FORMAT_VERSION};
+use maps::save::{self, SaveOptions};
+use maps::{Map, MapFormat};
+use serde_json::Value;
+
+const LEGACY: &str = "tests/fixtures/python/voronoi.map.gz";
+
+fn temp_file(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("maps-metadata-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn legacy_file_migrates_to_version_2() {
+    let legacy = Map::load_map(LEGACY).unwrap();
+    assert_eq!(legacy.map_metadata.created, None);
+    assert_eq!(legacy.map_metadata.modified, None);
+    let path = temp_file("migrate");
+    let path = path.to_str().unwrap();
+    let mut map = Map::load_map(LEGACY).unwrap();
+    let before = metadata::now();
+    save::save_map(path, &mut map, &SaveOptions { force: true, ..SaveOptions::new() }).unwrap();
+
+    let file: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(file["metadata"]["version"], FORMAT_VERSION);
+    assert_eq!(file["metadata"]["height"], 50);
+    assert_eq!(file["terrain"].as_array().unwrap().len(), 50);
+    assert!(file.get("mapsize").is_none() && file.get("0x0").is_none());
+    let migrated = Map::load_map(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    // Nothing from the legacy file is lost, and it saves back to the same legacy json
+    assert_eq!(migrated.mapsize(), legacy.mapsize());
+    assert_eq!(migrated.map_metadata.spawn, legacy.map_metadata.spawn);
+    assert_eq!(migrated.map_metadata.palette, legacy.map_metadata.palette);
+    assert_eq!(migrated.map_metadata.region_seeds.len(), legacy.map_metadata.region_seeds.len());
+    assert_eq!(migrated.map_grid, legacy.map_grid);
+    assert!(maps::compat::same_map(&migrated.to_json(MapFormat::Legacy), &legacy.to_json(MapFormat::Legacy)).unwrap());
+    // When it was made isn't known, when it was changed is
+    assert_eq!(migrated.map_metadata.created, None);
+    assert!(migrated.map_metadata.modified.unwrap() >= before);
+}
+
+#[test]
+fn saving_updates_modified() {
+    let path = temp_file("modified");
+    let path = path.to_str().unwrap();
+    let mut map = Map::new(50, 50, '#', '.', 'p');
+    let created = map.map_metadata.created;
+    map.map_metadata.modified = Some(1);
+    save::save_map(path, &mut map, &SaveOptions { force: true, ..SaveOptions::new() }).unwrap();
+    let saved = Map::load_map(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(saved.map_metadata.created, created);
+    assert!(saved.map_metadata.modified.unwrap() >= created.unwrap());
+    assert_eq!(saved.map_metadata.modified, map.map_metadata.modified);
+}
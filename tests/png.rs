@@ -0,0 +1,130 @@
+// Maps exported to png and imported back, and images painted with a legend
+extern crate maps;
+
+use maps::image::{self, ColorLegend, Image};
+use maps::{Biome, Cell, Coord, Entity, EntityKind, Map, MapError, Palette};
+
+const SPAWN: [u8; 3] = [0, 175, 95];
+
+// Terrain characters of every tile, biomes and what stands on the map aren't kept in a png
+fn terrain(map: &Map) -> Vec<Option<char>> {
+    map.map_grid.iter().map(|(_, cell)| cell.map(|c| c.c)).collect()
+}
+
+fn schema_error(bytes: &[u8], legend: &ColorLegend, scale: Option<u32>) -> String {
+    match image::import_png(bytes, legend, SPAWN, scale) {
+        Err(MapError::Schema(message)) => message,
+        other => panic!("expected a schema error, got {:?}", other.map(|m| m.mapsize()))
+    }
+}
+
+#[test]
+fn exported_maps_import_again() {
+    let mut map = Map::new(50, 60, '#', '.', 'p');
+    // A wall seed can land on the spawn, and the spawn color always reads back as floor
+    map.map_grid.set(map.map_metadata.spawn, Some(Cell::new('.')));
+    for scale in [1, 3, 8].iter() {
+        let png = image::export_png(&map, *scale).unwrap();
+        // The scale is found from the image, or given
+        for given in [None, Some(*scale)].iter() {
+            let back = image::import_png(&png, &ColorLegend::default(), SPAWN, *given).unwrap();
+            assert_eq!(back.mapsize(), (50, 60), "scale {} given {:?}", scale, given);
+            assert_eq!(terrain(&back), terrain(&map), "scale {} given {:?}", scale, given);
+            assert_eq!(back.map_metadata.spawn, map.map_metadata.spawn);
+        }
+        assert_eq!(image::detect_scale(&image::decode_png(&png).unwrap()), *scale);
+    }
+}
+
+#[test]
+fn export_draws_the_viewer_colors() {
+    let mut map = maps::text::import("######\n#p...#\n#....#\n######\n", None).unwrap();
+    let mut forest = Cell::new('.');
+    forest.biome = Some(Biome::Forest);
+    map.map_grid.set(Coord::new(2, 1), Some(forest));
+    map.map_metadata.exit = Some(Coord::new(2, 4));
+    map.add_entity(Coord::new(1, 4), Entity::new(EntityKind::Npc));
+    let picture = image::decode_png(&image::export_png(&map, 5).unwrap()).unwrap();
+    assert_eq!((picture.width, picture.height), (30, 20));
+    let color = |pair: (i16, i16)| image::xterm_rgb(pair.0);
+    for (y, x, expected) in [
+        (0, 0, color(maps::COLOR_WALL)),
+        (1, 2, color(maps::COLOR_FLOOR)),
+        (2, 1, color(maps::COLOR_FOREST)),
+        (2, 4, color(maps::COLOR_EXIT)),
+        (1, 4, color(maps::COLOR_ENTITY)),
+        (1, 1, color(maps::COLOR_PLAYER))
+    ].iter() {
+        // Every pixel of the tile's square
+        for py in y * 5..y * 5 + 5 {
+            for px in x * 5..x * 5 + 5 {
+                assert_eq!(picture.pixel(px, py), *expected, "{}x{}", y, x);
+            }
+        }
+    }
+    // Scales outside 1 to 64 are clamped
+    assert_eq!(image::render(&map, 0).unwrap().width, 6);
+    assert_eq!(image::render(&map, 1000).unwrap().width, 6 * image::MAX_SCALE);
+}
+
+#[test]
+fn painted_images_use_the_legend() {
+    let (white, black, blue) = ([255, 255, 255], [0, 0, 0], [0, 0, 255]);
+    let rows = [
+        [black, black, black, blue],
+        [black, white, SPAWN, black],
+        [black, black, black, black]
+    ];
+    let mut painted = Image::new(8, 6).unwrap();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            painted.fill_tile(Coord::new(y as i32, x as i32), 2, *color);
+        }
+    }
+    let legend = ColorLegend::parse("000000=#, ffffff=.,0000ff= ").unwrap();
+    let map = image::import_png(&painted.to_png(), &legend, SPAWN, None).unwrap();
+    assert_eq!(map.mapsize(), (3, 4));
+    assert_eq!(map.map_metadata.spawn, Coord::new(1, 2));
+    assert_eq!(map.cell(Coord::new(1, 1)).map(|c| c.c), Some('.'));
+    assert_eq!(map.cell(Coord::new(1, 2)).map(|c| c.c), Some('.'));
+    assert_eq!(map.cell(Coord::new(0, 0)).map(|c| c.c), Some('#'));
+    assert!(map.cell(Coord::new(0, 3)).is_none());
+    // Read as one pixel per tile, every pixel is a tile
+    assert!(schema_error(&painted.to_png(), &legend, Some(1)).contains("more than one spawn"));
+}
+
+#[test]
+fn bad_legends_and_colors() {
+    assert!(ColorLegend::parse("000000=#,ffffff").unwrap_err().contains("RRGGBB=#"));
+    assert!(ColorLegend::parse("00000g=#").unwrap_err().contains("bad legend color"));
+    assert!(ColorLegend::parse("000000=").unwrap_err().contains("no character"));
+    assert!(ColorLegend::parse("000000=##").unwrap_err().contains("one character"));
+    assert_eq!(ColorLegend::parse(" , ").unwrap().colors.len(), 0);
+    let mut painted = Image::new(2, 1).unwrap();
+    painted.fill_tile(Coord::new(0, 1), 1, [1, 2, 3]);
+    assert!(schema_error(&painted.to_png(), &ColorLegend::default(), None).contains("010203 at tile 0x1"));
+}
+
+#[test]
+fn unknown_tiles_are_not_the_player_color() {
+    let mut map = maps::text::import("####\n#p.#\n####\n", None).unwrap();
+    map.map_grid.set(Coord::new(1, 2), Some(Cell::new('?')));
+    let picture = image::render(&map, 1).unwrap();
+    let player = image::xterm_rgb(maps::COLOR_PLAYER.0);
+    assert_eq!(picture.pixel(1, 1), player);
+    assert_eq!(picture.pixel(2, 1), image::xterm_rgb(maps::COLOR_UNKNOWN.0));
+    assert_ne!(picture.pixel(2, 1), player);
+    assert_ne!(picture.pixel(2, 1), picture.pixel(0, 0));
+}
+
+#[test]
+fn images_over_the_pixel_limit_are_errors() {
+    assert!(matches!(Image::new(16385, 16384), Err(MapError::Validation(message)) if message.contains("16385x16384")));
+    // u32::MAX squared doesn't fit in a u32 either
+    assert!(Image::new(u32::MAX, u32::MAX).is_err());
+    // 300 tiles at 64 pixels is 19200 pixels on each side
+    let map = Map::empty(300, 300, Palette::default());
+    assert!(matches!(image::render(&map, 64), Err(MapError::Validation(_))));
+    assert!(image::export_png(&map, 64).is_err());
+    assert_eq!(image::render(&map, 32).unwrap().width, 9600);
+}
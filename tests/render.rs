@@ -0,0 +1,127 @@
+// Svg images and html pages drawn by render, checked against the map and the png export
+extern crate maps;
+extern crate roxmltree;
+
+use maps::image;
+use maps::render;
+use maps::{Cell, Coord, Entity, EntityKind, Map, Palette};
+
+fn small_map() -> Map {
+    let mut map = maps::text::import("######\n#.p..#\n#....#\n######\n", None).unwrap();
+    map.map_grid.set(Coord::new(2, 1), Some(Cell::new('?')));
+    map.map_metadata.exit = Some(Coord::new(2, 4));
+    map.add_entity(Coord::new(1, 4), Entity::new(EntityKind::Chest));
+    map.add_entity(Coord::new(1, 4), Entity::new(EntityKind::Monster));
+    map
+}
+
+fn parse_hex(hex: &str) -> [u8; 3] {
+    image::parse_color(hex).unwrap_or_else(|| panic!("bad color {}", hex))
+}
+
+#[test]
+fn svg_matches_png() {
+    let map = small_map();
+    let svg = render::export_svg(&map, 4);
+    let document = roxmltree::Document::parse(&svg).unwrap();
+    let root = document.root_element();
+    assert_eq!((root.attribute("width"), root.attribute("height")), (Some("24"), Some("16")));
+    // Paint the rects in order into one color per tile
+    let mut tiles = [[0, 0, 0]; 4 * 6];
+    let number = |node: &roxmltree::Node, name: &str| node.attribute(name).unwrap().parse::<usize>().unwrap();
+    for rect in root.children().filter(|n| n.has_tag_name("rect")).skip(1) {
+        let (x, y, width, height) = (number(&rect, "x"), number(&rect, "y"), number(&rect, "width"), number(&rect, "height"));
+        assert_eq!((x % 4, y % 4, width % 4, height), (0, 0, 0, 4));
+        for tile in x / 4..(x + width) / 4 {
+            tiles[y / 4 * 6 + tile] = parse_hex(rect.attribute("fill").unwrap());
+        }
+    }
+    let picture = image::render(&map, 1).unwrap();
+    for y in 0..4 {
+        for x in 0..6 {
+            assert_eq!(tiles[y * 6 + x], picture.pixel(x as u32, y as u32), "{}x{}", y, x);
+        }
+    }
+    // Same colored runs are one rect: the top wall, the floor from 1x1 to 1x3, the exit, two entities and the player
+    assert_eq!(root.children().filter(|n| n.has_tag_name("rect")).count(), 1 + 9 + 1 + 2 + 1);
+}
+
+#[test]
+fn svg_cell_sizes_are_clamped_and_do_not_overflow() {
+    let size = |svg: &str| {
+        let document = roxmltree::Document::parse(svg).unwrap();
+        let root = document.root_element();
+        (root.attribute("width").unwrap().to_string(), root.attribute("height").unwrap().to_string())
+    };
+    let map = small_map();
+    assert_eq!(size(&render::export_svg(&map, 0)), ("6".to_string(), "4".to_string()));
+    assert_eq!(size(&render::export_svg(&map, 1_000_000_000)), ((6 * render::MAX_CELL_SIZE).to_string(), (4 * render::MAX_CELL_SIZE).to_string()));
+    // 8400000 tiles wide at 256 pixels is past i32::MAX
+    let long = Map::empty(1, 8_400_000, Palette::default());
+    let svg = render::export_svg(&long, render::MAX_CELL_SIZE);
+    assert_eq!(size(&svg), ("2150400000".to_string(), "256".to_string()));
+}
+
+#[test]
+fn render_command_checks_the_cell_size() {
+    let dir = std::env::temp_dir().join(format!("maps-render-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Map::save_map(dir.join("cells.map").to_str().unwrap(), &Map::new(50, 50, '#', '.', 'p'), false).unwrap();
+    for (cell_size, code) in [("1000000000", 1), ("0", 1), ("256", 0)].iter() {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_maps")).arg("--dir").arg(&dir)
+            .args(["render", "--file", "cells.map", "--cell-size", cell_size, "--out"]).arg(dir.join("cells.svg")).output().unwrap();
+        assert_eq!(output.status.code(), Some(*code), "{}: {}", cell_size, String::from_utf8_lossy(&output.stderr));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Class, tooltip and character of every tile span
+fn spans(html: &str) -> Vec<(String, String, String)> {
+    let map = &html[html.find("<pre class=\"map\">\n").unwrap()..html.find("</pre>").unwrap()];
+    let mut found = Vec::new();
+    for part in map.split("<span ").skip(1) {
+        let class = &part[part.find("class=\"").unwrap() + 7..];
+        let class = &class[..class.find('"').unwrap()];
+        let title = &part[part.find("title=\"").unwrap() + 7..];
+        let title = &title[..title.find('"').unwrap()];
+        let text = &part[part.find('>').unwrap() + 1..part.find("</span>").unwrap()];
+        found.push((class.to_string(), title.to_string(), text.to_string()));
+    }
+    found
+}
+
+#[test]
+fn html_has_every_tile() {
+    let map = small_map();
+    let html = render::export_html(&map, "cave <1> & \"2\"");
+    assert!(html.contains("<title>cave &lt;1&gt; &amp; &quot;2&quot;</title>"));
+    let lines: Vec<&str> = html[html.find("<pre").unwrap()..html.find("</pre>").unwrap()].lines().skip(1).collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines.iter().all(|line| line.matches("<span ").count() == 6));
+    let tiles = spans(&html);
+    assert_eq!(tiles.len(), 24);
+    let tile = |y: usize, x: usize| &tiles[y * 6 + x];
+    assert_eq!(tile(0, 0), &(String::from("wall"), String::from("0x0 wall"), String::from("#")));
+    assert_eq!(tile(1, 1).0, "floor");
+    assert_eq!(tile(1, 2), &(String::from("player"), format!("1x2 floor region {} spawn", maps::analysis::region_at(&map, Coord::new(1, 2)).unwrap()), String::from("p")));
+    assert_eq!((tile(2, 4).0.as_str(), tile(2, 4).2.as_str()), ("exit", "&gt;"));
+    assert!(tile(2, 4).1.ends_with(" exit"));
+    // The first entity is drawn, the tooltip names both
+    assert_eq!((tile(1, 4).0.as_str(), tile(1, 4).2.clone()), ("entity", EntityKind::Chest.glyph().to_string()));
+    assert!(tile(1, 4).1.ends_with(" chest monster"), "{}", tile(1, 4).1);
+    assert_eq!(tile(2, 1), &(String::from("unknown"), String::from("2x1 '?'"), String::from("?")));
+    // Every class has a style
+    for (class, _, _) in tiles.iter() {
+        assert!(html.contains(&format!(".{} {{ color: ", class)), "{}", class);
+    }
+}
+
+#[test]
+fn entity_index_matches_the_entity_list() {
+    let map = small_map();
+    let index = render::entity_index(&map);
+    let kinds: Vec<EntityKind> = index.get(Coord::new(1, 4)).unwrap().iter().map(|e| e.entity.kind).collect();
+    assert_eq!(kinds, [EntityKind::Chest, EntityKind::Monster]);
+    assert!(index.iter().filter(|(coord, _)| *coord != Coord::new(1, 4)).all(|(_, here)| here.is_empty()));
+    assert_eq!(render::describe(&map, Coord::new(1, 4)), spans(&render::export_html(&map, "cave"))[10].1);
+}
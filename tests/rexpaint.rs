@@ -0,0 +1,115 @@
+// Maps exported to REXPaint images and imported back, and images drawn with a legend
+extern crate flate2;
+extern crate maps;
+
+use maps::rexpaint::{self, XpCell, XpImage, XpLayer};
+use maps::text::Legend;
+use maps::{Cell, Coord, EntityKind, Map, MapError};
+
+// Terrain character of every tile and the biome of floors, an image only shows the biome by the floor color
+fn terrain(map: &Map) -> Vec<Option<(char, Option<maps::Biome>)>> {
+    map.map_grid.iter().map(|(_, cell)| cell.map(|c| (c.c, c.biome.filter(|_| c.c == '.')))).collect()
+}
+
+fn entities(map: &Map) -> Vec<(i32, i32, EntityKind)> {
+    let mut found: Vec<(i32, i32, EntityKind)> = map.map_entities.iter().map(|e| (e.y, e.x, e.entity.kind)).collect();
+    found.sort_by_key(|(y, x, _)| (*y, *x));
+    found
+}
+
+#[test]
+fn exported_maps_import_again() {
+    let mut map = Map::new(50, 60, '#', '.', 'p');
+    // A wall seed can land on the spawn, and the spawn marker always reads back as floor
+    map.map_grid.set(map.map_metadata.spawn, Some(Cell::new('.')));
+    // The exit goes on a floor tile nothing else is drawn on
+    let exit = map.map_grid.iter().map(|(coord, _)| coord)
+        .filter(|coord| map.is_floor(*coord) && *coord != map.map_metadata.spawn && !map.map_entities.iter().any(|e| Coord::new(e.y, e.x) == *coord))
+        .nth(100).unwrap();
+    map.map_metadata.exit = Some(exit);
+    assert!(!map.map_entities.is_empty());
+    assert!(map.map_grid.iter().any(|(_, cell)| cell.is_some_and(|c| c.biome.is_some())));
+    let back = rexpaint::import_xp(&rexpaint::export_xp(&map), None).unwrap();
+    assert_eq!(back.mapsize(), map.mapsize());
+    assert_eq!(terrain(&back), terrain(&map));
+    assert_eq!(back.map_metadata.spawn, map.map_metadata.spawn);
+    assert_eq!(back.map_metadata.exit, map.map_metadata.exit);
+    assert_eq!(entities(&back), entities(&map));
+}
+
+#[test]
+fn exported_layers() {
+    let map = maps::text::import("####\n#p.#\n#.>#\n####\n", None).unwrap();
+    let image = XpImage::from_bytes(&rexpaint::export_xp(&map)).unwrap();
+    assert_eq!(image.version, rexpaint::XP_VERSION);
+    assert_eq!(image.layers.len(), 2);
+    let (terrain, markers) = (&image.layers[0], &image.layers[1]);
+    assert_eq!((terrain.height, terrain.width), (4, 4));
+    assert_eq!(terrain.get(Coord::new(0, 0)).glyph, '#' as u32);
+    assert_eq!(terrain.get(Coord::new(1, 1)).glyph, '.' as u32);
+    // Markers are the only opaque cells of the top layer
+    let opaque: Vec<(usize, u32)> = markers.cells.iter().enumerate().filter(|(_, c)| !c.is_transparent()).map(|(i, c)| (i, c.glyph)).collect();
+    assert_eq!(opaque, [(5, 'p' as u32), (10, '>' as u32)]);
+    // Bytes read back the same
+    assert_eq!(XpImage::from_bytes(&image.to_bytes()).unwrap().layers[1].cells, markers.cells);
+}
+
+#[test]
+fn drawn_images_use_the_legend() {
+    let rows = ["XXXXX", "Xo@gX", "XoEXX", "XXXXX"];
+    let mut terrain = XpLayer::new(4, 5);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            terrain.set(Coord::new(y as i32, x as i32), XpCell::new(c, (15, 0)));
+        }
+    }
+    // A box drawing character as wall on the layer above, and a cell left empty
+    let mut above = XpLayer::new(4, 5);
+    above.set(Coord::new(2, 3), XpCell::new('█', (15, 0)));
+    above.set(Coord::new(0, 0), XpCell::new('~', (15, 0)));
+    let bytes = XpImage { version: rexpaint::XP_VERSION, layers: vec![terrain, above] }.to_bytes();
+    let legend = Legend::parse("X=wall,█=wall,o=floor,@=spawn,E=exit,g=monster,~=empty").unwrap();
+    let map = rexpaint::import_xp(&bytes, Some(&legend)).unwrap();
+    assert_eq!(maps::text::export(&map).lines().skip_while(|l| *l != "---").skip(1).collect::<Vec<&str>>(), ["#####", "#.pm#", "#.>##", "#####"]);
+    assert_eq!(map.map_metadata.spawn, Coord::new(1, 2));
+    assert_eq!(map.map_metadata.exit, Some(Coord::new(2, 2)));
+    assert_eq!(entities(&map), [(1, 3, EntityKind::Monster)]);
+    // Without the legend the drawing can't be read
+    assert!(matches!(rexpaint::import_xp(&bytes, None), Err(MapError::Schema(message)) if message.contains("'X' at 0x0")));
+}
+
+#[test]
+fn every_cp437_glyph_has_one_character() {
+    for glyph in 1..256 {
+        let c = rexpaint::glyph_char(glyph).unwrap();
+        assert_eq!(rexpaint::char_glyph(c), glyph, "{} '{}'", glyph, c);
+    }
+    assert_eq!(rexpaint::glyph_char(256), None);
+    assert_eq!(rexpaint::char_glyph('€'), '?' as u32);
+}
+
+#[test]
+fn malformed_images_are_errors() {
+    let image = XpImage { version: rexpaint::XP_VERSION, layers: vec![XpLayer::new(3, 3), XpLayer::new(3, 4)] };
+    assert!(matches!(rexpaint::import_xp(&image.to_bytes(), None), Err(MapError::Schema(message)) if message.contains("different sizes")));
+    let bytes = XpImage { version: rexpaint::XP_VERSION, layers: vec![XpLayer::new(3, 3)] }.to_bytes();
+    let mut data = Vec::new();
+    std::io::Read::read_to_end(&mut flate2_decoder(&bytes), &mut data).unwrap();
+    assert!(matches!(XpImage::from_bytes(&gzip(&data[..data.len() - 5])), Err(MapError::Parse(_))));
+    // More layers than REXPaint draws
+    let layers = vec![XpLayer::new(1, 1); rexpaint::XP_MAX_LAYERS + 1];
+    let bytes = XpImage { version: rexpaint::XP_VERSION, layers }.to_bytes();
+    assert!(matches!(XpImage::from_bytes(&bytes), Err(MapError::Parse(message)) if message.contains("10 layers")));
+    // The unpacked size limit has room for MAX_TILES cells and every layer header
+    assert_eq!(rexpaint::XP_MAX_BYTES, 8 + 8 * 9 + 10 * maps::MAX_TILES as u64);
+}
+
+fn flate2_decoder(bytes: &[u8]) -> impl std::io::Read + '_ {
+    flate2::read::GzDecoder::new(bytes)
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, data).unwrap();
+    encoder.finish().unwrap()
+}
@@ -0,0 +1,149 @@
+// Saves replace map files in one step, only when asked to, and can keep the old map as a backup
+extern crate maps;
+
+use maps::save::{self, SaveOptions};
+use maps::{Codec, Map, MapError, MapFormat};
+use std::path::{Path, PathBuf};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("maps-save-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn existing_files_need_force_or_backup() {
+    let dir = temp_dir("force");
+    let path = dir.join("cave.map");
+    let path = path.to_str().unwrap();
+    let mut first = Map::new(50, 50, '#', '.', 'p');
+    let mut second = Map::new(60, 60, '#', '.', 'p');
+    save::save_map(path, &mut first, &SaveOptions::new()).unwrap();
+    let error = save::save_map(path, &mut second, &SaveOptions::new()).unwrap_err();
+    assert!(matches!(error, MapError::Validation(_)));
+    assert_eq!(std::fs::read(path).unwrap(), first.to_bytes(MapFormat::Json));
+    let options = SaveOptions { force: true, codec: Codec::Zstd, ..SaveOptions::new() };
+    save::save_map(path, &mut second, &options).unwrap();
+    assert_eq!(Map::load_map(path).unwrap().mapsize(), (60, 60));
+    assert_eq!(files(&dir), ["cave.map"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn backup_keeps_the_previous_version() {
+    let dir = temp_dir("backup");
+    let path = dir.join("cave.map");
+    let path = path.to_str().unwrap();
+    let options = SaveOptions { backup: true, ..SaveOptions::new() };
+    let mut maps: Vec<Map> = [50, 60, 70].iter().map(|size| Map::new(*size, *size, '#', '.', 'p')).collect();
+    // Nothing to back up the first time
+    save::save_map(path, &mut maps[0], &options).unwrap();
+    assert_eq!(files(&dir), ["cave.map"]);
+    for map in maps.iter_mut().skip(1) {
+        let old = std::fs::read(path).unwrap();
+        save::save_map(path, map, &options).unwrap();
+        assert_eq!(std::fs::read(save::backup_path(path)).unwrap(), old);
+        assert_eq!(std::fs::read(path).unwrap(), map.to_bytes(MapFormat::Json));
+    }
+    assert_eq!(files(&dir), ["cave.map", "cave.map.bak"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_writes_leave_the_old_file() {
+    let dir = temp_dir("failed");
+    let path = dir.join("cave.map");
+    std::fs::write(&path, b"old map").unwrap();
+    let result = save::write_file(&path, true, |file| {
+        std::io::Write::write_all(file, b"half a ma")?;
+        Err(MapError::Validation("stopped".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"old map");
+    // No temporary file or backup is left behind
+    assert_eq!(files(&dir), ["cave.map"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_map_with_replaces_files() {
+    let dir = temp_dir("replace");
+    let path = dir.join("cave.map.gz");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, b"old map").unwrap();
+    let map = Map::new(50, 50, '#', '.', 'p');
+    // Only with force or backup, save_map always replaces like --force
+    let options = SaveOptions { codec: Codec::Gzip, ..SaveOptions::new() };
+    assert!(matches!(Map::save_map_with(path, &map, &options), Err(MapError::Validation(_))));
+    assert_eq!(std::fs::read(path).unwrap(), b"old map");
+    Map::save_map_with(path, &map, &SaveOptions { backup: true, ..options }).unwrap();
+    assert_eq!(std::fs::read(save::backup_path(path)).unwrap(), b"old map");
+    Map::save_map(path, &map, true).unwrap();
+    assert_eq!(Map::read_map_file(path, true).unwrap(), map.to_bytes(MapFormat::Json));
+    assert_eq!(files(&dir), ["cave.map.gz", "cave.map.gz.bak"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn export_and_render_force_and_backup() {
+    let dir = temp_dir("export");
+    Map::save_map(dir.join("cave.map").to_str().unwrap(), &Map::new(50, 50, '#', '.', 'p'), false).unwrap();
+    let run = |args: &[&str]| std::process::Command::new(env!("CARGO_BIN_EXE_maps")).arg("--dir").arg(&dir).args(args).output().unwrap();
+    let out = dir.join("cave.out");
+    let out = out.to_str().unwrap();
+    for command in [["export", "--to", "txt"], ["render", "--format", "svg"]].iter() {
+        let _ = std::fs::remove_file(out);
+        let _ = std::fs::remove_file(save::backup_path(out));
+        let args = [command[0], "--file", "cave.map", command[1], command[2], "--out", out];
+        assert!(run(&args).status.success(), "{}", command[0]);
+        let first = std::fs::read(out).unwrap();
+        std::fs::write(out, b"edited").unwrap();
+        let refused = run(&args);
+        assert_eq!(refused.status.code(), Some(6), "{}", command[0]);
+        assert!(String::from_utf8_lossy(&refused.stderr).contains("already exists"));
+        assert_eq!(std::fs::read(out).unwrap(), b"edited");
+        assert!(run(&[&args[..], &["--backup"]].concat()).status.success());
+        assert_eq!(std::fs::read(save::backup_path(out)).unwrap(), b"edited");
+        assert_eq!(std::fs::read(out).unwrap(), first);
+        assert!(run(&[&args[..], &["--force"]].concat()).status.success());
+    }
+    // The tiled tileset image is written the same way
+    let tmj = dir.join("cave.tmj");
+    let tmj = tmj.to_str().unwrap();
+    let export = |extra: &[&str]| run(&[&["export", "--file", "cave.map", "--to", "tmj", "--out", tmj][..], extra].concat());
+    assert!(export(&[]).status.success());
+    std::fs::remove_file(tmj).unwrap();
+    // Refused before anything is written
+    let refused = export(&[]);
+    assert_eq!(refused.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("cave.tileset.png already exists"));
+    assert!(!std::path::Path::new(tmj).exists());
+    assert!(export(&["--force"]).status.success());
+    assert_eq!(files(&dir), ["cave.map", "cave.out", "cave.out.bak", "cave.tileset.png", "cave.tmj"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn create_force_and_backup() {
+    let dir = temp_dir("create");
+    let create = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_maps")).arg("--dir").arg(&dir)
+            .args(["create", "--file", "cave.map", "--size", "50"]).args(extra).output().unwrap()
+    };
+    assert!(create(&[]).status.success());
+    let refused = create(&[]);
+    assert_eq!(refused.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("already exists, --force replaces it"));
+    let old = std::fs::read(dir.join("cave.map")).unwrap();
+    assert!(create(&["--backup"]).status.success());
+    assert_eq!(std::fs::read(dir.join("cave.map.bak")).unwrap(), old);
+    assert!(create(&["--force"]).status.success());
+    assert_eq!(files(&dir), ["cave.map", "cave.map.bak"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+// `--file -` writes maps to stdout and reads them from stdin
+extern crate maps;
+
+use maps::{Codec, Map, MapFormat};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn maps_command(dir: &std::path::Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_maps"));
+    command.arg("--dir").arg(dir);
+    command
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("maps-stdio-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn create_writes_to_stdout() {
+    let dir = temp_dir("create");
+    for (flag, codec) in [(None, Codec::None), (Some("--compress"), Codec::Gzip), (Some("--compress=zstd"), Codec::Zstd), (Some("--compress=xz"), Codec::Xz)].iter() {
+        let mut command = maps_command(&dir);
+        command.args(["create", "--file", "-", "--size", "50"]);
+        if let Some(flag) = flag {
+            command.arg(flag);
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(Codec::detect(&output.stdout), *codec);
+        let map = maps::stream::read_map(&output.stdout[..], None, &mut |_| {}).unwrap();
+        assert_eq!(map.mapsize(), (50, 50));
+    }
+    // Nothing goes in the map directory
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn write_map_matches_saved_files() {
+    let map = Map::new(50, 50, '#', '.', 'p');
+    let mut out = Vec::new();
+    Map::write_map(&mut out, &map, Codec::None, MapFormat::Legacy).unwrap();
+    assert_eq!(out, map.to_bytes(MapFormat::Legacy));
+}
+
+#[test]
+fn view_reports_a_bad_map_on_stdin() {
+    let dir = temp_dir("view");
+    let mut child = maps_command(&dir).args(["view", "--file", "-"]).stdin(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"not a map").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: could not parse map file"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,152 @@
+// The streaming loader gives the same maps as parsing the whole file
+extern crate maps;
+extern crate serde_json;
+extern crate flate2;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use maps::stream::{self, Progress};
+use maps::{Biome, Cell, Coord, Map, MapError, MapFormat, Palette};
+use std::io::Write;
+
+fn as_json(map: &Map) -> serde_json::Value {
+    serde_json::from_str(&map.to_json(MapFormat::Json)).unwrap()
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn read(bytes: &[u8]) -> Result<Map, MapError> {
+    stream::read_map(bytes, Some(bytes.len() as u64), &mut |_| {})
+}
+
+#[test]
+fn every_format_streams_like_from_bytes() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    for format in [MapFormat::Json, MapFormat::Legacy, MapFormat::Binary].iter() {
+        let bytes = map.to_bytes(*format);
+        let expected = as_json(&Map::from_bytes(&bytes).unwrap());
+        assert_eq!(as_json(&read(&bytes).unwrap()), expected, "{:?}", format);
+        assert_eq!(as_json(&read(&gzip(&bytes)).unwrap()), expected, "{:?} gzip", format);
+    }
+}
+
+#[test]
+fn python_key_order_streams_like_from_bytes() {
+    // Python writes keys in hash order, so terrain comes before mapsize
+    for path in ["tests/fixtures/python/sketch.map", "tests/fixtures/python/voronoi.map.gz"].iter() {
+        let expected = Map::from_bytes(&Map::read_map_file(path, path.ends_with(".gz")).unwrap()).unwrap();
+        let map = stream::load_map(path, &mut |_| {}).unwrap();
+        assert_eq!(as_json(&map), as_json(&expected), "{}", path);
+        assert_eq!(map.to_json(MapFormat::Legacy), expected.to_json(MapFormat::Legacy), "{}", path);
+    }
+}
+
+#[test]
+fn version_2_keys_in_any_order() {
+    let map = Map::new(60, 60, '#', '.', 'p');
+    let expected = as_json(&map);
+    assert!(expected["biomes"].is_array() && expected["tile_regions"].is_array() && expected["entities"].is_array());
+    // serde_json sorts object keys like jq -S, so biomes and entities come before metadata
+    let sorted = expected.to_string();
+    assert!(sorted.find("\"biomes\"").unwrap() < sorted.find("\"metadata\"").unwrap());
+    let mut orders = vec![sorted];
+    let fields = ["terrain", "tile_regions", "biomes", "entities", "objects", "metadata"];
+    let fields: Vec<String> = fields.iter().filter(|f| expected.get(**f).is_some())
+        .map(|f| format!("{:?}: {}", f, expected[*f])).collect();
+    orders.push(format!("{{{}}}", fields.join(", ")));
+    for text in orders.iter() {
+        assert_eq!(as_json(&Map::from_json(text).unwrap()), expected);
+        assert_eq!(as_json(&read(text.as_bytes()).unwrap()), expected, "{}", &text[..80]);
+    }
+}
+
+#[test]
+fn cells_stay_small() {
+    // A 5000x5000 grid is 400 MB at 16 bytes a tile
+    assert!(std::mem::size_of::<Option<Cell>>() <= 16);
+}
+
+#[test]
+fn large_map_with_metadata_last() {
+    // Terrain read before the metadata is kept as cells, not json, and becomes the grid as it is
+    let (height, width) = (2000, 1500);
+    let mut map = Map::empty(height, width, Palette::default());
+    for (i, cell) in map.map_grid.values_mut().enumerate() {
+        let mut tile = Cell::new(if i % 7 == 0 { '#' } else { '.' });
+        tile.biome = Some(if i % 3 == 0 { Biome::Desert } else { Biome::Forest });
+        tile.region = Some((i % 5) as u32);
+        *cell = Some(tile);
+    }
+    let expected = as_json(&map);
+    let fields = ["terrain", "tile_regions", "biomes", "entities", "objects", "metadata"];
+    let text: Vec<String> = fields.iter().filter(|f| expected.get(**f).is_some())
+        .map(|f| format!("{:?}: {}", f, expected[*f])).collect();
+    let loaded = read(format!("{{{}}}", text.join(", ")).as_bytes()).unwrap();
+    assert_eq!(loaded.mapsize(), (height, width));
+    for (coord, cell) in map.map_grid.iter().step_by(997) {
+        assert_eq!(loaded.cell(coord), cell.as_ref(), "{:?}", coord);
+    }
+    assert_eq!(loaded.cell(Coord::new(height - 1, width - 1)), map.cell(Coord::new(height - 1, width - 1)));
+}
+
+#[test]
+fn short_terrain_rows_before_the_metadata_fit_the_map() {
+    let metadata = as_json(&Map::empty(3, 2, Palette::default()))["metadata"].to_string();
+    let text = format!(r##"{{"terrain": ["#.#", "."], "biomes": ["fdt"], "metadata": {}}}"##, metadata);
+    let map = read(text.as_bytes()).unwrap();
+    assert_eq!(as_json(&map), as_json(&Map::from_json(&text).unwrap()));
+    assert_eq!(map.mapsize(), (3, 2));
+    assert_eq!(map.cell(Coord::new(0, 1)).map(|c| (c.c, c.biome)), Some(('.', Some(Biome::Desert))));
+    assert_eq!(map.cell(Coord::new(1, 0)).map(|c| c.c), Some('.'));
+    assert!(map.cell(Coord::new(1, 1)).is_none() && map.cell(Coord::new(2, 0)).is_none());
+}
+
+#[test]
+fn legacy_keys_outside_the_map_are_kept() {
+    let text = r##"{"9x9": {"y": 9, "x": 9, "c": ".", "neighbors": []}, "1x1": {"y": 1, "x": 1, "c": ".", "neighbors": []},
+        "mapsize": {"y": 4, "x": 4, "c": "$", "neighbors": []}, "8x0": {"y": 8, "x": 0, "c": "#", "neighbors": []}}"##;
+    let map = read(text.as_bytes()).unwrap();
+    assert_eq!(as_json(&map), as_json(&Map::from_json(text).unwrap()));
+    assert_eq!(map.mapsize(), (4, 4));
+    assert!(map.map_objects.contains_key("9x9") && map.map_objects.contains_key("8x0"));
+}
+
+#[test]
+fn legacy_without_mapsize_fits_the_terrain() {
+    let text = r##"{"2x5": {"y": 2, "x": 5, "c": ".", "neighbors": []}, "0x0": {"y": 0, "x": 0, "c": "#", "neighbors": []}}"##;
+    assert_eq!(read(text.as_bytes()).unwrap().mapsize(), (3, 6));
+}
+
+#[test]
+fn progress_is_reported_to_the_end() {
+    let map = Map::new(300, 300, '#', '.', 'p');
+    for format in [MapFormat::Json, MapFormat::Legacy].iter() {
+        let bytes = gzip(&map.to_bytes(*format));
+        let mut reports: Vec<Progress> = Vec::new();
+        stream::read_map(&bytes[..], Some(bytes.len() as u64), &mut |p| reports.push(p)).unwrap();
+        assert!(reports.len() > 1, "{:?}", format);
+        assert!(reports.windows(2).all(|w| w[0].bytes <= w[1].bytes && w[0].tiles <= w[1].tiles), "{:?}", format);
+        let last = reports.last().unwrap();
+        assert!(last.done && reports.iter().filter(|p| p.done).count() == 1, "{:?}", format);
+        assert_eq!(last.bytes, bytes.len() as u64, "{:?}", format);
+        assert_eq!(last.fraction(), Some(1.0), "{:?}", format);
+        assert_eq!(last.tiles, 300 * 300, "{:?}", format);
+    }
+}
+
+#[test]
+fn broken_files_are_errors() {
+    let bytes = gzip(&Map::new(60, 60, '#', '.', 'p').to_bytes(MapFormat::Legacy));
+    assert!(matches!(read(&bytes[..bytes.len() / 2]), Err(MapError::Decompress(_))));
+    assert!(matches!(read(b"{\"mapsize\": "), Err(MapError::Parse(_))));
+    assert!(matches!(read(b"[1, 2]"), Err(MapError::Schema(_))));
+    assert!(matches!(read(br##"{"terrain": ["#."], "biomes": ["ff"]}"##), Err(MapError::Schema(_))));
+    match read(br#"{"metadata": {"version": 99}}"#) {
+        Err(MapError::Schema(message)) => assert!(message.contains("version 99 is newer"), "{}", message),
+        other => panic!("expected a schema error, got {:?}", other.map(|m| m.mapsize()))
+    }
+}
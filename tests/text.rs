@@ -0,0 +1,108 @@
+// Maps exported to text and imported back, and sketches drawn with a legend
+extern crate maps;
+
+use maps::text::{self, Legend, TileRole};
+use maps::{Cell, Coord, EntityKind, Map, MapError};
+
+fn terrain(map: &Map) -> Vec<Option<char>> {
+    map.map_grid.iter().map(|(_, cell)| cell.map(|c| c.c)).collect()
+}
+
+fn entities(map: &Map) -> Vec<(i32, i32, EntityKind)> {
+    let mut found: Vec<(i32, i32, EntityKind)> = map.map_entities.iter().map(|e| (e.y, e.x, e.entity.kind)).collect();
+    found.sort_by_key(|(y, x, _)| (*y, *x));
+    found
+}
+
+// Rows after the header
+fn rows(exported: &str) -> Vec<&str> {
+    exported.lines().skip_while(|l| *l != text::HEADER_END).skip(1).collect()
+}
+
+#[test]
+fn exported_maps_import_again() {
+    let mut map = Map::new(50, 60, '#', '.', 'p');
+    // A wall seed can land on the spawn, and the spawn marker always reads back as floor
+    map.map_grid.set(map.map_metadata.spawn, Some(Cell::new('.')));
+    let exit = map.map_grid.iter().map(|(coord, _)| coord)
+        .filter(|coord| map.is_floor(*coord) && *coord != map.map_metadata.spawn && !map.map_entities.iter().any(|e| Coord::new(e.y, e.x) == *coord))
+        .nth(100).unwrap();
+    map.map_metadata.exit = Some(exit);
+    assert!(!map.map_entities.is_empty());
+    let exported = text::export(&map);
+    let back = text::import(&exported, None).unwrap();
+    assert_eq!(back.mapsize(), map.mapsize());
+    assert_eq!(terrain(&back), terrain(&map));
+    assert_eq!(back.map_metadata.spawn, map.map_metadata.spawn);
+    assert_eq!(back.map_metadata.exit, Some(exit));
+    assert_eq!(entities(&back), entities(&map));
+    // And exports to the same text
+    assert_eq!(text::export(&back), exported);
+}
+
+#[test]
+fn header() {
+    let map = text::import("#####\n#p.>#\n#####\n", None).unwrap();
+    let exported = text::export(&map);
+    let header: Vec<&str> = exported.lines().take_while(|l| *l != text::HEADER_END).collect();
+    assert_eq!(header, [
+        text::HEADER,
+        "size: 3x5",
+        "spawn: 1x1",
+        "exit: 1x3",
+        "legend: #=wall,.=floor,p=spawn,>=exit,m=monster,c=chest,n=npc"
+    ]);
+    assert_eq!(rows(&exported), ["#####", "#p.>#", "#####"]);
+}
+
+#[test]
+fn sketches_use_a_custom_legend() {
+    let legend = Legend::parse("X=wall, o=floor,@=spawn,E=exit,g=monster,$=chest,,=wall,_=empty").unwrap();
+    assert_eq!(legend.role(','), Some(TileRole::Wall));
+    assert_eq!(legend.role(' '), Some(TileRole::Empty));
+    let sketch = "XXXXXX\nXo@g$X\nXoEX,X\nXXX_ X\n";
+    let map = text::import(sketch, Some(&legend)).unwrap();
+    assert_eq!(map.mapsize(), (4, 6));
+    assert_eq!(map.map_metadata.spawn, Coord::new(1, 2));
+    assert_eq!(map.map_metadata.exit, Some(Coord::new(2, 2)));
+    assert_eq!(entities(&map), [(1, 3, EntityKind::Monster), (1, 4, EntityKind::Chest)]);
+    assert!(map.cell(Coord::new(3, 3)).is_none() && map.cell(Coord::new(3, 4)).is_none());
+    // Exported with the map's own characters, which read back without the legend
+    let exported = text::export(&map);
+    assert_eq!(rows(&exported), ["######", "#.pmc#", "#.>###", "###  #"]);
+    let back = text::import(&exported, None).unwrap();
+    assert_eq!(terrain(&back), terrain(&map));
+    assert_eq!(entities(&back), entities(&map));
+    assert_eq!(back.map_metadata.exit, map.map_metadata.exit);
+    // A legend given on import wins over the one in the header
+    let swapped = Legend::parse("#=floor,.=wall,p=spawn,>=exit,m=monster,c=chest").unwrap();
+    let back = text::import(&exported, Some(&swapped)).unwrap();
+    assert_eq!(back.cell(Coord::new(0, 0)).map(|c| c.c), Some('.'));
+    assert_eq!(back.cell(Coord::new(1, 1)).map(|c| c.c), Some('#'));
+}
+
+#[test]
+fn legend_spec_round_trips() {
+    let legend = Legend::parse("c=npc,X=wall,o=floor,@=spawn").unwrap();
+    assert_eq!(legend.to_spec(), "X=wall,o=floor,@=spawn,c=npc");
+    assert_eq!(Legend::parse(&legend.to_spec()).unwrap().to_spec(), legend.to_spec());
+    assert!(Legend::parse("X=lava").unwrap_err().contains("unknown legend role 'lava'"));
+    assert!(Legend::parse("Xwall").unwrap_err().contains("must look like X=wall"));
+}
+
+#[test]
+fn header_size_and_markers() {
+    // Short rows leave the rest of the size without tiles, the header spawn is used when none is drawn
+    let map = text::import("maps txt 1\nsize: 3x4\nspawn: 1x2\nexit: 1x1\n---\n####\n#..\n", None).unwrap();
+    assert_eq!(map.mapsize(), (3, 4));
+    assert_eq!(map.map_metadata.spawn, Coord::new(1, 2));
+    assert_eq!(map.map_metadata.exit, Some(Coord::new(1, 1)));
+    assert!(map.cell(Coord::new(1, 3)).is_none() && map.cell(Coord::new(2, 0)).is_none());
+    let schema = |text: &str| matches!(text::import(text, None), Err(MapError::Schema(_)));
+    let parse = |text: &str| matches!(text::import(text, None), Err(MapError::Parse(_)));
+    assert!(schema("maps txt 1\nsize: 1x4\n---\n####\n####\n"));
+    assert!(schema("maps txt 1\nsize: 2x2\n---\n###\n"));
+    assert!(parse("maps txt 1\nsize: 2x2\n####\n"));
+    assert!(parse("maps txt 1\nsize: two\n---\n"));
+    assert!(parse("maps txt 1\nsize: 2x2\nspawn: here\n---\n##\n##\n"));
+}
@@ -0,0 +1,114 @@
+// Maps exported to Tiled json and xml and imported back, and Tiled files maps can't use
+extern crate maps;
+extern crate serde_json;
+
+use maps::tiled::{self, TiledMap};
+use maps::{Coord, Entity, EntityKind, Map, MapError};
+use serde_json::{json, Value};
+
+fn map_with_everything() -> Map {
+    let mut map = Map::new(60, 60, '#', '.', 'p');
+    let floor = map.map_grid.iter().filter(|(_, cell)| cell.is_some_and(|c| c.c == '.')).map(|(coord, _)| coord).nth(100).unwrap();
+    let mut chest = Entity::new(EntityKind::Chest);
+    chest.properties.insert(String::from("gold"), json!(25));
+    map.add_entity(floor, chest);
+    map.map_metadata.exit = Some(Coord::new(40, 41));
+    map
+}
+
+// Everything a Tiled file keeps, regions are labeled again on import so they're left out
+fn assert_same_map(back: &Map, map: &Map, name: &str) {
+    assert_eq!(back.mapsize(), map.mapsize(), "{}", name);
+    for (coord, cell) in map.map_grid.iter() {
+        let other = back.cell(coord);
+        assert_eq!(other.map(|c| (c.c, c.biome)), cell.map(|c| (c.c, c.biome)), "{} {}", name, coord.key());
+    }
+    assert_eq!(back.map_metadata.palette, map.map_metadata.palette, "{}", name);
+    assert_eq!(back.map_metadata.spawn, map.map_metadata.spawn, "{}", name);
+    assert_eq!(back.map_metadata.exit, map.map_metadata.exit, "{}", name);
+    assert_eq!(serde_json::to_value(&back.map_metadata.region_seeds).unwrap(), serde_json::to_value(&map.map_metadata.region_seeds).unwrap(), "{}", name);
+    assert_eq!(serde_json::to_value(&back.map_entities).unwrap(), serde_json::to_value(&map.map_entities).unwrap(), "{}", name);
+}
+
+#[test]
+fn tmj_round_trips() {
+    let map = map_with_everything();
+    assert!(map.map_entities.len() > 1 && map.map_grid.iter().any(|(_, cell)| cell.is_some_and(|c| c.biome.is_some())));
+    let back = tiled::import_tmj(&tiled::export_tmj(&map, "tileset.png")).unwrap();
+    assert_same_map(&back, &map, "tmj");
+}
+
+#[test]
+fn tmx_round_trips() {
+    let map = map_with_everything();
+    let back = tiled::import_tmx(&tiled::export_tmx(&map, "tileset.png")).unwrap();
+    assert_same_map(&back, &map, "tmx");
+}
+
+#[test]
+fn tmj_layers_match_the_map() {
+    let map = map_with_everything();
+    let value: Value = serde_json::from_str(&tiled::export_tmj(&map, "tileset.png")).unwrap();
+    assert_eq!(value["height"], 60);
+    let terrain = &value["layers"][0];
+    assert_eq!(terrain["name"], "terrain");
+    assert_eq!(terrain["data"].as_array().unwrap().len(), 60 * 60);
+    // Wall and floor are always the first two tiles
+    assert_eq!(terrain["data"][0], 1);
+    let objects = value["layers"].as_array().unwrap().iter().find(|l| l["type"] == "objectgroup").unwrap();
+    let spawn = objects["objects"].as_array().unwrap().iter().find(|o| o["type"] == "spawn").unwrap();
+    assert_eq!((spawn["y"].as_i64().unwrap(), spawn["x"].as_i64().unwrap()), (12 * 16, 35 * 16));
+}
+
+// A small tiled json map, changed by each malformed case
+fn small_tmj() -> Value {
+    let mut map = maps::text::import("###\n#p#\n###\n", None).unwrap();
+    map.map_metadata.region_seeds.clear();
+    serde_json::from_str(&TiledMap::from_map(&map).to_tmj("tileset.png")).unwrap()
+}
+
+fn schema_error(value: &Value) -> String {
+    match tiled::import_tmj(&value.to_string()) {
+        Err(MapError::Schema(message)) => message,
+        other => panic!("expected a schema error, got {:?}", other.map(|m| m.mapsize()))
+    }
+}
+
+#[test]
+fn malformed_files_are_errors() {
+    assert!(tiled::import_tmj(&small_tmj().to_string()).is_ok());
+    assert!(matches!(tiled::import_tmj("{\"height\": "), Err(MapError::Parse(_))));
+    assert!(matches!(tiled::import_tmx("<map><layer></map>"), Err(MapError::Parse(_))));
+
+    let mut value = small_tmj();
+    value.as_object_mut().unwrap().remove("width");
+    assert!(schema_error(&value).contains("missing \"width\""));
+    let mut value = small_tmj();
+    value["tilesets"][0]["source"] = json!("tiles.tsj");
+    assert!(schema_error(&value).contains("external tilesets"));
+    let mut value = small_tmj();
+    value["layers"][0]["encoding"] = json!("base64");
+    assert!(schema_error(&value).contains("csv"));
+    let mut value = small_tmj();
+    value["layers"][0]["data"] = json!([1, 1, 1]);
+    assert!(schema_error(&value).contains("tile layer has 3 tiles"));
+    let mut value = small_tmj();
+    value["layers"][0]["data"][4] = json!(99);
+    assert!(schema_error(&value).contains("terrain tile 99"));
+    let mut value = small_tmj();
+    value["layers"].as_array_mut().unwrap().retain(|l| l["type"] != "tilelayer");
+    assert!(schema_error(&value).contains("no terrain tile layer"));
+}
+
+#[test]
+fn huge_sizes_are_errors() {
+    for (height, width) in [(100000, 100000), (65536, 65536), (i32::MAX as i64, 2)].iter() {
+        let mut value = small_tmj();
+        value["height"] = json!(height);
+        value["width"] = json!(width);
+        assert!(schema_error(&value).contains("tiles"), "{}x{}", height, width);
+    }
+    let tmx = tiled::export_tmx(&maps::text::import("###\n#p#\n###\n", None).unwrap(), "tileset.png")
+        .replacen("width=\"3\" height=\"3\"", "width=\"100000\" height=\"100000\"", 1);
+    assert!(matches!(tiled::import_tmx(&tmx), Err(MapError::Schema(_))));
+}
@@ -0,0 +1,91 @@
+// Map file names, paths and the map directory
+extern crate maps;
+
+use maps::Validation;
+use std::path::Path;
+
+#[test]
+fn allowed_file_names() {
+    for name in ["cave", "cave.map", "cave.map.gz", "cave.map.zst", "cave-2_final.map", "höhle.map", "洞窟.map", "v1.2.map"].iter() {
+        assert_eq!(Validation::check_filename(name), Ok(()), "{}", name);
+    }
+}
+
+#[test]
+fn rejected_file_names_say_why() {
+    let cases = [
+        ("", "'' is not a file name"),
+        ("maps/..", "'maps/..' is not a file name"),
+        (".hidden", "file name '.hidden' can't start with '.'"),
+        ("-map", "file name '-map' can't start with '-'"),
+        ("my map", "file name 'my map' can't have ' ' in it, use letters, numbers, '_', '-' and '.'"),
+        ("cave?.map", "file name 'cave?.map' can't have '?' in it, use letters, numbers, '_', '-' and '.'")
+    ];
+    for (name, message) in cases.iter() {
+        assert_eq!(Validation::check_filename(name), Err(message.to_string()), "{}", name);
+        assert!(!Validation::validate_filename(name), "{}", name);
+    }
+    let long = "a".repeat(maps::MAX_NAME_LEN + 1);
+    assert!(Validation::check_filename(&long).unwrap_err().contains("longer than 255 bytes"));
+    // The old pattern's unescaped '.' let any character in before the extension
+    assert!(Validation::check_filename("cave;map").is_err());
+    assert!(Validation::check_filename("cave/map.gz").is_ok());
+}
+
+#[test]
+fn bare_names_are_in_the_map_directory() {
+    let validation = Validation::in_dir("/srv/maps", "cave.map");
+    assert_eq!(Path::new(&validation.file_path), Path::new("/srv/maps/cave.map"));
+    assert!(!Validation::is_path("cave.map"));
+}
+
+#[test]
+fn paths_are_used_as_they_are() {
+    for path in ["levels/cave.map", "./cave.map", "/tmp/cave.map", "../cave.map"].iter() {
+        assert!(Validation::is_path(path), "{}", path);
+        assert_eq!(Validation::in_dir("/srv/maps", path).file_path, path.to_string());
+    }
+    // Only the last part of a path has to follow the file name policy
+    assert!(Validation::in_dir("/srv/maps", "my levels/cave.map").file_valid);
+}
+
+#[test]
+fn map_directory_order() {
+    assert_eq!(Validation::map_dir_from(Some("/from/flag"), None), "/from/flag");
+    assert_eq!(Validation::map_dir_from(Some("/from/flag"), Some("/from/env")), "/from/flag");
+    assert_eq!(Validation::map_dir_from(None, Some("/from/env")), "/from/env");
+    assert_eq!(Validation::map_dir_from(None, Some("")), maps::DEFAULT_DIR);
+    assert_eq!(Validation::map_dir_from(None, None), maps::DEFAULT_DIR);
+    assert_eq!(Validation::map_dir(Some("/from/flag")), "/from/flag");
+}
+
+// The binary gets MAPS_DIR from its own environment, so the test process never changes its variables
+#[test]
+fn map_directory_from_the_environment() {
+    let dir = std::env::temp_dir().join(format!("maps-validation-env-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("cave.map"), maps::Map::new(50, 50, '#', '.', 'p').to_bytes(maps::MapFormat::Json)).unwrap();
+    let check = |env: &std::path::Path| std::process::Command::new(env!("CARGO_BIN_EXE_maps"))
+        .env(maps::DIR_VARIABLE, env).args(["check", "--file", "cave.map"]).output().unwrap();
+    assert!(check(&dir).status.success());
+    assert_eq!(check(&dir.join("nope")).status.code(), Some(6));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checks_give_specific_errors() {
+    let dir = std::env::temp_dir().join(format!("maps-validation-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+    let message = |result: Result<(), maps::MapError>| result.unwrap_err().to_string();
+    let missing = Validation::in_dir(dir, "missing.map");
+    assert!(message(missing.check_existing()).ends_with("missing.map' does not exist"));
+    assert!(missing.check_new().is_ok());
+    let no_dir = Validation::in_dir(&format!("{}/nope", dir), "cave.map");
+    assert!(message(no_dir.check_new()).starts_with("directory '") && message(no_dir.check_new()).ends_with("nope' does not exist"));
+    std::fs::create_dir_all(format!("{}/folder", dir)).unwrap();
+    assert!(message(Validation::in_dir(dir, "folder").check_existing()).ends_with("folder' is a directory"));
+    assert!(message(Validation::in_dir(dir, "folder").check_new()).contains("already exists"));
+    assert_eq!(message(Validation::in_dir(dir, "a b").check_new()), "file name 'a b' can't have ' ' in it, use letters, numbers, '_', '-' and '.'");
+    std::fs::remove_dir_all(dir).unwrap();
+}